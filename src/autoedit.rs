@@ -0,0 +1,156 @@
+use crate::textbuffer::*;
+use crate::brackets::*;
+
+// What typing does at one cursor: the text that replaces its selection and where the cursor goes
+// in it. head and tail can point past the new text to step over the chars after it.
+#[derive(Clone, Debug)]
+pub struct TypedEdit{
+    pub start:usize,
+    pub end:usize,
+    pub text:String,
+    pub head:usize,
+    pub tail:usize
+}
+
+impl TypedEdit{
+    fn new(start:usize, end:usize, text:String)->TypedEdit{
+        let len = text.chars().count();
+        TypedEdit{start:start, end:end, text:text, head:len, tail:len}
+    }
+}
+
+fn is_space(ch:char)->bool{
+    ch == ' ' || ch == '\t'
+}
+
+impl CursorSet{
+    // newlines keep the indent of their line, and indent one more level after an open bracket.
+    // between a bracket pair the close bracket goes on a line of its own
+    pub fn insert_newline(&mut self, indent_size:usize, text_buffer:&mut TextBuffer){
+        let edits = self.set.iter().map(|cursor|{
+            let (start, end) = cursor.order();
+            let (row, col) = text_buffer.offset_to_row_col(start);
+            let line = text_buffer.get_line(row);
+            let indent:String = line[0..col].iter().take_while(|ch| is_space(**ch)).collect();
+            let mut text = format!("\n{}", indent);
+            let before = line[0..col].iter().rev().find(|ch| !is_space(**ch)).cloned();
+            if let Some(open) = before.filter(|ch| *ch == '(' || *ch == '[' || *ch == '{'){
+                text.push_str(&" ".repeat(indent_size));
+                if text_buffer.rope.char_at(end) == Some(BracketIndex::close_for(open)){
+                    let head = text.chars().count();
+                    return TypedEdit{start:start, end:end, text:format!("{}\n{}", text, indent), head:head, tail:head}
+                }
+            }
+            TypedEdit::new(start, end, text)
+        }).collect();
+        self.apply_typed_edits(edits, TextUndoGrouping::Newline, text_buffer);
+    }
+
+    // types a char at every cursor. pairs are the brackets and quotes that close themselves:
+    // an open one gets its close one after it or around the selection, typing a close one that
+    // is already there steps over it, and a close bracket on an empty line takes the indent of
+    // the line of its open bracket
+    pub fn type_char(&mut self, ch:char, pairs:&[(char, char)], indent_size:usize, text_buffer:&mut TextBuffer){
+        // only the first cursor on a row can rewrite its indent, the edits of the others would overlap it
+        let mut last_row = None;
+        let edits = self.set.iter().map(|cursor|{
+            let row = text_buffer.offset_to_row_col(cursor.order().0).0;
+            let can_dedent = last_row != Some(row);
+            last_row = Some(row);
+            Self::typed_edit(cursor, ch, pairs, indent_size, can_dedent, text_buffer)
+        }).collect();
+        let grouping = if ch == ' '{TextUndoGrouping::Space}else{TextUndoGrouping::Character};
+        self.apply_typed_edits(edits, grouping, text_buffer);
+    }
+
+    fn typed_edit(cursor:&Cursor, ch:char, pairs:&[(char, char)], indent_size:usize, can_dedent:bool, text_buffer:&TextBuffer)->TypedEdit{
+        let (start, end) = cursor.order();
+        let next = text_buffer.rope.char_at(end);
+        let is_close = pairs.iter().any(|(_, close)| *close == ch);
+        if !cursor.has_selection() && is_close && next == Some(ch){ // step over
+            return TypedEdit{start:start, end:start, text:String::new(), head:1, tail:1}
+        }
+        if let Some((_, close)) = pairs.iter().find(|(open, _)| *open == ch).cloned(){
+            if cursor.has_selection(){ // put the pair around the selection
+                let mut text = ch.to_string();
+                text.extend(text_buffer.rope.chars_at(start).take(end - start));
+                text.push(close);
+                return TypedEdit{start:start, end:end, text:text, head:1 + end - start, tail:1}
+            }
+            let prev = if start > 0{text_buffer.rope.char_at(start - 1)}else{None};
+            let next_allows = match next{
+                None=>true,
+                Some(next)=>next.is_whitespace() || pairs.iter().any(|(open, close)| *close == next && *open != next) || next == ',' || next == ';'
+            };
+            // quotes don't close right after a word, that is more likely the end of a string
+            let prev_allows = close != ch || match prev{
+                None=>true,
+                Some(prev)=>!prev.is_alphanumeric() && prev != '_' && prev != ch
+            };
+            if next_allows && prev_allows{
+                let mut text = ch.to_string();
+                text.push(close);
+                return TypedEdit{start:start, end:end, text:text, head:1, tail:1}
+            }
+        }
+        if !cursor.has_selection() && can_dedent && (ch == ')' || ch == ']' || ch == '}'){
+            let (row, col) = text_buffer.offset_to_row_col(start);
+            if col > 0 && text_buffer.line_chars(row).take(col).all(is_space){
+                let mut text:String = match Self::open_row(start, text_buffer){
+                    Some(open_row)=>text_buffer.line_chars(open_row).take_while(|ch| is_space(*ch)).collect(),
+                    None=>" ".repeat(col.saturating_sub(indent_size))
+                };
+                text.push(ch);
+                return TypedEdit::new(start - col, end, text)
+            }
+        }
+        TypedEdit::new(start, end, ch.to_string())
+    }
+
+    // the row of the open bracket a close bracket typed at offset would close
+    fn open_row(offset:usize, text_buffer:&TextBuffer)->Option<usize>{
+        let bracket_index = &text_buffer.bracket_index;
+        if !bracket_index.is_current(text_buffer){
+            return None
+        }
+        let mut index = match bracket_index.brackets.binary_search_by_key(&offset, |bracket| bracket.offset){
+            Ok(index)=>index,
+            Err(index)=>index
+        };
+        // skip the pairs before us, the first open bracket we don't skip is ours
+        while index > 0{
+            index -= 1;
+            let bracket = &bracket_index.brackets[index];
+            if bracket.is_open{
+                return Some(text_buffer.rope.offset_to_row(bracket.offset))
+            }
+            if let Some(open) = bracket.partner{
+                index = open;
+            }
+        }
+        None
+    }
+
+    // applies the edits of every cursor as one undo step
+    pub fn apply_typed_edits(&mut self, edits:Vec<TypedEdit>, grouping:TextUndoGrouping, text_buffer:&mut TextBuffer){
+        let cursors_clone = self.clone();
+        let mut delta:isize = 0;
+        let mut ops = Vec::new();
+        for (cursor, edit) in self.set.iter_mut().zip(edits){
+            let start = (edit.start as isize + delta) as usize;
+            if edit.end > edit.start || edit.text.len() > 0{
+                let op = text_buffer.replace_with_string(start, edit.end - edit.start, &edit.text);
+                delta += op.len as isize - (edit.end - edit.start) as isize;
+                ops.push(op);
+            }
+            cursor.head = start + edit.head;
+            cursor.tail = start + edit.tail;
+        }
+        for cursor in &mut self.set{
+            cursor.calc_max(text_buffer);
+        }
+        if ops.len() > 0{
+            text_buffer.push_undo(ops, grouping, cursors_clone);
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::textbuffer::*;
+use crate::tokenizer::*;
+
+// Every (), [] and {} in a buffer with its partner. Brackets are found with the tokenizer, so
+// the ones in strings and comments don't count.
+#[derive(Clone, Default)]
+pub struct BracketIndex{
+    // in offset order
+    pub brackets:Vec<Bracket>,
+    // the change_id of the text the index was built for
+    pub built_for:Option<u64>
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Bracket{
+    pub offset:usize,
+    pub ch:char,
+    pub is_open:bool,
+    // None if the bracket doesn't have a matching one
+    pub partner:Option<usize>,
+    // how many pairs are around it
+    pub depth:usize
+}
+
+impl BracketIndex{
+    pub fn build<T:Tokenizer>(text_buffer:&TextBuffer, tokenizer:&mut T)->BracketIndex{
+        let mut brackets:Vec<Bracket> = Vec::new();
+        let mut stack:Vec<usize> = Vec::new();
+        for (range, token_type) in TokenRanges::new(text_buffer, tokenizer){
+            if token_type == TokenType::Paren && range.len() == 1{
+                let offset = range.start;
+                let ch = text_buffer.rope.char_at(offset).unwrap_or('\0');
+                let index = brackets.len();
+                match ch{
+                    '(' | '[' | '{'=>{
+                        brackets.push(Bracket{offset:offset, ch:ch, is_open:true, partner:None, depth:stack.len()});
+                        stack.push(index);
+                    },
+                    _=>{
+                        // a close that doesn't fit the innermost open one is left alone
+                        let mut partner = None;
+                        if let Some(open) = stack.last().cloned(){
+                            if Self::close_for(brackets[open].ch) == ch{
+                                stack.pop();
+                                brackets[open].partner = Some(index);
+                                partner = Some(open);
+                            }
+                        }
+                        brackets.push(Bracket{offset:offset, ch:ch, is_open:false, partner:partner, depth:stack.len()});
+                    }
+                }
+            }
+        }
+        BracketIndex{
+            brackets:brackets,
+            built_for:Some(text_buffer.change_id)
+        }
+    }
+
+    pub fn close_for(ch:char)->char{
+        match ch{
+            '('=>')',
+            '['=>']',
+            '{'=>'}',
+            _=>'\0'
+        }
+    }
+
+    pub fn is_current(&self, text_buffer:&TextBuffer)->bool{
+        self.built_for == Some(text_buffer.change_id)
+    }
+
+    pub fn bracket_at(&self, offset:usize)->Option<usize>{
+        self.brackets.binary_search_by_key(&offset, |bracket| bracket.offset).ok()
+    }
+
+    // the bracket right after the cursor, or else the one right before it
+    pub fn bracket_near(&self, offset:usize)->Option<usize>{
+        if let Some(index) = self.bracket_at(offset){
+            return Some(index)
+        }
+        if offset > 0{
+            return self.bracket_at(offset - 1)
+        }
+        None
+    }
+
+    // the innermost pair with offset between its open bracket and its close one, as indices
+    pub fn enclosing_pair(&self, offset:usize)->Option<(usize, usize)>{
+        let mut index = match self.brackets.binary_search_by_key(&offset, |bracket| bracket.offset){
+            Ok(index)=>index,
+            Err(index)=>index
+        };
+        // walk back over the pairs before us, an open bracket we don't skip is around us
+        while index > 0{
+            index -= 1;
+            let bracket = &self.brackets[index];
+            if bracket.is_open{
+                if let Some(close) = bracket.partner{
+                    if self.brackets[close].offset >= offset{
+                        return Some((index, close))
+                    }
+                }
+            }
+            else if let Some(open) = bracket.partner{
+                index = open;
+            }
+        }
+        None
+    }
+
+    // the offsets of the pair the cursor is at
+    pub fn pair_at_cursor(&self, offset:usize)->Option<(usize, usize)>{
+        let index = self.bracket_near(offset)?;
+        let partner = self.brackets[index].partner?;
+        let (open, close) = (index.min(partner), index.max(partner));
+        Some((self.brackets[open].offset, self.brackets[close].offset))
+    }
+}
+
+impl CursorSet{
+    // moves each cursor to the partner of the bracket it is at, or else to the end of the block it is in
+    pub fn jump_to_bracket(&mut self, only_head:bool, bracket_index:&BracketIndex, text_buffer:&TextBuffer){
+        for cursor in &mut self.set{
+            let target = if let Some(index) = bracket_index.bracket_near(cursor.head){
+                bracket_index.brackets[index].partner
+            }
+            else{
+                None
+            };
+            let target = target.or_else(|| bracket_index.enclosing_pair(cursor.head).map(|(_, close)| close));
+            if let Some(target) = target{
+                cursor.head = bracket_index.brackets[target].offset;
+                if !only_head{cursor.tail = cursor.head}
+                cursor.calc_max(text_buffer);
+            }
+        }
+        self.fuse_adjacent(text_buffer)
+    }
+
+    // selects the inside of the block around each selection, then the block with its brackets, then
+    // the block around that
+    pub fn select_enclosing_block(&mut self, bracket_index:&BracketIndex, text_buffer:&TextBuffer){
+        for cursor in &mut self.set{
+            let (start, end) = cursor.order();
+            let mut pair = bracket_index.enclosing_pair(start);
+            while let Some((open, close)) = pair{
+                let open_offset = bracket_index.brackets[open].offset;
+                let close_offset = bracket_index.brackets[close].offset;
+                if close_offset >= end{
+                    if open_offset + 1 < start || close_offset > end{ // the inside is bigger
+                        cursor.tail = open_offset + 1;
+                        cursor.head = close_offset;
+                        break
+                    }
+                    cursor.tail = open_offset;
+                    cursor.head = close_offset + 1;
+                    break
+                }
+                pair = bracket_index.enclosing_pair(open_offset);
+            }
+            cursor.calc_max(text_buffer);
+        }
+        self.fuse_adjacent(text_buffer)
+    }
+}
@@ -1,5 +1,14 @@
 use widgets::*;
 use crate::textbuffer::*;
+use crate::findbar::*;
+use crate::tokenizer::*;
+use crate::brackets::*;
+use crate::folds::*;
+use crate::softwrap::*;
+use crate::keymap::*;
+use crate::vim::*;
+use crate::gutter::*;
+use crate::minimap::*;
 
 #[derive(Clone)]
 pub struct CodeEditor{
@@ -8,9 +17,34 @@ pub struct CodeEditor{
     pub bg: Quad,
     pub cursor: Quad,
     pub marker: Quad,
+    pub search_marker_color: Color,
+    pub bracket_marker_color: Color,
     pub tab:Quad,
+    pub tab_active_color: Color,
     pub text: Text,
     pub cursors:CursorSet,
+    pub find_bar:FindBar,
+    // modal editing, toggled with a key command
+    pub vim:Option<Vim>,
+    pub gutter:Gutter,
+    pub minimap:Option<Minimap>,
+    pub soft_wrap:SoftWrap,
+    pub wrap_column:usize,
+    pub indent_size:usize,
+    pub col_whitespace:Color,
+    pub col_keyword:Color,
+    pub col_flow_keyword:Color,
+    pub col_identifier:Color,
+    pub col_operator:Color,
+    pub col_function:Color,
+    pub col_number:Color,
+    pub col_paren:Color,
+    pub col_comment:Color,
+    pub col_doc_comment:Color,
+    pub col_string:Color,
+    pub col_delim:Color,
+    pub col_type:Color,
+    pub col_fold:Color,
     pub _hit_state:HitState,
     pub _bg_area:Area,
     pub _text_inst:Option<AlignedInstance>,
@@ -21,11 +55,41 @@ pub struct CodeEditor{
     pub _visible_lines:usize,
     pub _visibility_margin:Margin,
     pub _select_scroll:Option<SelectScroll>,
+    // the word or line a double or triple click selected, dragging extends by that unit
+    pub _select_unit:Option<(SelectUnit, usize, usize)>,
+    // the box of a rectangular selection, until the cursors move
+    pub _grid_select:Option<GridSelect>,
+    pub _scroll_to_cursor:bool,
+    // the line numbers and marks left of the text
+    pub _gutter_width:f32,
+    pub _wrap_layout:WrapLayout,
+    // the breaks of the wrapped line being drawn, and where we are on it
+    pub _row_breaks:Vec<usize>,
+    pub _row_break:usize,
+    pub _row_col:usize,
+    pub _row_indent:usize,
+    pub _row_wraps:usize,
     
     pub _monospace_size:Vec2,
     pub _instance_count:usize,
     pub _first_on_line:bool,
-    pub _draw_cursor:DrawCursor
+    pub _draw_cursor:DrawCursor,
+    pub _draw_search:DrawCursor,
+    // the bracket pair at the cursor as one char selections
+    pub _bracket_cursors:Vec<Cursor>,
+    pub _draw_brackets:DrawCursor,
+    // the rows inside the block the cursor is in, and its indent guide
+    pub _active_scope:Option<(usize, usize, usize)>,
+    // the comment markers of the language we last drew with
+    pub _line_comment:Option<&'static str>,
+    pub _block_comment:Option<(&'static str, &'static str)>,
+    pub _auto_close_pairs:&'static [(char, char)],
+    pub _key_sequence:Vec<KeyChord>,
+    // other views can edit the buffer too, this view moves its cursors along up to _synced_change_id
+    pub _view_id:u64,
+    pub _synced_change_id:u64,
+    // the offset and row of the top line on screen, edits above it don't move the text
+    pub _scroll_anchor:(usize, usize)
 }
 
 #[derive(Clone, Default)]
@@ -41,6 +105,21 @@ pub struct SelectScroll{
     pub abs:Vec2
 }
 
+// rows and columns on screen, the columns can be past the end of a line
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GridSelect{
+    pub start_row:usize,
+    pub start_col:usize,
+    pub end_row:usize,
+    pub end_col:usize
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SelectUnit{
+    Word,
+    Line
+}
+
 impl ElementLife for CodeEditor{
     fn construct(&mut self, _cx:&mut Cx){}
     fn destruct(&mut self, _cx:&mut Cx){}
@@ -53,11 +132,19 @@ impl Style for CodeEditor{
         let cursor_sh = Self::def_cursor_shader(cx);
         let code_editor = Self{
             cursors:CursorSet::new(),
+            find_bar:Style::style(cx),
+            vim:None,
+            gutter:Style::style(cx),
+            minimap:None,
+            soft_wrap:SoftWrap::Off,
+            wrap_column:100,
+            indent_size:4,
             tab:Quad{
                 color:color("#5"),
                 shader_id:cx.add_shader(tab_sh, "Editor.tab"),
                 ..Style::style(cx)
             },
+            tab_active_color:color("#9"),
             view:View{
                 scroll_h:Some(ScrollBar{
                     ..Style::style(cx)
@@ -78,6 +165,8 @@ impl Style for CodeEditor{
                 shader_id:cx.add_shader(marker_sh, "Editor.marker"),
                 ..Style::style(cx)
             }, 
+            search_marker_color:color256(90,78,40),
+            bracket_marker_color:color256(70,70,70),
             cursor:Quad{
                 color:color256(136,136,136),
                 shader_id:cx.add_shader(cursor_sh, "Editor.cursor"),
@@ -98,6 +187,21 @@ impl Style for CodeEditor{
                 wrapping:Wrapping::Line,
                 ..Style::style(cx)
             },
+            // syntax highlighting colors
+            col_whitespace:color256(110,110,110),
+            col_keyword:color256(91,155,211),
+            col_flow_keyword:color256(196,133,190),
+            col_identifier:color256(212,212,212),
+            col_operator:color256(212,212,212),
+            col_function:color256(220,220,174),
+            col_type:color256(86,201,177),
+            col_number:color256(182,206,170),
+            col_comment:color256(99,141,84),
+            col_doc_comment:color256(120,160,100),
+            col_paren:color256(212,212,212),
+            col_string:color256(204,145,123),
+            col_delim:color256(212,212,212),
+            col_fold:color256(160,160,160),
             _hit_state:HitState{no_scrolling:true, ..Default::default()},
             _monospace_size:Vec2::zero(),
             _last_finger_move:None,
@@ -111,7 +215,28 @@ impl Style for CodeEditor{
             _text_area:Area::Empty,
             _instance_count:0,
             _select_scroll:None,
-            _draw_cursor:DrawCursor::new()
+            _select_unit:None,
+            _grid_select:None,
+            _scroll_to_cursor:false,
+            _gutter_width:0.,
+            _wrap_layout:WrapLayout::default(),
+            _row_breaks:Vec::new(),
+            _row_break:0,
+            _row_col:0,
+            _row_indent:0,
+            _row_wraps:0,
+            _draw_cursor:DrawCursor::new(),
+            _draw_search:DrawCursor::new(),
+            _bracket_cursors:Vec::new(),
+            _draw_brackets:DrawCursor::new(),
+            _active_scope:None,
+            _line_comment:None,
+            _block_comment:None,
+            _auto_close_pairs:&[],
+            _key_sequence:Vec::new(),
+            _view_id:0,
+            _synced_change_id:0,
+            _scroll_anchor:(0, 0)
         };
         //tab.animator.default = tab.anim_default(cx);
         code_editor
@@ -121,7 +246,10 @@ impl Style for CodeEditor{
 #[derive(Clone, PartialEq)]
 pub enum CodeEditorEvent{
     None,
-    Change
+    Change,
+    Save,
+    // wants a second view on the buffer next to this one
+    SplitEditor
 }
 
 impl CodeEditor{
@@ -187,12 +315,21 @@ impl CodeEditor{
         sh
     }
 
-    pub fn handle_code_editor(&mut self, cx:&mut Cx, event:&mut Event, text_buffer:&mut TextBuffer)->CodeEditorEvent{
+    pub fn handle_code_editor(&mut self, cx:&mut Cx, event:&mut Event, text_buffer:&mut TextBuffer, keymap:&Keymap)->CodeEditorEvent{
+        let mut code_editor_event = CodeEditorEvent::None;
+        self.sync_text_buffer(cx, text_buffer);
+        // only used where push_undo has no wall clock, any event that can lead to an edit moves it along
+        match event{
+            Event::FingerDown(fe)=>text_buffer.undo_time = fe.time,
+            Event::FingerMove(fe)=>text_buffer.undo_time = fe.time,
+            Event::FingerUp(fe)=>text_buffer.undo_time = fe.time,
+            Event::KeyDown(ke)=>text_buffer.undo_time = ke.time,
+            _=>()
+        }
         match self.view.handle_scroll_bars(cx, event){
             (_,ScrollBarEvent::Scroll{..}) | (ScrollBarEvent::Scroll{..},_)=>{
                 if let Some(last_finger_move) = self._last_finger_move{
-                    let offset = self.text.find_closest_offset(cx, &self._text_area, last_finger_move);
-                    self.cursors.set_last_cursor_head(offset, text_buffer);
+                    self.drag_to_abs(cx, text_buffer, last_finger_move);
                 }
                 // the editor actually redraws on scroll, its because we don't actually
                 // generate the entire file as GPU text-buffer just the visible area
@@ -201,6 +338,13 @@ impl CodeEditor{
             },
             _=>()
         }
+        // a buffer that is still loading or failed to load must not be edited
+        if text_buffer.load_id != 0 || text_buffer.load_error.is_some(){
+            match event{
+                Event::KeyDown(_) | Event::TextInput(_) | Event::TextPaste(_)=>return code_editor_event,
+                _=>()
+            }
+        }
         match event.hits(cx, self._bg_area, &mut self._hit_state){
 
             Event::Animate(_ae)=>{
@@ -209,32 +353,102 @@ impl CodeEditor{
                 cx.set_down_mouse_cursor(MouseCursor::Text);
                 // give us the focus
                 cx.set_key_focus(self._bg_area);
-                let offset = self.text.find_closest_offset(cx, &self._text_area, fe.abs);
-                if fe.modifiers.shift{
-                    if !fe.modifiers.logo{ // simply place selection
-                        self.cursors.only_last_cursor_head(offset, text_buffer);
+                self.find_bar.has_focus = false;
+                // the minimap scrolls, it doesn't place cursors
+                let minimap_line = match &mut self.minimap{
+                    Some(minimap) if minimap.contains(fe.rel)=>Some(minimap.begin_drag(fe.rel)),
+                    _=>None
+                };
+                if let Some(line) = minimap_line{
+                    if let Some(line) = line{
+                        self.scroll_to_map_line(cx, text_buffer, line);
                     }
-                    else{ // grid select
-
+                    self.view.redraw_view_area(cx);
+                    return code_editor_event
+                }
+                if let Some(vim) = &mut self.vim{
+                    vim.handle_finger_down();
+                }
+                // the gutter folds and unfolds, or selects lines
+                if fe.rel.x + self._scroll_pos.x < self._gutter_width{
+                    let (row, _) = self.visual_to_pos(text_buffer, ((fe.rel.y + self._scroll_pos.y) / self._monospace_size.y).max(0.) as usize);
+                    let row = row.min(text_buffer.get_line_count() - 1);
+                    let slot = self.gutter.slot_at(fe.rel.x + self._scroll_pos.x);
+                    if slot != GutterSlot::Fold || !self.toggle_fold(row, text_buffer){
+                        self.select_gutter_lines(row, fe.modifiers.shift, fe.modifiers.logo, text_buffer);
+                        self._last_finger_move = Some(fe.abs);
                     }
+                    self.view.redraw_view_area(cx);
+                    return code_editor_event
+                }
+                let offset = self.offset_at_abs(cx, text_buffer, fe.abs);
+                self._grid_select = None;
+                self._select_unit = None;
+                // grid select works in buffer columns, which wrapped rows don't line up with
+                if !self.is_wrapping(text_buffer) && (fe.modifiers.alt || fe.modifiers.shift && fe.modifiers.logo){ // grid select
+                    let (row, _, col) = self.pos_at_abs(cx, text_buffer, fe.abs);
+                    self._grid_select = Some(GridSelect{start_row:row, start_col:col, end_row:row, end_col:col});
+                    self.update_grid_select(text_buffer);
+                }
+                else if fe.modifiers.shift{ // simply place selection
+                    self.cursors.only_last_cursor_head(offset, text_buffer);
                 }
                 else{ // cursor drag with possible add
                     self.cursors.set_last_cursor_head_and_tail(fe.modifiers.logo, offset, text_buffer);
+                    // double click selects a word, triple click a line
+                    self._select_unit = match fe.tap_count{
+                        2=>Some(SelectUnit::Word),
+                        3=>Some(SelectUnit::Line),
+                        _=>None
+                    }.map(|unit|{
+                        let (start, end) = Self::unit_range(unit, offset, text_buffer);
+                        (unit, start, end)
+                    });
+                    if let Some((_, start, end)) = self._select_unit{
+                        self.cursors.set_last_cursor_head(end, text_buffer);
+                        let last = self.cursors.last_cursor;
+                        self.cursors.set[last].tail = start;
+                    }
                 }
                 self.view.redraw_view_area(cx);
                 self._last_finger_move = Some(fe.abs);
             },
-            Event::FingerHover(_fe)=>{
-                cx.set_hover_mouse_cursor(MouseCursor::Text);
+            Event::FingerHover(fe)=>{
+                let over_minimap = match &self.minimap{
+                    Some(minimap)=>minimap.contains(fe.rel),
+                    None=>false
+                };
+                if over_minimap || fe.rel.x + self._scroll_pos.x < self._gutter_width{
+                    cx.set_hover_mouse_cursor(MouseCursor::Default);
+                }
+                else{
+                    cx.set_hover_mouse_cursor(MouseCursor::Text);
+                }
             },
             Event::FingerUp(_fe)=>{
                 //self.cursors.end_cursor_drag(text_buffer);
+                if let Some(minimap) = &mut self.minimap{
+                    if minimap._drag.is_some(){
+                        minimap.end_drag();
+                        self.view.redraw_view_area(cx);
+                    }
+                }
                 self._select_scroll = None;
+                self._select_unit = None;
                 self._last_finger_move = None;
             },
             Event::FingerMove(fe)=>{
-                let offset = self.text.find_closest_offset(cx, &self._text_area, fe.abs);
-                self.cursors.set_last_cursor_head(offset, text_buffer);
+                let minimap_drag = match &self.minimap{
+                    Some(minimap) if minimap._drag.is_some()=>Some(minimap.drag_to(fe.rel)),
+                    _=>None
+                };
+                if let Some(line) = minimap_drag{
+                    if let Some(line) = line{
+                        self.scroll_to_map_line(cx, text_buffer, line);
+                    }
+                    return code_editor_event
+                }
+                self.drag_to_abs(cx, text_buffer, fe.abs);
 
                 self._last_finger_move = Some(fe.abs);
                 // determine selection drag scroll dynamics
@@ -289,94 +503,319 @@ impl CodeEditor{
                 }
             },
             Event::KeyDown(ke)=>{
-                let cursor_moved = match ke.key_code{
-                    KeyCode::ArrowUp=>{
-                        self.cursors.move_up(1, ke.modifiers.shift, text_buffer);
+                if self.find_bar.has_focus{
+                    if let Some(cursor_moved) = self.find_bar.handle_key_down(&ke, &mut self.cursors, text_buffer){
+                        if cursor_moved{
+                            self.scroll_last_cursor_visible(cx, text_buffer);
+                        }
+                        self.view.redraw_view_area(cx);
+                        return code_editor_event
+                    }
+                }
+                if let Some(vim) = &mut self.vim{
+                    if let Some(vim_event) = vim.handle_key_down(&ke, &mut self.cursors, text_buffer, self.indent_size){
+                        return self.handle_vim_event(cx, vim_event, text_buffer)
+                    }
+                }
+                let command = match keymap.resolve(&[KeyContext::Editor], &mut self._key_sequence, &ke){
+                    Some(command)=>command,
+                    None=>return code_editor_event
+                };
+                let cursor_moved = match command{
+                    KeyCommand::CursorUp | KeyCommand::SelectUp=>{
+                        self.move_cursors_up(1, command == KeyCommand::SelectUp, text_buffer);
                         true
                     },
-                    KeyCode::ArrowDown=>{
-                        self.cursors.move_down(1, ke.modifiers.shift, text_buffer);
+                    KeyCommand::CursorDown | KeyCommand::SelectDown=>{
+                        self.move_cursors_down(1, command == KeyCommand::SelectDown, text_buffer);
                         true
                     },
-                    KeyCode::ArrowLeft=>{
-                        self.cursors.move_left(1, ke.modifiers.shift, text_buffer);
+                    KeyCommand::CursorLeft | KeyCommand::SelectLeft=>{
+                        self.cursors.move_left(1, command == KeyCommand::SelectLeft, text_buffer);
                         true
                     },
-                    KeyCode::ArrowRight=>{
-                        self.cursors.move_right(1, ke.modifiers.shift, text_buffer);
+                    KeyCommand::CursorRight | KeyCommand::SelectRight=>{
+                        self.cursors.move_right(1, command == KeyCommand::SelectRight, text_buffer);
                         true
                     },
-                    KeyCode::PageUp=>{
-                        self.cursors.move_up(self._visible_lines.max(5) - 4, ke.modifiers.shift, text_buffer);
+                    KeyCommand::WordLeft | KeyCommand::SelectWordLeft=>{
+                        self.cursors.move_word_left(command == KeyCommand::SelectWordLeft, text_buffer);
                         true
                     },
-                    KeyCode::PageDown=>{
-                        self.cursors.move_down(self._visible_lines.max(5) - 4, ke.modifiers.shift, text_buffer);
+                    KeyCommand::WordRight | KeyCommand::SelectWordRight=>{
+                        self.cursors.move_word_right(command == KeyCommand::SelectWordRight, text_buffer);
                         true
                     },
-                    KeyCode::Home=>{
-                        self.cursors.move_home(ke.modifiers.shift, text_buffer);
+                    KeyCommand::PageUp | KeyCommand::SelectPageUp=>{
+                        self.move_cursors_up(self._visible_lines.max(5) - 4, command == KeyCommand::SelectPageUp, text_buffer);
                         true
                     },
-                    KeyCode::End=>{
-                        self.cursors.move_end(ke.modifiers.shift, text_buffer);
+                    KeyCommand::PageDown | KeyCommand::SelectPageDown=>{
+                        self.move_cursors_down(self._visible_lines.max(5) - 4, command == KeyCommand::SelectPageDown, text_buffer);
                         true
                     },
-                    KeyCode::Backspace=>{
+                    KeyCommand::LineStart | KeyCommand::SelectLineStart=>{
+                        self.cursors.move_home(command == KeyCommand::SelectLineStart, text_buffer);
+                        true
+                    },
+                    KeyCommand::LineEnd | KeyCommand::SelectLineEnd=>{
+                        self.cursors.move_end(command == KeyCommand::SelectLineEnd, text_buffer);
+                        true
+                    },
+                    KeyCommand::SelectAll=>{
+                        self.cursors.select_all(text_buffer);
+                        // don't scroll!
+                        self.view.redraw_view_area(cx);
+                        false
+                    },
+                    KeyCommand::Backspace=>{
                         self.cursors.backspace(text_buffer);
                         true
                     },
-                    KeyCode::Delete=>{
+                    KeyCommand::Delete=>{
                         self.cursors.delete(text_buffer);
                         true
                     },
-                    KeyCode::KeyZ=>{
-                        if ke.modifiers.logo || ke.modifiers.control{
-                            if ke.modifiers.shift{ // redo
-                                text_buffer.redo(true, &mut self.cursors);
-                                true
-                            }
-                            else{ // undo
-                                text_buffer.undo(true, &mut self.cursors);
-                                true
-                            }
+                    KeyCommand::BackspaceWord=>{
+                        self.cursors.backspace_word(text_buffer);
+                        true
+                    },
+                    KeyCommand::DeleteWord=>{
+                        self.cursors.delete_word(text_buffer);
+                        true
+                    },
+                    KeyCommand::Tab=>{ // indent when something is selected
+                        if self.cursors.set.iter().any(|cursor| cursor.has_selection()){
+                            self.cursors.indent_lines(self.indent_size, text_buffer);
                         }
                         else{
-                            false
+                            self.cursors.insert_tab(self.indent_size, text_buffer);
                         }
+                        true
                     },
-                    KeyCode::KeyX=>{ // cut
-                        if ke.modifiers.logo || ke.modifiers.control{ // cut
-                            self.cursors.replace_text("", text_buffer);
-                            true
+                    KeyCommand::Indent=>{
+                        self.cursors.indent_lines(self.indent_size, text_buffer);
+                        true
+                    },
+                    KeyCommand::Outdent=>{
+                        self.cursors.outdent_lines(self.indent_size, text_buffer);
+                        true
+                    },
+                    KeyCommand::AddCursorAbove=>{
+                        self.cursors.add_cursor_above(text_buffer);
+                        true
+                    },
+                    KeyCommand::AddCursorBelow=>{
+                        self.cursors.add_cursor_below(text_buffer);
+                        true
+                    },
+                    KeyCommand::NextOccurrence=>{
+                        self.cursors.add_next_occurrence(text_buffer);
+                        true
+                    },
+                    KeyCommand::AllOccurrences=>{
+                        self.cursors.select_all_occurrences(text_buffer);
+                        true
+                    },
+                    KeyCommand::SplitIntoLines=>{
+                        self.cursors.split_into_lines(text_buffer);
+                        true
+                    },
+                    KeyCommand::MoveLinesUp | KeyCommand::MoveLinesDown=>{
+                        self.cursors.move_lines(command == KeyCommand::MoveLinesUp, text_buffer);
+                        true
+                    },
+                    KeyCommand::DuplicateLines=>{
+                        self.cursors.duplicate_lines(text_buffer);
+                        true
+                    },
+                    KeyCommand::JoinLines=>{
+                        self.cursors.join_lines(text_buffer);
+                        true
+                    },
+                    KeyCommand::DeleteLines=>{
+                        self.cursors.delete_lines(text_buffer);
+                        true
+                    },
+                    KeyCommand::ToggleLineComment=>{ // block comments if the language has no line ones
+                        if let Some(marker) = self._line_comment{
+                            self.cursors.toggle_line_comment(marker, text_buffer);
                         }
-                        else{
-                            false
+                        else if let Some((open, close)) = self._block_comment{
+                            self.cursors.toggle_block_comment(open, close, text_buffer);
+                        }
+                        true
+                    },
+                    KeyCommand::ToggleBlockComment=>{
+                        if let Some((open, close)) = self._block_comment{
+                            self.cursors.toggle_block_comment(open, close, text_buffer);
                         }
+                        true
+                    },
+                    KeyCommand::ToggleSoftWrap | KeyCommand::ToggleColumnWrap=>{
+                        let soft_wrap = if command == KeyCommand::ToggleColumnWrap{SoftWrap::Column}else{SoftWrap::ViewWidth};
+                        self.soft_wrap = if self.soft_wrap == soft_wrap{SoftWrap::Off}else{soft_wrap};
+                        self.view.redraw_view_area(cx);
+                        false
                     },
-                    KeyCode::KeyA=>{ // select all
-                        if ke.modifiers.logo || ke.modifiers.control{ // cut
-                            self.cursors.select_all(text_buffer);
-                            // don't scroll!
+                    KeyCommand::Undo=>{
+                        text_buffer.undo(true, &mut self.cursors);
+                        true
+                    },
+                    KeyCommand::Redo=>{
+                        text_buffer.redo(true, &mut self.cursors);
+                        true
+                    },
+                    KeyCommand::PrevUndoBranch | KeyCommand::NextUndoBranch=>{
+                        text_buffer.switch_undo_branch(if command == KeyCommand::NextUndoBranch{1}else{-1}, &mut self.cursors);
+                        true
+                    },
+                    KeyCommand::UndoMinute=>{ // undo history a minute back in time
+                        text_buffer.undo_by_time(-60.0, &mut self.cursors);
+                        true
+                    },
+                    KeyCommand::RedoMinute=>{ // and a minute forward
+                        text_buffer.undo_by_time(60.0, &mut self.cursors);
+                        true
+                    },
+                    KeyCommand::Cut=>{
+                        cx.set_clipboard_text(&self.cursors.get_all_as_string(text_buffer));
+                        self.cursors.replace_text("", text_buffer);
+                        true
+                    },
+                    KeyCommand::Save=>{
+                        code_editor_event = CodeEditorEvent::Save;
+                        false
+                    },
+                    KeyCommand::Find | KeyCommand::FindReplace=>{
+                        self.find_bar.open(command == KeyCommand::FindReplace, &self.cursors, text_buffer);
+                        self.view.redraw_view_area(cx);
+                        false
+                    },
+                    KeyCommand::FindNext | KeyCommand::FindPrev=>{
+                        self.find_bar.select_next(command == KeyCommand::FindPrev, &mut self.cursors, text_buffer)
+                    },
+                    KeyCommand::CloseFind=>{
+                        if self.find_bar.is_open{
+                            self.find_bar.close();
                             self.view.redraw_view_area(cx);
-                            false
+                        }
+                        false
+                    },
+                    KeyCommand::JumpToBracket | KeyCommand::SelectEnclosingBlock=>{
+                        if text_buffer.bracket_index.is_current(text_buffer){
+                            if command == KeyCommand::SelectEnclosingBlock{
+                                self.cursors.select_enclosing_block(&text_buffer.bracket_index, text_buffer);
+                            }
+                            else{
+                                self.cursors.jump_to_bracket(false, &text_buffer.bracket_index, text_buffer);
+                            }
+                            true
                         }
                         else{
                             false
                         }
-                    }
+                    },
+                    KeyCommand::Fold=>{ // the block the cursor is in
+                        let (row, _) = text_buffer.offset_to_row_col(self.cursors.set[self.cursors.last_cursor].head);
+                        if let Some(region) = text_buffer.folds.region_around(row){
+                            text_buffer.folds.fold(region);
+                            self.cursors_out_of_folds(text_buffer);
+                        }
+                        self.view.redraw_view_area(cx);
+                        true
+                    },
+                    KeyCommand::FoldAll=>{
+                        text_buffer.folds.fold_all();
+                        self.cursors_out_of_folds(text_buffer);
+                        self.view.redraw_view_area(cx);
+                        true
+                    },
+                    KeyCommand::Unfold=>{ // the block at the cursor
+                        let (row, _) = text_buffer.offset_to_row_col(self.cursors.set[self.cursors.last_cursor].head);
+                        text_buffer.folds.unfold(row);
+                        self.view.redraw_view_area(cx);
+                        true
+                    },
+                    KeyCommand::UnfoldAll=>{
+                        text_buffer.folds.unfold_all();
+                        self.view.redraw_view_area(cx);
+                        true
+                    },
+                    KeyCommand::ToggleLineNumbers=>{ // absolute, relative, none
+                        self.gutter.line_numbers = match self.gutter.line_numbers{
+                            LineNumbers::Absolute=>LineNumbers::Relative,
+                            LineNumbers::Relative=>LineNumbers::Off,
+                            LineNumbers::Off=>LineNumbers::Absolute
+                        };
+                        self.view.redraw_view_area(cx);
+                        false
+                    },
+                    KeyCommand::ToggleMinimap=>{
+                        self.minimap = if self.minimap.is_some(){None}else{Some(Style::style(cx))};
+                        self.view.redraw_view_area(cx);
+                        false
+                    },
+                    KeyCommand::ToggleVim=>{
+                        self.vim = if self.vim.is_some(){None}else{Some(Style::style(cx))};
+                        self.view.redraw_view_area(cx);
+                        false
+                    },
+                    KeyCommand::SplitEditor=>{
+                        code_editor_event = CodeEditorEvent::SplitEditor;
+                        false
+                    },
                     _=>false
                 };
                 if cursor_moved{
+                    self._grid_select = None;
                     self.scroll_last_cursor_visible(cx, text_buffer);
                     self.view.redraw_view_area(cx);
                 }
             },
             Event::TextInput(te)=>{
+                if self.find_bar.has_focus{
+                    self.find_bar.handle_text_input(&te.input);
+                    self.view.redraw_view_area(cx);
+                    return code_editor_event
+                }
+                if let Some(vim) = &mut self.vim{
+                    if let Some(vim_event) = vim.handle_text_input(&te.input, &mut self.cursors, text_buffer, self.indent_size){
+                        return self.handle_vim_event(cx, vim_event, text_buffer)
+                    }
+                }
                 if te.replace_last{
                     text_buffer.undo(false, &mut self.cursors);
+                    text_buffer.undo_tree.forget_last();
+                }
+                let mut chars = te.input.chars();
+                let single_char = match (chars.next(), chars.next()){
+                    (Some(ch), None) if !te.replace_last=>Some(ch),
+                    _=>None
+                };
+                if let Some(grid_select) = self._grid_select.take(){
+                    self.replace_grid_text(&grid_select, &te.input, text_buffer);
+                }
+                else if single_char == Some('\n'){
+                    self.cursors.insert_newline(self.indent_size, text_buffer);
+                }
+                else if let Some(ch) = single_char{
+                    self.cursors.type_char(ch, self._auto_close_pairs, self.indent_size, text_buffer);
+                }
+                else{
+                    self.cursors.replace_text(&te.input, text_buffer);
+                }
+                self.scroll_last_cursor_visible(cx, text_buffer);
+                self.view.redraw_view_area(cx);
+            },
+            Event::TextPaste(tp)=>{
+                if self.find_bar.has_focus{
+                    self.find_bar.handle_text_input(&tp.text);
+                    self.view.redraw_view_area(cx);
+                    return code_editor_event
                 }
-                self.cursors.replace_text(&te.input, text_buffer);
+                self._grid_select = None;
+                self.cursors.paste_text(&tp.text, text_buffer);
                 self.scroll_last_cursor_visible(cx, text_buffer);
                 self.view.redraw_view_area(cx);
             },
@@ -388,9 +827,59 @@ impl CodeEditor{
             },
             _=>()
         };
-        CodeEditorEvent::None
+        code_editor_event
    }
 
+    fn handle_vim_event(&mut self, cx:&mut Cx, vim_event:VimEvent, text_buffer:&TextBuffer)->CodeEditorEvent{
+        if let Some(vim) = &mut self.vim{
+            if let Some(text) = vim.clipboard_write.take(){
+                cx.set_clipboard_text(&text);
+            }
+        }
+        self._grid_select = None;
+        if vim_event == VimEvent::CursorMoved{
+            self.scroll_last_cursor_visible(cx, text_buffer);
+        }
+        self.view.redraw_view_area(cx);
+        if vim_event == VimEvent::Save{CodeEditorEvent::Save}else{CodeEditorEvent::None}
+    }
+
+    // makes the buffer edits of this view ours, and moves cursors and scrolling along with the
+    // edits other views made since we last looked
+    fn sync_text_buffer(&mut self, cx:&mut Cx, text_buffer:&mut TextBuffer){
+        if self._view_id == 0{
+            self._view_id = text_buffer.alloc_view_id();
+            self._synced_change_id = text_buffer.change_id;
+        }
+        text_buffer.view_id = self._view_id;
+        if self._synced_change_id == text_buffer.change_id{
+            return
+        }
+        let mut offsets = vec![self._scroll_anchor.0];
+        if let Some(vim) = &self.vim{
+            for (anchor, pos) in &vim._visual{
+                offsets.push(*anchor);
+                offsets.push(*pos);
+            }
+        }
+        text_buffer.remap_since(self._synced_change_id, self._view_id, &mut self.cursors, &mut offsets);
+        self._synced_change_id = text_buffer.change_id;
+        self.cursors.fuse_adjacent(text_buffer);
+        if let Some(vim) = &mut self.vim{
+            for (index, (anchor, pos)) in vim._visual.iter_mut().enumerate(){
+                *anchor = offsets[1 + index * 2];
+                *pos = offsets[2 + index * 2];
+            }
+        }
+        let (anchor_row, _) = text_buffer.offset_to_row_col(offsets[0]);
+        if anchor_row != self._scroll_anchor.1{
+            let delta = anchor_row as f32 - self._scroll_anchor.1 as f32;
+            let scroll_pos = self.view.get_scroll_pos(cx);
+            self.view.set_scroll_pos(cx, Vec2{x:scroll_pos.x, y:(scroll_pos.y + delta * self._monospace_size.y).max(0.)});
+        }
+        self._scroll_anchor = (offsets[0], anchor_row);
+    }
+
     pub fn begin_code_editor(&mut self, cx:&mut Cx, text_buffer:&TextBuffer)->bool{
         // pull the bg color from our animation system, uses 'default' value otherwise
         // self.bg.color = self.animator.last_vec4("bg.color");
@@ -399,13 +888,18 @@ impl CodeEditor{
         self.view.begin_view(cx, &Layout{..Default::default()});
         //   return false
         //}
-        if text_buffer.load_id != 0{
+        if text_buffer.load_id != 0 || text_buffer.load_error.is_some(){
             let bg_inst = self.bg.begin_quad(cx, &Layout{
                 align:Align::left_top(),
                 ..self.bg_layout.clone()
             });
             self.text.color = color("#666");
-            self.text.draw_text(cx, "...");
+            if let Some(load_error) = &text_buffer.load_error{
+                self.text.draw_text(cx, load_error);
+            }
+            else{
+                self.text.draw_text(cx, "...");
+            }
             self.bg.end_quad(cx, &bg_inst);
             self._bg_area = bg_inst.into_area();
             self.view.end_view(cx);
@@ -433,12 +927,20 @@ impl CodeEditor{
             };
 
             self._monospace_size = self.text.get_monospace_size(cx, None);
+            self._gutter_width = self.gutter.begin_gutter(text_buffer, &self.cursors, self._monospace_size);
             self._line_geometry.truncate(0);
+            self._row_wraps = 0;
             self._draw_cursor = DrawCursor::new();
             self._first_on_line = true;
             self._visible_lines = 0;
             // prime the next cursor
             self._draw_cursor.set_next(&self.cursors.set);
+            // and the search matches
+            self.find_bar.update_matches(text_buffer);
+            self._draw_search = DrawCursor::new();
+            self._draw_search.set_next(&self.find_bar._match_cursors);
+            self._draw_brackets = DrawCursor::new();
+            self._draw_brackets.set_next(&self._bracket_cursors);
             // cursor after text
             cx.new_instance_layer(self.cursor.shader_id, 0);
             
@@ -446,52 +948,570 @@ impl CodeEditor{
         }
     }
     
+    // draws the buffer as the tokenizer splits it up. only the visible lines are tokenized,
+    // starting from the line state the buffer cached for the first one
+    pub fn draw_code_editor<T:Tokenizer + Default>(&mut self, cx:&mut Cx, text_buffer:&mut TextBuffer, tokenizer:&mut T){
+        self.sync_text_buffer(cx, text_buffer);
+        self._line_comment = tokenizer.line_comment();
+        self._block_comment = tokenizer.block_comment();
+        self._auto_close_pairs = tokenizer.auto_close_pairs();
+        if !text_buffer.bracket_index.is_current(text_buffer){
+            text_buffer.bracket_index = BracketIndex::build(text_buffer, &mut T::default());
+        }
+        if !text_buffer.folds.is_current(text_buffer){
+            let regions = Folds::find_regions(text_buffer);
+            let change_id = text_buffer.change_id;
+            text_buffer.folds.set_regions(regions, change_id);
+        }
+        // a cursor that ended up in folded lines opens them
+        let (cursor_row, _) = text_buffer.offset_to_row_col(self.cursors.set[self.cursors.last_cursor].head);
+        if text_buffer.folds.reveal_row(cursor_row){
+            self._scroll_to_cursor = true;
+        }
+        self.update_brackets(text_buffer);
+        if !self.begin_code_editor(cx, text_buffer){
+            return
+        }
+
+        if self.soft_wrap != SoftWrap::Off{
+            let cols = if self.soft_wrap == SoftWrap::Column{
+                self.wrap_column
+            }
+            else{
+                let minimap_width = if let Some(minimap) = &self.minimap{minimap.total_width()}else{0.};
+                ((cx.width_total(false) - self._gutter_width - minimap_width) / self._monospace_size.x - 2.).max(0.) as usize
+            };
+            self._wrap_layout.update(text_buffer, cols.max(20));
+        }
+
+        // folded lines take no space and wrapped ones more, so the rows on screen are found through the layout
+        let line_count = text_buffer.get_line_count();
+        let line_height = self._monospace_size.y;
+        let view_height = cx.height_total(false);
+        let first_visual = ((self._scroll_pos.y - self._visibility_margin.t) / line_height).max(0.) as usize;
+        let last_visual = ((self._scroll_pos.y + view_height + self._visibility_margin.b) / line_height).max(0.) as usize + 1;
+        let (first_row, _) = self.visual_to_pos(text_buffer, first_visual);
+        let first_row = first_row.min(line_count - 1);
+        let (last_row, _) = self.visual_to_pos(text_buffer, last_visual);
+        let (anchor_row, _) = self.visual_to_pos(text_buffer, (self._scroll_pos.y / line_height).max(0.) as usize);
+        let anchor_row = anchor_row.min(line_count - 1);
+        self._scroll_anchor = (text_buffer.rope.row_to_offset(anchor_row), anchor_row);
+
+        let (start_row, line_state) = text_buffer.token_cache.start_for_row(first_row);
+        self.skip_lines(cx, text_buffer, 0, start_row);
+        tokenizer.set_line_state(line_state);
+
+        let mut chunk = Vec::new();
+        let mut state = TokenizerState::new_at_row(text_buffer, start_row);
+        let mut row = start_row;
+        // the tokenizer borrows the buffer, so the cache is updated after
+        let mut line_states = Vec::new();
+        let mut skip_from_row = line_count;
+        let mut at_row_start = true;
+        let mut hidden = false;
+
+        let mut after_newline = true;
+        let mut last_tabs = Self::indent_tabs_above(text_buffer, start_row);
+        let mut newline_tabs = 0;
+
+        loop{
+            if at_row_start{
+                at_row_start = false;
+                // jump over folded lines when we know the line state after them
+                if let Some(last) = text_buffer.folds.hidden_span_end(row){
+                    let (cached_row, cached_state) = text_buffer.token_cache.start_for_row(last + 1);
+                    if cached_row == last + 1{
+                        for _ in row..cached_row{
+                            self.hide_line(cx);
+                        }
+                        row = cached_row;
+                        tokenizer.set_line_state(cached_state);
+                        state = TokenizerState::new_at_row(text_buffer, row);
+                        if row > last_row{
+                            skip_from_row = row;
+                            break
+                        }
+                    }
+                }
+                hidden = text_buffer.folds.is_hidden(row);
+                if !hidden{
+                    self.begin_row(text_buffer, row);
+                    self.draw_gutter(cx, text_buffer, row);
+                }
+            }
+            let token_type = tokenizer.next_token(&mut state, &mut chunk);
+            match token_type{
+                TokenType::Eof=>{
+                    break
+                },
+                _ if hidden=>(),
+                TokenType::Newline=>{
+                    // empty lines continue the indent guides of the line above
+                    if after_newline{
+                        self.draw_tab_lines(cx, last_tabs);
+                    }
+                    else{
+                        last_tabs = newline_tabs;
+                    }
+                    after_newline = true;
+                    newline_tabs = 0;
+                },
+                TokenType::Whitespace=>{
+                    if after_newline{ // indent guides every 4 spaces
+                        let tabs = chunk.len() >> 2;
+                        last_tabs = tabs;
+                        newline_tabs = tabs;
+                        self.draw_tab_lines(cx, tabs);
+                    }
+                },
+                _=>{
+                    after_newline = false;
+                }
+            }
+            if !hidden{
+                let color = self.token_color(token_type);
+                self.draw_wrapped_text(cx, &chunk, state.offset, color);
+            }
+            chunk.truncate(0);
+            if token_type == TokenType::Newline{
+                if hidden{
+                    self.hide_line(cx);
+                }
+                else{
+                    if text_buffer.folds.is_folded(row){
+                        self.draw_fold_marker(cx, state.offset - 2);
+                    }
+                    self.new_line(cx);
+                }
+                row += 1;
+                line_states.push((row, tokenizer.line_state()));
+                if row > last_row{
+                    skip_from_row = row;
+                    break
+                }
+                at_row_start = true;
+            }
+        }
+        for (row, line_state) in line_states{
+            text_buffer.token_cache.set_line_state(row, line_state);
+        }
+        // the minimap tokenizes the rows in its column, from the line states cached above
+        if let Some((first_row, last_row)) = self.layout_minimap(cx, text_buffer){
+            let max_cols = match &self.minimap{
+                Some(minimap) if !minimap.lines.is_current(text_buffer, first_row, last_row)=>Some((minimap.width / minimap.char_size.x) as usize),
+                _=>None
+            };
+            if let Some(max_cols) = max_cols{
+                let (lines, line_states) = MinimapLines::build(text_buffer, &mut T::default(), first_row, last_row, max_cols, |token_type| self.token_color(token_type));
+                for (row, line_state) in line_states{
+                    text_buffer.token_cache.set_line_state(row, line_state);
+                }
+                if let Some(minimap) = &mut self.minimap{
+                    minimap.lines = lines;
+                }
+            }
+        }
+        self.skip_lines(cx, text_buffer, skip_from_row, line_count);
+
+        self.end_code_editor(cx, text_buffer);
+    }
+
+    // lays out lines without tokenizing them, they are out of view
+    fn skip_lines(&mut self, cx:&mut Cx, text_buffer:&TextBuffer, from_row:usize, to_row:usize){
+        let line_count = text_buffer.get_line_count();
+        for row in from_row..to_row{
+            if text_buffer.folds.is_hidden(row){
+                self.hide_line(cx);
+                continue
+            }
+            // a wrapped line is walked as one block as high as its lines together
+            let line_len = text_buffer.get_line_len(row);
+            let (width, lines) = if self.is_wrapping(text_buffer){
+                let wraps = self._wrap_layout.breaks[row].len();
+                (if wraps > 0{self._wrap_layout.cols}else{line_len}, wraps + 1)
+            }
+            else{
+                (line_len, 1)
+            };
+            cx.walk_turtle(
+                Bounds::Fix(self._gutter_width + self._monospace_size.x * width as f32),
+                Bounds::Fix(self._monospace_size.y * lines as f32),
+                Margin::zero(),
+                None
+            );
+            if row + 1 < line_count{
+                self.new_line(cx);
+            }
+        }
+    }
+
+    pub fn is_wrapping(&self, text_buffer:&TextBuffer)->bool{
+        self.soft_wrap != SoftWrap::Off && self._wrap_layout.is_current(text_buffer)
+    }
+
+    // the row a line on screen shows, and which of its wrapped lines it is
+    pub fn visual_to_pos(&self, text_buffer:&TextBuffer, visual:usize)->(usize, usize){
+        if self.is_wrapping(text_buffer){
+            self._wrap_layout.visual_to_pos(visual)
+        }
+        else{
+            (text_buffer.folds.visual_to_row(visual), 0)
+        }
+    }
+
+    // the offset under a finger, found from the layout of the lines on screen
+    // the row, the line of it on screen and the column at a position, the column can be past the line end
+    pub fn pos_at_abs(&mut self, cx:&mut Cx, text_buffer:&TextBuffer, abs:Vec2)->(usize, usize, usize){
+        let origin = self._bg_area.get_rect_no_scrolling(cx);
+        let scroll_pos = self.view.get_scroll_pos(cx);
+        let x = abs.x - origin.x + scroll_pos.x - self._gutter_width;
+        let y = abs.y - origin.y + scroll_pos.y;
+        let visual = (y / self._monospace_size.y).max(0.) as usize;
+        let x_col = (x / self._monospace_size.x).round().max(0.) as usize;
+        let (row, sub) = self.visual_to_pos(text_buffer, visual);
+        (row.min(text_buffer.get_line_count() - 1), sub, x_col)
+    }
+
+    pub fn offset_at_abs(&mut self, cx:&mut Cx, text_buffer:&TextBuffer, abs:Vec2)->usize{
+        let (row, sub, x_col) = self.pos_at_abs(cx, text_buffer, abs);
+        if self.is_wrapping(text_buffer){
+            self._wrap_layout.offset_at(text_buffer, row, sub, x_col)
+        }
+        else{
+            text_buffer.row_col_to_offset(row, x_col.min(text_buffer.get_line_len(row)))
+        }
+    }
+
+    // up and down go by lines on screen when lines are wrapped
+    fn move_cursors_up(&mut self, line_count:usize, only_head:bool, text_buffer:&TextBuffer){
+        if self.is_wrapping(text_buffer){
+            self.move_cursors_visual(-(line_count as isize), only_head, text_buffer);
+        }
+        else{
+            self.cursors.move_up(line_count, only_head, text_buffer);
+        }
+    }
+
+    fn move_cursors_down(&mut self, line_count:usize, only_head:bool, text_buffer:&TextBuffer){
+        if self.is_wrapping(text_buffer){
+            self.move_cursors_visual(line_count as isize, only_head, text_buffer);
+        }
+        else{
+            self.cursors.move_down(line_count, only_head, text_buffer);
+        }
+    }
+
+    fn move_cursors_visual(&mut self, delta:isize, only_head:bool, text_buffer:&TextBuffer){
+        let layout = &self._wrap_layout;
+        for cursor in &mut self.cursors.set{
+            let (row, col) = text_buffer.offset_to_row_col(cursor.head);
+            let (visual, x_col) = layout.pos_of(row, col);
+            let target = visual as isize + delta;
+            if target < 0{
+                cursor.head = 0;
+            }
+            else if target as usize >= layout.visual_count{
+                cursor.head = text_buffer.get_char_count();
+            }
+            else{
+                let (row, sub) = layout.visual_to_pos(target as usize);
+                cursor.head = layout.offset_at(text_buffer, row, sub, x_col);
+            }
+            if !only_head{cursor.tail = cursor.head}
+        }
+        self.cursors.fuse_adjacent(text_buffer);
+    }
+
+    // follows the finger while selecting, with a grid select it moves the corner of the box
+    pub fn drag_to_abs(&mut self, cx:&mut Cx, text_buffer:&TextBuffer, abs:Vec2){
+        if let Some(mut grid_select) = self._grid_select{
+            let (row, _, col) = self.pos_at_abs(cx, text_buffer, abs);
+            grid_select.end_row = row;
+            grid_select.end_col = col;
+            self._grid_select = Some(grid_select);
+            self.update_grid_select(text_buffer);
+        }
+        else{
+            let offset = self.offset_at_abs(cx, text_buffer, abs);
+            self.drag_last_cursor(offset, text_buffer);
+        }
+    }
+
+    // one cursor per visible row of the box, the head on the side being dragged. rows that end
+    // before the box get a cursor at their end
+    pub fn update_grid_select(&mut self, text_buffer:&TextBuffer){
+        let grid_select = if let Some(grid_select) = self._grid_select{grid_select}else{return};
+        let (first_row, last_row) = if grid_select.start_row <= grid_select.end_row{
+            (grid_select.start_row, grid_select.end_row)
+        }
+        else{
+            (grid_select.end_row, grid_select.start_row)
+        };
+        self.cursors.set.truncate(0);
+        for row in first_row..(last_row + 1){
+            if text_buffer.folds.is_hidden(row){
+                continue
+            }
+            let line_len = text_buffer.get_line_len(row);
+            self.cursors.set.push(Cursor{
+                head:text_buffer.row_col_to_offset(row, grid_select.end_col.min(line_len)),
+                tail:text_buffer.row_col_to_offset(row, grid_select.start_col.min(line_len)),
+                max:grid_select.end_col
+            });
+        }
+        self.cursors.last_cursor = if grid_select.end_row >= grid_select.start_row{self.cursors.set.len() - 1}else{0};
+    }
+
+    // typing into a grid select fills the rows that end before the box with spaces first
+    pub fn replace_grid_text(&mut self, grid_select:&GridSelect, text:&str, text_buffer:&mut TextBuffer){
+        let left_col = grid_select.start_col.min(grid_select.end_col);
+        let texts:Vec<String> = self.cursors.set.iter().map(|cursor|{
+            let (start, _) = cursor.order();
+            let (_, col) = text_buffer.offset_to_row_col(start);
+            let mut line_text = " ".repeat(left_col.max(col) - col);
+            line_text.push_str(text);
+            line_text
+        }).collect();
+        self.cursors.replace_texts(&texts, text_buffer);
+    }
+
+    pub fn unit_range(unit:SelectUnit, offset:usize, text_buffer:&TextBuffer)->(usize, usize){
+        match unit{
+            SelectUnit::Word=>text_buffer.word_range(offset),
+            SelectUnit::Line=>text_buffer.line_range(offset)
+        }
+    }
+
+    // moves the head of the last cursor while dragging, after a double or triple click it grows
+    // the selection a word or line at a time and keeps the first one selected
+    pub fn drag_last_cursor(&mut self, offset:usize, text_buffer:&TextBuffer){
+        if let Some((unit, start, end)) = self._select_unit{
+            let (unit_start, unit_end) = Self::unit_range(unit, offset, text_buffer);
+            let (head, tail) = if offset < start{(unit_start, end)}else{(unit_end.max(end), start)};
+            self.cursors.set_last_cursor_head(head, text_buffer);
+            let last = self.cursors.last_cursor;
+            self.cursors.set[last].tail = tail;
+        }
+        else{
+            self.cursors.set_last_cursor_head(offset, text_buffer);
+        }
+    }
+
+    // the lines on screen, folded ones don't count and wrapped ones count for every line
+    fn screen_line_count(&self, text_buffer:&TextBuffer)->usize{
+        if self.is_wrapping(text_buffer){
+            self._wrap_layout.visual_count
+        }
+        else{
+            text_buffer.folds.row_to_visual(text_buffer.get_line_count() - 1) + 1
+        }
+    }
+
+    // the map lines of the top and bottom of the editor and how far it is scrolled go to the minimap
+    fn layout_minimap(&mut self, cx:&mut Cx, text_buffer:&TextBuffer)->Option<(usize, usize)>{
+        if self.minimap.is_none(){
+            return None
+        }
+        let line_count = text_buffer.get_line_count();
+        let line_height = self._monospace_size.y;
+        let view_height = cx.height_total(false);
+        let (top_row, _) = self.visual_to_pos(text_buffer, (self._scroll_pos.y / line_height) as usize);
+        let (bottom_row, _) = self.visual_to_pos(text_buffer, ((self._scroll_pos.y + view_height) / line_height) as usize);
+        let top_line = text_buffer.folds.row_to_visual(top_row.min(line_count - 1));
+        let bottom_line = text_buffer.folds.row_to_visual(bottom_row.min(line_count - 1));
+        // the editor has an empty line at the bottom to scroll to
+        let max_scroll = (self.screen_line_count(text_buffer) + 1) as f32 * line_height - view_height;
+        let ratio = if max_scroll > 0.{self._scroll_pos.y / max_scroll}else{0.};
+        self.minimap.as_mut().map(|minimap| minimap.layout_minimap(cx, text_buffer, top_line, bottom_line, ratio))
+    }
+
+    fn draw_minimap(&mut self, cx:&mut Cx, text_buffer:&TextBuffer){
+        let diagnostics:Vec<(usize, Color)> = self.gutter.diagnostics.iter().map(|(row, level)| (*row, self.gutter.level_color(*level))).collect();
+        if let Some(minimap) = &mut self.minimap{
+            minimap.draw_minimap(cx, text_buffer, self._scroll_pos, &self.cursors.set, &self.find_bar._match_cursors, &diagnostics);
+        }
+    }
+
+    // scrolls the editor so a line of the minimap is at the top
+    fn scroll_to_map_line(&mut self, cx:&mut Cx, text_buffer:&TextBuffer, line:usize){
+        let row = text_buffer.folds.visual_to_row(line).min(text_buffer.get_line_count() - 1);
+        let screen_line = if self.is_wrapping(text_buffer){self._wrap_layout.visual_starts[row]}else{text_buffer.folds.row_to_visual(row)};
+        let scroll_pos = self.view.get_scroll_pos(cx);
+        if self.view.set_scroll_pos(cx, Vec2{x:scroll_pos.x, y:screen_line as f32 * self._monospace_size.y}){
+            self.view.redraw_view_area(cx);
+        }
+    }
+
+    // a click on the gutter selects the line, dragging grows it a line at a time. shift extends
+    // the selection to the line and logo adds a cursor
+    fn select_gutter_lines(&mut self, row:usize, extend:bool, add:bool, text_buffer:&TextBuffer){
+        self._grid_select = None;
+        let offset = text_buffer.row_col_to_offset(row, 0);
+        if extend{
+            let tail = self.cursors.set[self.cursors.last_cursor].tail;
+            let (start, end) = text_buffer.line_range(tail);
+            self._select_unit = Some((SelectUnit::Line, start, end));
+            self.drag_last_cursor(offset, text_buffer);
+        }
+        else{
+            let (start, end) = text_buffer.line_range(offset);
+            self.cursors.set_last_cursor_head_and_tail(add, start, text_buffer);
+            self._select_unit = Some((SelectUnit::Line, start, end));
+            self.cursors.set_last_cursor_head(end, text_buffer);
+            let last = self.cursors.last_cursor;
+            self.cursors.set[last].tail = start;
+        }
+    }
+
+    // folds the region at row, or unfolds it if it is folded
+    pub fn toggle_fold(&mut self, row:usize, text_buffer:&mut TextBuffer)->bool{
+        if text_buffer.folds.toggle(row){
+            self.cursors_out_of_folds(text_buffer);
+            true
+        }
+        else{
+            false
+        }
+    }
+
+    // cursors in lines that got folded move to the end of the line the fold starts on
+    fn cursors_out_of_folds(&mut self, text_buffer:&TextBuffer){
+        for cursor in &mut self.cursors.set{
+            let (row, _) = text_buffer.offset_to_row_col(cursor.head);
+            if text_buffer.folds.is_hidden(row){
+                let row = text_buffer.folds.visible_row_above(row, 1).unwrap_or(0);
+                cursor.head = text_buffer.row_col_to_offset(row, text_buffer.get_line_len(row));
+                cursor.tail = cursor.head;
+                cursor.calc_max(text_buffer);
+            }
+        }
+        self.cursors.fuse_adjacent(text_buffer);
+    }
+
+    // the gutter at the start of a line, as high as the line wraps
+    fn draw_gutter(&mut self, cx:&mut Cx, text_buffer:&TextBuffer, row:usize){
+        let lines = if self._row_breaks.len() > 0{self._row_breaks.len() + 1}else{1};
+        self.gutter.draw_gutter_row(cx, &mut self.text, self._text_inst.as_mut().unwrap(), text_buffer, row, lines, self._visibility_margin, self._scroll_pos);
+    }
+
+    // the dots after the line a fold starts on, they all map to the end of the line
+    fn draw_fold_marker(&mut self, cx:&mut Cx, offset:usize){
+        let dots = ['.', '.', '.'];
+        let geom = cx.walk_turtle(
+            Bounds::Fix(self._monospace_size.x * dots.len() as f32),
+            Bounds::Fix(self._monospace_size.y),
+            Margin::zero(),
+            None
+        );
+        if cx.visible_in_turtle(geom, self._visibility_margin, self._scroll_pos){
+            self.text.color = self.col_fold;
+            for (index, dot) in dots.iter().enumerate(){
+                let x = geom.x + self._monospace_size.x * index as f32;
+                self.text.add_text(cx, x, geom.y, offset, self._text_inst.as_mut().unwrap(), &[*dot], |_,_,_,_|{0.0});
+            }
+        }
+    }
+
+    // the bracket pair at the last cursor and the block it is in
+    fn update_brackets(&mut self, text_buffer:&TextBuffer){
+        self._bracket_cursors.truncate(0);
+        self._active_scope = None;
+        let bracket_index = &text_buffer.bracket_index;
+        let head = self.cursors.set[self.cursors.last_cursor].head;
+        let pair = bracket_index.pair_at_cursor(head);
+        if let Some((open, close)) = pair{
+            self._bracket_cursors.push(Cursor{head:open + 1, tail:open, max:0});
+            self._bracket_cursors.push(Cursor{head:close + 1, tail:close, max:0});
+        }
+        let scope = pair.or_else(|| bracket_index.enclosing_pair(head).map(|(open, close)|{
+            (bracket_index.brackets[open].offset, bracket_index.brackets[close].offset)
+        }));
+        if let Some((open, close)) = scope{
+            let (open_row, _) = text_buffer.offset_to_row_col(open);
+            let (close_row, _) = text_buffer.offset_to_row_col(close);
+            if close_row > open_row + 1{
+                let tabs = Folds::line_indent(text_buffer, open_row).unwrap_or(0) >> 2;
+                self._active_scope = Some((open_row + 1, close_row, tabs));
+            }
+        }
+    }
+
+    // empty lines take their indent guides from the line above, which we might not have drawn
+    fn indent_tabs_above(text_buffer:&TextBuffer, row:usize)->usize{
+        for row in (row.max(1000) - 1000..row).rev(){
+            if let Some(indent) = Folds::line_indent(text_buffer, row){
+                return indent >> 2
+            }
+        }
+        0
+    }
+
+    pub fn token_color(&self, token_type:TokenType)->Color{
+        match token_type{
+            TokenType::Whitespace | TokenType::Newline | TokenType::Eof=>self.col_whitespace,
+            TokenType::Keyword=>self.col_keyword,
+            TokenType::Flow=>self.col_flow_keyword,
+            TokenType::Identifier=>self.col_identifier,
+            TokenType::Call=>self.col_function,
+            TokenType::TypeName=>self.col_type,
+            TokenType::String=>self.col_string,
+            TokenType::Number=>self.col_number,
+            TokenType::Comment=>self.col_comment,
+            TokenType::DocComment=>self.col_doc_comment,
+            TokenType::Paren=>self.col_paren,
+            TokenType::Operator=>self.col_operator,
+            TokenType::Delimiter=>self.col_delim
+        }
+    }
+
     pub fn end_code_editor(&mut self, cx:&mut Cx, text_buffer:&TextBuffer){
         // lets insert an empty newline at the bottom so its nicer to scroll
         cx.turtle_new_line();
         cx.walk_turtle(Bounds::Fix(0.0),  Bounds::Fix(self._monospace_size.y),  Margin::zero(), None);
         
         self.text.end_text(cx, self._text_inst.as_ref().unwrap());
+
+        // a selection set from outside is scrolled to once we know where the lines are
+        if self._scroll_to_cursor{
+            self._scroll_to_cursor = false;
+            self.scroll_last_cursor_visible(cx, text_buffer);
+        }
+
         // lets draw cursors and selection rects.
         let draw_cursor = &self._draw_cursor;
         let pos = cx.turtle_origin();
         cx.new_instance_layer(self.cursor.shader_id, 0);
 
         // draw the cursors    
+        // vim normal mode draws a block cursor's underline on the char it is on
+        let underline = match &self.vim{
+            Some(vim)=>vim.mode == VimMode::Normal && vim.command_line.is_none(),
+            None=>false
+        };
         for rc in &draw_cursor.cursors{
-           self.cursor.draw_quad(cx, Rect{x:rc.x - pos.x, y:rc.y - pos.y, w:rc.w, h:rc.h});
+            if underline{
+                self.cursor.draw_quad(cx, Rect{x:rc.x - pos.x, y:rc.y - pos.y + rc.h - 2., w:self._monospace_size.x, h:2.});
+            }
+            else{
+                self.cursor.draw_quad(cx, Rect{x:rc.x - pos.x, y:rc.y - pos.y, w:rc.w, h:rc.h});
+            }
         }
 
         
         self._text_area = self._text_inst.take().unwrap().inst.into_area();
 
-        // draw selections
-        let sel = &draw_cursor.selections;
-        for i in 0..sel.len(){
-            let cur = &sel[i];
-            let mk_inst = self.marker.draw_quad(cx, Rect{x:cur.rc.x - pos.x, y:cur.rc.y - pos.y, w:cur.rc.w, h:cur.rc.h});
-            // do we have a prev?
-            if i > 0 && sel[i-1].index == cur.index{
-                let p_rc = &sel[i-1].rc;
-                mk_inst.push_vec2(cx, Vec2{x:p_rc.x - cur.rc.x, y:p_rc.w}); // prev_x, prev_w
-            }
-            else{
-                mk_inst.push_vec2(cx, Vec2{x:0., y:-1.}); // prev_x, prev_w
-            }
-            // do we have a next
-            if i < sel.len() - 1 && sel[i+1].index == cur.index{
-                let n_rc = &sel[i+1].rc;
-                mk_inst.push_vec2(cx, Vec2{x:n_rc.x - cur.rc.x, y:n_rc.w}); // prev_x, prev_w
-            }
-            else{
-                mk_inst.push_vec2(cx, Vec2{x:0., y:-1.}); // prev_x, prev_w
-            }
-        }
+        // draw search matches and the bracket pair, and the selections over them
+        let selection_color = self.marker.color;
+        self.marker.color = self.search_marker_color;
+        Self::draw_markers(cx, &mut self.marker, &self._draw_search.selections, pos);
+        self.marker.color = self.bracket_marker_color;
+        Self::draw_markers(cx, &mut self.marker, &self._draw_brackets.selections, pos);
+        self.marker.color = selection_color;
+        Self::draw_markers(cx, &mut self.marker, &draw_cursor.selections, pos);
 
         // do select scrolling
-        if let Some(select_scroll) = &self._select_scroll{
-            let offset = self.text.find_closest_offset(cx, &self._text_area, select_scroll.abs);
-            self.cursors.set_last_cursor_head(offset, text_buffer);
+        if let Some(select_scroll) = self._select_scroll.clone(){
+            self.drag_to_abs(cx, text_buffer, select_scroll.abs);
             if self.view.set_scroll_pos(cx, Vec2{
                 x:self._scroll_pos.x + select_scroll.delta.x,
                 y:self._scroll_pos.y + select_scroll.delta.y
@@ -503,9 +1523,20 @@ impl CodeEditor{
             }
         }
 
+        if self.minimap.is_some(){
+            self.draw_minimap(cx, text_buffer);
+        }
+        if self.find_bar.is_open{
+            self.find_bar.draw_find_bar(cx, &self.cursors, self._scroll_pos);
+        }
+        if let Some(vim) = &mut self.vim{
+            vim.draw_vim_status(cx, self._scroll_pos);
+        }
+
         self.view.end_view(cx);
 
         // place the IME
+        let draw_cursor = &self._draw_cursor;
         if self._bg_area == cx.key_focus{
             if let Some(last_cursor) = draw_cursor.last_cursor{
                 let rc = draw_cursor.cursors[last_cursor];
@@ -518,6 +1549,29 @@ impl CodeEditor{
         }
     }
 
+    fn draw_markers(cx:&mut Cx, marker:&mut Quad, sel:&Vec<DrawSel>, pos:Vec2){
+        for i in 0..sel.len(){
+            let cur = &sel[i];
+            let mk_inst = marker.draw_quad(cx, Rect{x:cur.rc.x - pos.x, y:cur.rc.y - pos.y, w:cur.rc.w, h:cur.rc.h});
+            // do we have a prev?
+            if i > 0 && sel[i-1].index == cur.index{
+                let p_rc = &sel[i-1].rc;
+                mk_inst.push_vec2(cx, Vec2{x:p_rc.x - cur.rc.x, y:p_rc.w}); // prev_x, prev_w
+            }
+            else{
+                mk_inst.push_vec2(cx, Vec2{x:0., y:-1.}); // prev_x, prev_w
+            }
+            // do we have a next
+            if i < sel.len() - 1 && sel[i+1].index == cur.index{
+                let n_rc = &sel[i+1].rc;
+                mk_inst.push_vec2(cx, Vec2{x:n_rc.x - cur.rc.x, y:n_rc.w}); // prev_x, prev_w
+            }
+            else{
+                mk_inst.push_vec2(cx, Vec2{x:0., y:-1.}); // prev_x, prev_w
+            }
+        }
+    }
+
     pub fn draw_tab_lines(&mut self, cx:&mut Cx, tabs:usize){
         let walk = cx.get_turtle_walk();
         let tab_width = self._monospace_size.x*4.;
@@ -526,9 +1580,18 @@ impl CodeEditor{
             self._visibility_margin, 
             self._scroll_pos,
         ){
-            for _i in 0..tabs{
+            // the guide of the block the cursor is in is lit
+            let row = self._line_geometry.len();
+            let active_tab = match self._active_scope{
+                Some((first_row, end_row, tab)) if row >= first_row && row < end_row=>Some(tab),
+                _=>None
+            };
+            let tab_color = self.tab.color;
+            for i in 0..tabs{
+                self.tab.color = if Some(i) == active_tab{self.tab_active_color}else{tab_color};
                 self.tab.draw_quad_walk(cx, Bounds::Fix(tab_width), Bounds::Fix(self._monospace_size.y), Margin::zero());
             }   
+            self.tab.color = tab_color;
             cx.set_turtle_walk(walk);
         }
     }
@@ -539,14 +1602,75 @@ impl CodeEditor{
         self._monospace_size = self.text.get_monospace_size(cx, None);
     }
 
-    pub fn new_line(&mut self, cx:&mut Cx){
-        // line geometry is used for scrolling look up of cursors
+    fn begin_row(&mut self, text_buffer:&TextBuffer, row:usize){
+        self._row_breaks.truncate(0);
+        self._row_break = 0;
+        self._row_col = 0;
+        self._row_indent = 0;
+        if self.is_wrapping(text_buffer){
+            self._row_breaks.extend_from_slice(&self._wrap_layout.breaks[row]);
+            self._row_indent = self._wrap_layout.indents[row];
+        }
+    }
+
+    // draws a chunk, continuing on the next line on screen where a wrapped line breaks
+    fn draw_wrapped_text(&mut self, cx:&mut Cx, chunk:&[char], end_offset:usize, color:Color){
+        let chunk_offset = end_offset - chunk.len() - 1;
+        let mut start = 0;
+        while start < chunk.len(){
+            let mut end = chunk.len();
+            if self._row_break < self._row_breaks.len(){
+                let brk = self._row_breaks[self._row_break];
+                if brk == self._row_col{
+                    self._row_break += 1;
+                    self.wrap_line(cx);
+                    continue
+                }
+                if brk < self._row_col + end - start{
+                    end = start + brk - self._row_col;
+                }
+            }
+            self.draw_text(cx, &chunk[start..end], chunk_offset + end + 1, color);
+            self._row_col += end - start;
+            start = end;
+        }
+    }
+
+    // continues a wrapped line on the next line on screen, indented like the line
+    fn wrap_line(&mut self, cx:&mut Cx){
+        cx.turtle_new_line();
+        self._first_on_line = true;
+        self._row_wraps += 1;
+        self.emit_line_selections();
+        cx.walk_turtle(
+            Bounds::Fix(self._gutter_width + self._monospace_size.x * self._row_indent as f32),
+            Bounds::Fix(self._monospace_size.y),
+            Margin::zero(),
+            None
+        );
+    }
+
+    // a folded line keeps its place in the line geometry but takes no space
+    pub fn hide_line(&mut self, cx:&mut Cx){
         self._line_geometry.push(
             LineGeom{
                 walk:cx.get_rel_turtle_walk(),
                 font_size:self.text.font_size
             }
         );
+    }
+
+    pub fn new_line(&mut self, cx:&mut Cx){
+        // line geometry is used for scrolling look up of cursors, it holds the top of the first line of a wrapped one
+        let mut walk = cx.get_rel_turtle_walk();
+        walk.y -= self._monospace_size.y * self._row_wraps as f32;
+        self._row_wraps = 0;
+        self._line_geometry.push(
+            LineGeom{
+                walk:walk,
+                font_size:self.text.font_size
+            }
+        );
         // add a bit of room to the right
         cx.walk_turtle(
             Bounds::Fix(self._monospace_size.x * 3.), 
@@ -556,14 +1680,27 @@ impl CodeEditor{
         );
         cx.turtle_new_line();
         self._first_on_line = true;
+        self.emit_line_selections();
+    }
+
+    // selections continue on the next line as a new rect
+    fn emit_line_selections(&mut self){
         let mut draw_cursor = &mut self._draw_cursor;
         if !draw_cursor.first{ // we have some selection data to emit
            draw_cursor.emit_selection(true);
            draw_cursor.first = true;
         }
+        let draw_search = &mut self._draw_search;
+        if !draw_search.first{
+           draw_search.emit_selection(true);
+        }
+        let draw_brackets = &mut self._draw_brackets;
+        if !draw_brackets.first{
+           draw_brackets.emit_selection(true);
+        }
     }
 
-    pub fn draw_text(&mut self, cx:&mut Cx, chunk:&Vec<char>, end_offset:usize, color:Color){
+    pub fn draw_text(&mut self, cx:&mut Cx, chunk:&[char], end_offset:usize, color:Color){
         if chunk.len()>0{
             let geom = cx.walk_turtle(
                 Bounds::Fix(self._monospace_size.x * (chunk.len() as f32)), 
@@ -585,9 +1722,16 @@ impl CodeEditor{
                 let cursors = &self.cursors.set;
                 let last_cursor = self.cursors.last_cursor;
                 let draw_cursor = &mut self._draw_cursor;
+                let match_cursors = &self.find_bar._match_cursors;
+                let draw_search = &mut self._draw_search;
+                let bracket_cursors = &self._bracket_cursors;
+                let draw_brackets = &mut self._draw_brackets;
                 let height = self._monospace_size.y;
 
                 self.text.add_text(cx, geom.x, geom.y, end_offset - chunk.len() - 1, self._text_inst.as_mut().unwrap(), &chunk, |unicode, offset, x, w|{
+                    draw_search.process_markers(match_cursors, offset, x, geom.y, w, height);
+                    draw_brackets.process_markers(bracket_cursors, offset, x, geom.y, w, height);
+
                     // check if we need to skip cursors
                     while offset > draw_cursor.end{ // jump to next cursor
                         if !draw_cursor.set_next(cursors){ // cant go further
@@ -616,6 +1760,22 @@ impl CodeEditor{
         }
     }
 
+    // selects a range, for instance a search result, and scrolls it into view on the next draw
+    pub fn select_range(&mut self, cx:&mut Cx, start:usize, end:usize, text_buffer:&TextBuffer){
+        let char_count = text_buffer.get_char_count();
+        let mut cursor = Cursor{
+            head:end.min(char_count),
+            tail:start.min(char_count),
+            max:0
+        };
+        cursor.calc_max(text_buffer);
+        self.cursors.set = vec![cursor];
+        self.cursors.last_cursor = 0;
+        self._scroll_to_cursor = true;
+        cx.set_key_focus(self._bg_area);
+        self.view.redraw_view_area(cx);
+    }
+
     fn scroll_last_cursor_visible(&mut self, cx:&mut Cx, text_buffer:&TextBuffer){
         // so we have to compute (approximately) the rect of our cursor
         if self.cursors.last_cursor >= self.cursors.set.len(){
@@ -627,9 +1787,16 @@ impl CodeEditor{
         if row < self._line_geometry.len(){
             let geom = &self._line_geometry[row];
             let mono_size = self.text.get_monospace_size(cx, Some(geom.font_size));
+            let (sub, x_col) = if self.is_wrapping(text_buffer){
+                let (visual, x_col) = self._wrap_layout.pos_of(row, col);
+                (visual - self._wrap_layout.visual_starts[row], x_col)
+            }
+            else{
+                (0, col)
+            };
             let rect = Rect{
-                x:(col as f32) * mono_size.x,
-                y:geom.walk.y - mono_size.y * 1.,
+                x:self._gutter_width + (x_col as f32) * mono_size.x,
+                y:geom.walk.y + mono_size.y * (sub as f32 - 1.),
                 w:mono_size.x * 4.,
                 h:mono_size.y * 3.
             };
@@ -730,6 +1897,21 @@ impl DrawCursor{
         }
     }
 
+    // search matches and the like are tracked like cursors, only their selection rects are used
+    pub fn process_markers(&mut self, markers:&Vec<Cursor>, offset:usize, x:f32, y:f32, w:f32, h:f32){
+        while offset > self.end && self.set_next(markers){}
+        if self.next_index > 0 && offset >= self.start && offset <= self.end{
+            self.process_geom(usize::max_value(), offset, x, y, w, h);
+            if offset == self.end{
+                self.emit_selection(false);
+                // the next one can start where this one ends, like the brackets of ()
+                if self.set_next(markers) && offset == self.start{
+                    self.process_geom(usize::max_value(), offset, x, y, w, h);
+                }
+            }
+        }
+    }
+
     pub fn process_geom(&mut self, last_cursor:usize, offset:usize, x:f32, y:f32, w:f32, h:f32){
         if offset == self.head{ // emit a cursor
             if self.next_index - 1 == last_cursor{
@@ -0,0 +1,294 @@
+use widgets::*;
+use crate::textbuffer::*;
+use crate::textsearch::*;
+
+// The find/replace bar at the top of a code editor. It is keyboard driven: while it has the
+// focus, typed text goes into the find or replace field instead of the buffer.
+#[derive(Clone)]
+pub struct FindBar{
+    pub bg:Quad,
+    pub text:Text,
+    pub is_open:bool,
+    pub has_focus:bool,
+    pub show_replace:bool,
+    pub field:FindBarField,
+    pub find:String,
+    pub replace:String,
+    pub options:SearchOptions,
+    pub search:Option<TextSearch>,
+    pub matches:Vec<SearchMatch>,
+    pub error:Option<String>,
+    // the matches as cursors, so the editor can draw them like selections
+    pub _match_cursors:Vec<Cursor>,
+    pub _searched:Option<(String, SearchOptions, u64)>
+}
+
+#[derive(Clone, PartialEq)]
+pub enum FindBarField{
+    Find,
+    Replace
+}
+
+impl Style for FindBar{
+    fn style(cx:&mut Cx)->Self{
+        Self{
+            bg:Quad{
+                color:color256(45,45,45),
+                ..Style::style(cx)
+            },
+            text:Text{
+                font_id:cx.load_font(&cx.font("mono_font")),
+                font_size:11.0,
+                brightness:1.05,
+                line_spacing:1.4,
+                wrapping:Wrapping::None,
+                ..Style::style(cx)
+            },
+            is_open:false,
+            has_focus:false,
+            show_replace:false,
+            field:FindBarField::Find,
+            find:String::new(),
+            replace:String::new(),
+            options:SearchOptions::default(),
+            search:None,
+            matches:Vec::new(),
+            error:None,
+            _match_cursors:Vec::new(),
+            _searched:None
+        }
+    }
+}
+
+impl FindBar{
+    pub fn open(&mut self, show_replace:bool, cursors:&CursorSet, text_buffer:&TextBuffer){
+        self.is_open = true;
+        self.has_focus = true;
+        self.show_replace = self.show_replace || show_replace;
+        self.field = FindBarField::Find;
+        // search for the selection, if it is on one line
+        let selection = cursors.get_last_cursor_as_string(text_buffer);
+        if selection.len() > 0 && !selection.contains('\n'){
+            self.find = selection;
+        }
+    }
+
+    pub fn close(&mut self){
+        self.is_open = false;
+        self.has_focus = false;
+        self.search = None;
+        self.matches.truncate(0);
+        self._match_cursors.truncate(0);
+        self._searched = None;
+    }
+
+    // reruns the search when the query or the text changed
+    pub fn update_matches(&mut self, text_buffer:&TextBuffer){
+        if !self.is_open{
+            return
+        }
+        let key = (self.find.clone(), self.options.clone(), text_buffer.change_id);
+        if self._searched.as_ref() == Some(&key){
+            return
+        }
+        self._searched = Some(key);
+        self.error = None;
+        self.search = None;
+        self.matches.truncate(0);
+        if self.find.len() > 0{
+            match TextSearch::new(&self.find, &self.options){
+                Ok(search)=>{
+                    self.matches = search.find_in_text_buffer(text_buffer);
+                    self.search = Some(search);
+                },
+                Err(err)=>self.error = Some(err)
+            }
+        }
+        self._match_cursors = self.matches.iter().map(|search_match| Cursor{
+            head:search_match.end,
+            tail:search_match.start,
+            max:0
+        }).collect();
+    }
+
+    // the match the last cursor has selected
+    pub fn current_match(&self, cursors:&CursorSet)->Option<usize>{
+        let (start, end) = cursors.set[cursors.last_cursor].order();
+        self.matches.iter().position(|search_match| search_match.start == start && search_match.end == end)
+    }
+
+    pub fn select_next(&mut self, backward:bool, cursors:&mut CursorSet, text_buffer:&TextBuffer)->bool{
+        self.update_matches(text_buffer);
+        if self.matches.len() == 0{
+            return false
+        }
+        let (start, end) = cursors.set[cursors.last_cursor].order();
+        let index = if backward{
+            self.matches.iter().rposition(|m| m.end <= start && (m.start, m.end) != (start, end)).unwrap_or(self.matches.len() - 1)
+        }
+        else{
+            self.matches.iter().position(|m| m.start >= end && (m.start, m.end) != (start, end)).unwrap_or(0)
+        };
+        let search_match = &self.matches[index];
+        cursors.set.truncate(0);
+        cursors.last_cursor = 0;
+        let mut cursor = Cursor{
+            head:search_match.end,
+            tail:search_match.start,
+            max:0
+        };
+        cursor.calc_max(text_buffer);
+        cursors.set.push(cursor);
+        true
+    }
+
+    // replaces the selected match and moves on to the next one
+    pub fn replace_next(&mut self, cursors:&mut CursorSet, text_buffer:&mut TextBuffer)->bool{
+        self.update_matches(text_buffer);
+        if let Some(index) = self.current_match(cursors){
+            if let Some(search) = &self.search{
+                let replace = search.expand_replace(&self.replace, &self.matches[index], &text_buffer.rope.to_vec());
+                cursors.set = vec![cursors.set[cursors.last_cursor].clone()];
+                cursors.last_cursor = 0;
+                cursors.replace_text(&replace, text_buffer);
+            }
+        }
+        self.select_next(false, cursors, text_buffer)
+    }
+
+    pub fn replace_all(&mut self, cursors:&mut CursorSet, text_buffer:&mut TextBuffer)->bool{
+        self.update_matches(text_buffer);
+        if self.matches.len() == 0{
+            return false
+        }
+        if let Some(search) = &self.search{
+            let chars = text_buffer.rope.to_vec();
+            let replaces:Vec<String> = self.matches.iter().map(|search_match| search.expand_replace(&self.replace, search_match, &chars)).collect();
+            cursors.select_matches(&self.matches, text_buffer);
+            cursors.replace_texts(&replaces, text_buffer);
+        }
+        true
+    }
+
+    pub fn select_all_matches(&mut self, cursors:&mut CursorSet, text_buffer:&TextBuffer)->bool{
+        self.update_matches(text_buffer);
+        if self.matches.len() == 0{
+            return false
+        }
+        cursors.select_matches(&self.matches, text_buffer);
+        true
+    }
+
+    // returns None if the key is not for us, otherwise if the cursors moved
+    pub fn handle_key_down(&mut self, ke:&KeyEvent, cursors:&mut CursorSet, text_buffer:&mut TextBuffer)->Option<bool>{
+        let command = ke.modifiers.logo || ke.modifiers.control;
+        if command && ke.modifiers.alt{ // option toggles
+            match ke.key_code{
+                KeyCode::KeyC=>self.options.ignore_case = !self.options.ignore_case,
+                KeyCode::KeyW=>self.options.whole_word = !self.options.whole_word,
+                KeyCode::KeyR=>self.options.regex = !self.options.regex,
+                KeyCode::Return=>return Some(self.replace_all(cursors, text_buffer)),
+                _=>return None
+            }
+            return Some(false)
+        }
+        match ke.key_code{
+            KeyCode::Escape=>{
+                self.close();
+                Some(false)
+            },
+            KeyCode::Tab=>{
+                self.show_replace = true;
+                self.field = if self.field == FindBarField::Find{FindBarField::Replace}else{FindBarField::Find};
+                Some(false)
+            },
+            KeyCode::Backspace=>{
+                match self.field{
+                    FindBarField::Find=>self.find.pop(),
+                    FindBarField::Replace=>self.replace.pop()
+                };
+                Some(false)
+            },
+            KeyCode::Return=>{
+                if ke.modifiers.alt{ // all matches become cursors
+                    let moved = self.select_all_matches(cursors, text_buffer);
+                    self.has_focus = false;
+                    Some(moved)
+                }
+                else if self.field == FindBarField::Replace{
+                    Some(self.replace_next(cursors, text_buffer))
+                }
+                else{
+                    Some(self.select_next(ke.modifiers.shift, cursors, text_buffer))
+                }
+            },
+            _=>None
+        }
+    }
+
+    pub fn handle_text_input(&mut self, input:&str){
+        let input:String = input.chars().filter(|ch| !ch.is_control()).collect();
+        match self.field{
+            FindBarField::Find=>self.find.push_str(&input),
+            FindBarField::Replace=>self.replace.push_str(&input)
+        }
+    }
+
+    // draws the bar over the top of the view, at its current scroll position
+    pub fn draw_find_bar(&mut self, cx:&mut Cx, cursors:&CursorSet, scroll_pos:Vec2){
+        let origin = cx.turtle_origin();
+        let width = cx.width_total(false);
+        let mono_size = self.text.get_monospace_size(cx, None);
+        let lines = if self.show_replace{2.}else{1.};
+
+        cx.new_instance_layer(self.bg.shader_id, 0);
+        self.bg.draw_quad(cx, Rect{x:scroll_pos.x, y:scroll_pos.y, w:width, h:mono_size.y * lines + 8.});
+
+        cx.new_instance_layer(self.text.shader_id, 0);
+        let mut inst = self.text.begin_text(cx);
+        let x = origin.x + scroll_pos.x + 6.;
+        let mut y = origin.y + scroll_pos.y + 4.;
+
+        let find_color = if self.has_focus && self.field == FindBarField::Find{color("#f")}else{color("#a")};
+        let mut fx = self.draw_label(cx, &mut inst, x, y, "Find    ", color("#8"));
+        fx = self.draw_label(cx, &mut inst, fx, y, &format!("{}{} ", self.find, if self.has_focus && self.field == FindBarField::Find{"|"}else{""}), find_color);
+        let status = if let Some(error) = &self.error{
+            error.clone()
+        }
+        else if self.find.len() == 0{
+            "".to_string()
+        }
+        else if self.matches.len() == 0{
+            "No results".to_string()
+        }
+        else if let Some(index) = self.current_match(cursors){
+            format!("{} of {}", index + 1, self.matches.len())
+        }
+        else{
+            format!("{} matches", self.matches.len())
+        };
+        fx = self.draw_label(cx, &mut inst, fx + mono_size.x, y, &status, if self.error.is_some(){color("#c66")}else{color("#8")});
+
+        // the option toggles, lit when on
+        let option_color = |on:bool|{if on{color("#f")}else{color("#5")}};
+        fx = self.draw_label(cx, &mut inst, fx + mono_size.x * 2., y, "Aa ", option_color(self.options.ignore_case));
+        fx = self.draw_label(cx, &mut inst, fx, y, "W ", option_color(self.options.whole_word));
+        self.draw_label(cx, &mut inst, fx, y, ".*", option_color(self.options.regex));
+
+        if self.show_replace{
+            y += mono_size.y;
+            let replace_color = if self.has_focus && self.field == FindBarField::Replace{color("#f")}else{color("#a")};
+            let rx = self.draw_label(cx, &mut inst, x, y, "Replace ", color("#8"));
+            self.draw_label(cx, &mut inst, rx, y, &format!("{}{}", self.replace, if self.has_focus && self.field == FindBarField::Replace{"|"}else{""}), replace_color);
+        }
+        self.text.end_text(cx, &inst);
+    }
+
+    fn draw_label(&mut self, cx:&mut Cx, inst:&mut AlignedInstance, x:f32, y:f32, label:&str, color:Color)->f32{
+        let chunk:Vec<char> = label.chars().collect();
+        let mono_size = self.text.get_monospace_size(cx, None);
+        self.text.color = color;
+        self.text.add_text(cx, x, y, 0, inst, &chunk, |_,_,_,_|{0.0});
+        x + mono_size.x * chunk.len() as f32
+    }
+}
@@ -0,0 +1,268 @@
+use crate::textbuffer::*;
+
+// The blocks of a buffer that can fold, and the ones that are folded. A fold hides the rows
+// after its start row up to and including its end row, the start row stays visible.
+#[derive(Clone, Default)]
+pub struct Folds{
+    // sorted on start_row, one per start row
+    pub regions:Vec<FoldRegion>,
+    // the change_id of the text the regions were found for
+    pub built_for:Option<u64>,
+    // sorted on start_row, folds can be inside other folds
+    pub folded:Vec<FoldRegion>,
+    // the folded rows as merged (first, last) ranges
+    pub _hidden:Vec<(usize, usize)>
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FoldRegion{
+    pub start_row:usize,
+    pub end_row:usize
+}
+
+impl Folds{
+    // a multi line bracket pair folds up to the row before its close bracket. rows that start a
+    // more indented block without one fold up to the last row of that block
+    pub fn find_regions(text_buffer:&TextBuffer)->Vec<FoldRegion>{
+        let line_count = text_buffer.get_line_count();
+        let mut ends:Vec<Option<usize>> = vec![None; line_count];
+        let bracket_index = &text_buffer.bracket_index;
+        for bracket in &bracket_index.brackets{
+            if let (true, Some(close)) = (bracket.is_open, bracket.partner){
+                let open_row = text_buffer.rope.offset_to_row(bracket.offset);
+                let close_row = text_buffer.rope.offset_to_row(bracket_index.brackets[close].offset);
+                if close_row > open_row + 1{
+                    ends[open_row] = Some(ends[open_row].unwrap_or(0).max(close_row - 1));
+                }
+            }
+        }
+        // the rows still open at an indent, closed by the first row that isn't indented more
+        let mut open:Vec<(usize, usize)> = Vec::new();
+        let mut last_row = 0;
+        for row in 0..line_count{
+            let indent = if let Some(indent) = Self::line_indent(text_buffer, row){indent}else{continue};
+            while let Some((start_row, start_indent)) = open.last().cloned(){
+                if start_indent < indent{
+                    break
+                }
+                open.pop();
+                if last_row > start_row && ends[start_row].is_none(){
+                    ends[start_row] = Some(last_row);
+                }
+            }
+            open.push((row, indent));
+            last_row = row;
+        }
+        for (start_row, _) in open{
+            if last_row > start_row && ends[start_row].is_none(){
+                ends[start_row] = Some(last_row);
+            }
+        }
+        ends.into_iter().enumerate().filter_map(|(start_row, end_row)|{
+            end_row.map(|end_row| FoldRegion{start_row:start_row, end_row:end_row})
+        }).collect()
+    }
+
+    // the leading whitespace of a row, None if it has nothing else
+    pub fn line_indent(text_buffer:&TextBuffer, row:usize)->Option<usize>{
+        let mut indent = 0;
+        for ch in text_buffer.line_chars(row){
+            if ch != ' ' && ch != '\t'{
+                return Some(indent)
+            }
+            indent += 1;
+        }
+        None
+    }
+
+    pub fn is_current(&self, text_buffer:&TextBuffer)->bool{
+        self.built_for == Some(text_buffer.change_id)
+    }
+
+    pub fn set_regions(&mut self, regions:Vec<FoldRegion>, change_id:u64){
+        self.regions = regions;
+        self.built_for = Some(change_id);
+    }
+
+    pub fn clear(&mut self){
+        self.regions.truncate(0);
+        self.built_for = None;
+        self.folded.truncate(0);
+        self._hidden.truncate(0);
+    }
+
+    pub fn region_at(&self, row:usize)->Option<FoldRegion>{
+        self.regions.binary_search_by_key(&row, |region| region.start_row).ok().map(|index| self.regions[index])
+    }
+
+    // the innermost region the row is in or starts that isn't folded yet
+    pub fn region_around(&self, row:usize)->Option<FoldRegion>{
+        let end = match self.regions.binary_search_by_key(&row, |region| region.start_row){
+            Ok(index)=>index + 1,
+            Err(index)=>index
+        };
+        self.regions[0..end].iter().rev().find(|region| region.end_row >= row && !self.is_folded(region.start_row)).cloned()
+    }
+
+    pub fn is_folded(&self, row:usize)->bool{
+        self.folded.iter().any(|fold| fold.start_row == row)
+    }
+
+    pub fn fold(&mut self, region:FoldRegion){
+        if !self.is_folded(region.start_row){
+            let index = self.folded.iter().position(|fold| fold.start_row > region.start_row).unwrap_or(self.folded.len());
+            self.folded.insert(index, region);
+            self.update_hidden();
+        }
+    }
+
+    pub fn unfold(&mut self, row:usize){
+        self.folded.retain(|fold| fold.start_row != row);
+        self.update_hidden();
+    }
+
+    // returns false if there is nothing to fold or unfold at row
+    pub fn toggle(&mut self, row:usize)->bool{
+        if self.is_folded(row){
+            self.unfold(row);
+            true
+        }
+        else if let Some(region) = self.region_at(row){
+            self.fold(region);
+            true
+        }
+        else{
+            false
+        }
+    }
+
+    pub fn fold_all(&mut self){
+        self.folded = self.regions.clone();
+        self.update_hidden();
+    }
+
+    pub fn unfold_all(&mut self){
+        self.folded.truncate(0);
+        self.update_hidden();
+    }
+
+    // opens all folds that hide the row
+    pub fn reveal_row(&mut self, row:usize)->bool{
+        if !self.is_hidden(row){
+            return false
+        }
+        self.folded.retain(|fold| row <= fold.start_row || row > fold.end_row);
+        self.update_hidden();
+        true
+    }
+
+    // an edit on row replaced removed_rows line breaks with inserted_rows new ones. folds below it
+    // move along, an edit that changes the rows of a fold opens it
+    pub fn invalidate(&mut self, row:usize, removed_rows:usize, inserted_rows:usize){
+        if removed_rows == 0 && inserted_rows == 0 || self.folded.len() == 0{
+            return
+        }
+        self.folded = self.folded.iter().filter_map(|fold|{
+            if row > fold.end_row{
+                Some(*fold)
+            }
+            else if row + removed_rows < fold.start_row{
+                Some(FoldRegion{
+                    start_row:fold.start_row + inserted_rows - removed_rows,
+                    end_row:fold.end_row + inserted_rows - removed_rows
+                })
+            }
+            else{
+                None
+            }
+        }).collect();
+        self.update_hidden();
+    }
+
+    fn update_hidden(&mut self){
+        self._hidden.truncate(0);
+        for fold in &self.folded{
+            let (first, last) = (fold.start_row + 1, fold.end_row);
+            if let Some(span) = self._hidden.last_mut(){
+                if first <= span.1 + 1{
+                    span.1 = span.1.max(last);
+                    continue
+                }
+            }
+            self._hidden.push((first, last));
+        }
+    }
+
+    // the last row of the folded rows row is in
+    pub fn hidden_span_end(&self, row:usize)->Option<usize>{
+        let index = match self._hidden.binary_search_by_key(&row, |span| span.0){
+            Ok(index)=>index,
+            Err(0)=>return None,
+            Err(index)=>index - 1
+        };
+        let (_, last) = self._hidden[index];
+        if row <= last{Some(last)}else{None}
+    }
+
+    pub fn is_hidden(&self, row:usize)->bool{
+        self.hidden_span_end(row).is_some()
+    }
+
+    // the index of the line on screen a visible row is on
+    pub fn row_to_visual(&self, row:usize)->usize{
+        let mut visual = row;
+        for (first, last) in &self._hidden{
+            if *first > row{
+                break
+            }
+            visual -= last.min(&row) - first + 1;
+        }
+        visual
+    }
+
+    // maps the index of a line on screen to its row
+    pub fn visual_to_row(&self, visual:usize)->usize{
+        let mut row = visual;
+        for (first, last) in &self._hidden{
+            if *first > row{
+                break
+            }
+            row += last - first + 1;
+        }
+        row
+    }
+
+    // the row count lines on screen below row, None if that is past the last one
+    pub fn visible_row_below(&self, row:usize, count:usize, line_count:usize)->Option<usize>{
+        let mut row = row;
+        for _ in 0..count{
+            row += 1;
+            if let Some(last) = self.hidden_span_end(row){
+                row = last + 1;
+            }
+            if row >= line_count{
+                return None
+            }
+        }
+        Some(row)
+    }
+
+    // the row count lines on screen above row, None if that is before the first one
+    pub fn visible_row_above(&self, row:usize, count:usize)->Option<usize>{
+        let mut row = row;
+        for _ in 0..count{
+            if row == 0{
+                return None
+            }
+            row -= 1;
+            if self.is_hidden(row){ // the start row of the fold is the first visible one
+                let index = match self._hidden.binary_search_by_key(&row, |span| span.0){
+                    Ok(index)=>index,
+                    Err(index)=>index - 1
+                };
+                row = self._hidden[index].0 - 1;
+            }
+        }
+        Some(row)
+    }
+}
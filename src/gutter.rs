@@ -0,0 +1,222 @@
+use widgets::*;
+use crate::textbuffer::*;
+
+// The strip left of the text. It is drawn a row at a time as the editor lays out its lines,
+// so it covers exactly the rows on screen and moves with folds and wrapping. From left to
+// right it has a bar for version control changes, the line number, a diagnostic mark and the
+// fold toggle.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineNumbers{
+    Off,
+    Absolute,
+    // the distance to the row of the last cursor, that row itself shows its number
+    Relative
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DiagnosticLevel{
+    Error,
+    Warning,
+    Info
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum VcsChange{
+    Added,
+    Modified,
+    // lines went away above the row
+    Removed
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GutterSlot{
+    Vcs,
+    LineNumber,
+    Diagnostic,
+    Fold
+}
+
+#[derive(Clone)]
+pub struct Gutter{
+    pub bar:Quad,
+    pub line_numbers:LineNumbers,
+    pub col_line_number:Color,
+    pub col_line_number_active:Color,
+    pub col_fold:Color,
+    pub col_fold_open:Color,
+    pub col_error:Color,
+    pub col_warning:Color,
+    pub col_info:Color,
+    pub col_added:Color,
+    pub col_modified:Color,
+    pub col_removed:Color,
+    // set from outside, sorted on row
+    pub diagnostics:Vec<(usize, DiagnosticLevel)>,
+    // set from outside, sorted (first_row, last_row) ranges
+    pub vcs_changes:Vec<(usize, usize, VcsChange)>,
+    // where the slots start, and the total width
+    pub _number_x:f32,
+    pub _diagnostic_x:f32,
+    pub _fold_x:f32,
+    pub _width:f32,
+    pub _digits:usize,
+    pub _mono_size:Vec2,
+    // sorted rows with a cursor head on them
+    pub _cursor_rows:Vec<usize>,
+    pub _relative_row:usize
+}
+
+impl Style for Gutter{
+    fn style(cx:&mut Cx)->Self{
+        Self{
+            bar:Style::style(cx),
+            line_numbers:LineNumbers::Absolute,
+            col_line_number:color256(90,90,90),
+            col_line_number_active:color256(200,200,200),
+            col_fold:color256(160,160,160),
+            col_fold_open:color256(110,110,110),
+            col_error:color256(230,80,80),
+            col_warning:color256(220,180,60),
+            col_info:color256(80,150,220),
+            col_added:color256(90,170,90),
+            col_modified:color256(80,130,200),
+            col_removed:color256(200,80,80),
+            diagnostics:Vec::new(),
+            vcs_changes:Vec::new(),
+            _number_x:0.,
+            _diagnostic_x:0.,
+            _fold_x:0.,
+            _width:0.,
+            _digits:0,
+            _mono_size:Vec2::zero(),
+            _cursor_rows:Vec::new(),
+            _relative_row:0
+        }
+    }
+}
+
+impl Gutter{
+    // lays out the slots for the line count, gives back the width
+    pub fn begin_gutter(&mut self, text_buffer:&TextBuffer, cursors:&CursorSet, mono_size:Vec2)->f32{
+        self._mono_size = mono_size;
+        self._digits = format!("{}", text_buffer.get_line_count()).len().max(2);
+        let number_width = if self.line_numbers == LineNumbers::Off{0.}else{mono_size.x * (self._digits + 1) as f32};
+        self._number_x = mono_size.x * 0.5;
+        self._diagnostic_x = self._number_x + number_width;
+        self._fold_x = self._diagnostic_x + mono_size.x;
+        self._width = self._fold_x + mono_size.x * 2.;
+
+        self._cursor_rows = cursors.set.iter().map(|cursor| text_buffer.offset_to_row_col(cursor.head).0).collect();
+        self._cursor_rows.sort();
+        self._cursor_rows.dedup();
+        let last_head = cursors.set[cursors.last_cursor].head;
+        self._relative_row = text_buffer.folds.row_to_visual(text_buffer.offset_to_row_col(last_head).0);
+        self._width
+    }
+
+    // the slot at x from the left of the gutter
+    pub fn slot_at(&self, x:f32)->GutterSlot{
+        if x >= self._fold_x{
+            GutterSlot::Fold
+        }
+        else if x >= self._diagnostic_x{
+            GutterSlot::Diagnostic
+        }
+        else if x >= self._number_x{
+            GutterSlot::LineNumber
+        }
+        else{
+            GutterSlot::Vcs
+        }
+    }
+
+    pub fn diagnostic_at(&self, row:usize)->Option<DiagnosticLevel>{
+        match self.diagnostics.binary_search_by_key(&row, |(row, _)| *row){
+            Ok(index)=>Some(self.diagnostics[index].1),
+            Err(_)=>None
+        }
+    }
+
+    pub fn level_color(&self, level:DiagnosticLevel)->Color{
+        match level{
+            DiagnosticLevel::Error=>self.col_error,
+            DiagnosticLevel::Warning=>self.col_warning,
+            DiagnosticLevel::Info=>self.col_info
+        }
+    }
+
+    pub fn vcs_change_at(&self, row:usize)->Option<VcsChange>{
+        let index = match self.vcs_changes.binary_search_by_key(&row, |(first, _, _)| *first){
+            Ok(index)=>index,
+            Err(0)=>return None,
+            Err(index)=>index - 1
+        };
+        let (_, last, change) = self.vcs_changes[index];
+        if row <= last{Some(change)}else{None}
+    }
+
+    // walks the gutter of a row, lines is how many lines on screen the row wraps over
+    pub fn draw_gutter_row(&mut self, cx:&mut Cx, text:&mut Text, text_inst:&mut AlignedInstance, text_buffer:&TextBuffer, row:usize, lines:usize, visibility_margin:Margin, scroll_pos:Vec2){
+        let mono_size = self._mono_size;
+        let geom = cx.walk_turtle(Bounds::Fix(self._width), Bounds::Fix(mono_size.y), Margin::zero(), None);
+        if !cx.visible_in_turtle(geom, visibility_margin, scroll_pos){
+            return
+        }
+        let origin = cx.turtle_origin();
+        let offset = text_buffer.rope.row_to_offset(row);
+
+        if let Some(change) = self.vcs_change_at(row){
+            let (color, rect) = match change{
+                VcsChange::Added=>(self.col_added, Rect{x:geom.x, y:geom.y, w:3., h:mono_size.y * lines as f32}),
+                VcsChange::Modified=>(self.col_modified, Rect{x:geom.x, y:geom.y, w:3., h:mono_size.y * lines as f32}),
+                VcsChange::Removed=>(self.col_removed, Rect{x:geom.x, y:geom.y - 1., w:self._number_x, h:2.})
+            };
+            self.bar.color = color;
+            self.bar.draw_quad(cx, Rect{x:rect.x - origin.x, y:rect.y - origin.y, ..rect});
+        }
+
+        if self.line_numbers != LineNumbers::Off{
+            let is_active = self._cursor_rows.binary_search(&row).is_ok();
+            let visual = text_buffer.folds.row_to_visual(row);
+            // the row relative numbers count from shows its own, left aligned like vim does
+            let chars:Vec<char> = if self.line_numbers == LineNumbers::Relative && visual != self._relative_row{
+                let distance = if visual > self._relative_row{visual - self._relative_row}else{self._relative_row - visual};
+                format!("{:>width$}", distance, width = self._digits)
+            }
+            else if self.line_numbers == LineNumbers::Relative{
+                format!("{:<width$}", row + 1, width = self._digits)
+            }
+            else{
+                format!("{:>width$}", row + 1, width = self._digits)
+            }.chars().collect();
+            text.color = if is_active{self.col_line_number_active}else{self.col_line_number};
+            text.add_text(cx, geom.x + self._number_x, geom.y, offset, text_inst, &chars, |_,_,_,_|{0.0});
+        }
+
+        if let Some(level) = self.diagnostic_at(row){
+            self.bar.color = self.level_color(level);
+            let size = mono_size.x * 0.6;
+            self.bar.draw_quad(cx, Rect{
+                x:geom.x + self._diagnostic_x + (mono_size.x - size) * 0.5 - origin.x,
+                y:geom.y + (mono_size.y - size) * 0.5 - origin.y,
+                w:size,
+                h:size
+            });
+        }
+
+        let toggle = if text_buffer.folds.is_folded(row){
+            Some(('+', self.col_fold))
+        }
+        else if text_buffer.folds.region_at(row).is_some(){
+            Some(('-', self.col_fold_open))
+        }
+        else{
+            None
+        };
+        if let Some((toggle, color)) = toggle{
+            text.color = color;
+            text.add_text(cx, geom.x + self._fold_x, geom.y, offset, text_inst, &[toggle], |_,_,_,_|{0.0});
+        }
+    }
+}
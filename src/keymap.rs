@@ -0,0 +1,481 @@
+use widgets::*;
+
+// Key bindings. A binding maps a sequence of key chords in a context to a named command, so
+// widgets dispatch commands instead of matching key codes. The defaults and the user file use
+// the same format, one binding per line, later lines win so the user file overrides defaults:
+//
+//   # context  keys          command
+//   editor     cmd-shift-k   delete_lines
+//   editor     cmd-k cmd-0   fold_all
+//   editor     ctrl-w        none
+//
+// cmd is the command key of the platform, logo on mac and control elsewhere. In a browser we
+// can't tell which one the user has, so logo counts as control there. none unbinds the keys.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyContext{
+    Editor,
+    FileTree,
+    Dock
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeymapPlatform{
+    Mac,
+    Web,
+    Other
+}
+
+impl KeymapPlatform{
+    pub fn current()->KeymapPlatform{
+        if cfg!(target_os = "macos"){
+            KeymapPlatform::Mac
+        }
+        else if cfg!(target_arch = "wasm32"){
+            KeymapPlatform::Web
+        }
+        else{
+            KeymapPlatform::Other
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyCommand{
+    None,
+    // editor
+    CursorUp,
+    CursorDown,
+    CursorLeft,
+    CursorRight,
+    WordLeft,
+    WordRight,
+    PageUp,
+    PageDown,
+    LineStart,
+    LineEnd,
+    SelectUp,
+    SelectDown,
+    SelectLeft,
+    SelectRight,
+    SelectWordLeft,
+    SelectWordRight,
+    SelectPageUp,
+    SelectPageDown,
+    SelectLineStart,
+    SelectLineEnd,
+    SelectAll,
+    Backspace,
+    Delete,
+    BackspaceWord,
+    DeleteWord,
+    Tab,
+    Indent,
+    Outdent,
+    AddCursorAbove,
+    AddCursorBelow,
+    NextOccurrence,
+    AllOccurrences,
+    SplitIntoLines,
+    MoveLinesUp,
+    MoveLinesDown,
+    DuplicateLines,
+    JoinLines,
+    DeleteLines,
+    ToggleLineComment,
+    ToggleBlockComment,
+    ToggleSoftWrap,
+    ToggleColumnWrap,
+    Undo,
+    Redo,
+    PrevUndoBranch,
+    NextUndoBranch,
+    UndoMinute,
+    RedoMinute,
+    Cut,
+    Save,
+    Find,
+    FindReplace,
+    FindNext,
+    FindPrev,
+    CloseFind,
+    JumpToBracket,
+    SelectEnclosingBlock,
+    Fold,
+    FoldAll,
+    Unfold,
+    UnfoldAll,
+    ToggleVim,
+    ToggleLineNumbers,
+    ToggleMinimap,
+    SplitEditor,
+    // file tree
+    OpenFile,
+    // dock
+    SaveAll,
+    SearchInFiles
+}
+
+const KEY_COMMAND_NAMES:&[(&str, KeyCommand)] = &[
+    ("none", KeyCommand::None),
+    ("cursor_up", KeyCommand::CursorUp),
+    ("cursor_down", KeyCommand::CursorDown),
+    ("cursor_left", KeyCommand::CursorLeft),
+    ("cursor_right", KeyCommand::CursorRight),
+    ("word_left", KeyCommand::WordLeft),
+    ("word_right", KeyCommand::WordRight),
+    ("page_up", KeyCommand::PageUp),
+    ("page_down", KeyCommand::PageDown),
+    ("line_start", KeyCommand::LineStart),
+    ("line_end", KeyCommand::LineEnd),
+    ("select_up", KeyCommand::SelectUp),
+    ("select_down", KeyCommand::SelectDown),
+    ("select_left", KeyCommand::SelectLeft),
+    ("select_right", KeyCommand::SelectRight),
+    ("select_word_left", KeyCommand::SelectWordLeft),
+    ("select_word_right", KeyCommand::SelectWordRight),
+    ("select_page_up", KeyCommand::SelectPageUp),
+    ("select_page_down", KeyCommand::SelectPageDown),
+    ("select_line_start", KeyCommand::SelectLineStart),
+    ("select_line_end", KeyCommand::SelectLineEnd),
+    ("select_all", KeyCommand::SelectAll),
+    ("backspace", KeyCommand::Backspace),
+    ("delete", KeyCommand::Delete),
+    ("backspace_word", KeyCommand::BackspaceWord),
+    ("delete_word", KeyCommand::DeleteWord),
+    ("tab", KeyCommand::Tab),
+    ("indent", KeyCommand::Indent),
+    ("outdent", KeyCommand::Outdent),
+    ("add_cursor_above", KeyCommand::AddCursorAbove),
+    ("add_cursor_below", KeyCommand::AddCursorBelow),
+    ("next_occurrence", KeyCommand::NextOccurrence),
+    ("all_occurrences", KeyCommand::AllOccurrences),
+    ("split_into_lines", KeyCommand::SplitIntoLines),
+    ("move_lines_up", KeyCommand::MoveLinesUp),
+    ("move_lines_down", KeyCommand::MoveLinesDown),
+    ("duplicate_lines", KeyCommand::DuplicateLines),
+    ("join_lines", KeyCommand::JoinLines),
+    ("delete_lines", KeyCommand::DeleteLines),
+    ("toggle_line_comment", KeyCommand::ToggleLineComment),
+    ("toggle_block_comment", KeyCommand::ToggleBlockComment),
+    ("toggle_soft_wrap", KeyCommand::ToggleSoftWrap),
+    ("toggle_column_wrap", KeyCommand::ToggleColumnWrap),
+    ("undo", KeyCommand::Undo),
+    ("redo", KeyCommand::Redo),
+    ("prev_undo_branch", KeyCommand::PrevUndoBranch),
+    ("next_undo_branch", KeyCommand::NextUndoBranch),
+    ("undo_minute", KeyCommand::UndoMinute),
+    ("redo_minute", KeyCommand::RedoMinute),
+    ("cut", KeyCommand::Cut),
+    ("save", KeyCommand::Save),
+    ("find", KeyCommand::Find),
+    ("find_replace", KeyCommand::FindReplace),
+    ("find_next", KeyCommand::FindNext),
+    ("find_prev", KeyCommand::FindPrev),
+    ("close_find", KeyCommand::CloseFind),
+    ("jump_to_bracket", KeyCommand::JumpToBracket),
+    ("select_enclosing_block", KeyCommand::SelectEnclosingBlock),
+    ("fold", KeyCommand::Fold),
+    ("fold_all", KeyCommand::FoldAll),
+    ("unfold", KeyCommand::Unfold),
+    ("unfold_all", KeyCommand::UnfoldAll),
+    ("toggle_vim", KeyCommand::ToggleVim),
+    ("toggle_line_numbers", KeyCommand::ToggleLineNumbers),
+    ("toggle_minimap", KeyCommand::ToggleMinimap),
+    ("split_editor", KeyCommand::SplitEditor),
+    ("open_file", KeyCommand::OpenFile),
+    ("save_all", KeyCommand::SaveAll),
+    ("search_in_files", KeyCommand::SearchInFiles)
+];
+
+impl KeyCommand{
+    pub fn from_name(name:&str)->Option<KeyCommand>{
+        KEY_COMMAND_NAMES.iter().find(|(n, _)| *n == name).map(|(_, command)| *command)
+    }
+}
+
+const DEFAULT_KEYMAP:&str = "
+editor up               cursor_up
+editor down             cursor_down
+editor left             cursor_left
+editor right            cursor_right
+editor pageup           page_up
+editor pagedown         page_down
+editor home             line_start
+editor end              line_end
+editor shift-up         select_up
+editor shift-down       select_down
+editor shift-left       select_left
+editor shift-right      select_right
+editor shift-pageup     select_page_up
+editor shift-pagedown   select_page_down
+editor shift-home       select_line_start
+editor shift-end        select_line_end
+editor cmd-a            select_all
+editor backspace        backspace
+editor shift-backspace  backspace
+editor delete           delete
+editor shift-delete     delete
+editor tab              tab
+editor shift-tab        outdent
+editor cmd-]            indent
+editor cmd-[            outdent
+editor cmd-alt-up       add_cursor_above
+editor cmd-alt-down     add_cursor_below
+editor cmd-d            next_occurrence
+editor cmd-shift-l      all_occurrences
+editor alt-shift-i      split_into_lines
+editor alt-up           move_lines_up
+editor alt-down         move_lines_down
+editor cmd-shift-d      duplicate_lines
+editor cmd-j            join_lines
+editor cmd-shift-k      delete_lines
+editor cmd-/            toggle_line_comment
+editor alt-shift-a      toggle_block_comment
+editor alt-z            toggle_soft_wrap
+editor alt-shift-z      toggle_column_wrap
+editor cmd-z            undo
+editor cmd-shift-z      redo
+editor cmd-alt-z        prev_undo_branch
+editor cmd-alt-shift-z  next_undo_branch
+editor cmd-alt-minus    undo_minute
+editor cmd-alt-equals   redo_minute
+editor cmd-x            cut
+editor cmd-s            save
+editor cmd-f            find
+editor cmd-alt-f        find_replace
+editor cmd-g            find_next
+editor cmd-shift-g      find_prev
+editor escape           close_find
+editor cmd-\\            jump_to_bracket
+editor cmd-shift-\\      select_enclosing_block
+editor cmd-shift-[      fold
+editor cmd-alt-shift-[  fold_all
+editor cmd-shift-]      unfold
+editor cmd-alt-shift-]  unfold_all
+editor cmd-k cmd-0      fold_all
+editor cmd-k cmd-j      unfold_all
+editor cmd-k cmd-m      toggle_vim
+editor cmd-k cmd-l      toggle_line_numbers
+editor cmd-k cmd-n      toggle_minimap
+editor cmd-k cmd-\\      split_editor
+file_tree return        open_file
+dock cmd-alt-s          save_all
+dock cmd-shift-f        search_in_files
+";
+
+const DEFAULT_KEYMAP_ALT_WORDS:&str = "
+editor alt-left             word_left
+editor alt-right            word_right
+editor alt-shift-left       select_word_left
+editor alt-shift-right      select_word_right
+editor alt-backspace        backspace_word
+editor alt-delete           delete_word
+";
+
+const DEFAULT_KEYMAP_CTRL_WORDS:&str = "
+editor ctrl-left            word_left
+editor ctrl-right           word_right
+editor ctrl-shift-left      select_word_left
+editor ctrl-shift-right     select_word_right
+editor ctrl-backspace       backspace_word
+editor ctrl-delete          delete_word
+editor ctrl-h               find_replace
+editor ctrl-y               redo
+";
+
+// the editor always took control as well as logo for these, on the mac that stays working
+const DEFAULT_KEYMAP_MAC_CTRL:&str = "
+editor ctrl-z               undo
+editor ctrl-shift-z         redo
+editor ctrl-x               cut
+editor ctrl-a               select_all
+";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyChord{
+    pub key_code:KeyCode,
+    pub modifiers:KeyModifiers
+}
+
+#[derive(Clone, Debug)]
+pub struct KeyBinding{
+    pub context:KeyContext,
+    pub chords:Vec<KeyChord>,
+    pub command:KeyCommand
+}
+
+#[derive(Clone)]
+pub struct Keymap{
+    pub platform:KeymapPlatform,
+    pub bindings:Vec<KeyBinding>
+}
+
+impl Keymap{
+    pub fn default_for(platform:KeymapPlatform)->Keymap{
+        let mut keymap = Keymap{
+            platform:platform,
+            bindings:Vec::new()
+        };
+        let words = match platform{
+            KeymapPlatform::Mac=>vec![DEFAULT_KEYMAP_ALT_WORDS, DEFAULT_KEYMAP_MAC_CTRL],
+            KeymapPlatform::Other=>vec![DEFAULT_KEYMAP_CTRL_WORDS],
+            KeymapPlatform::Web=>vec![DEFAULT_KEYMAP_ALT_WORDS, DEFAULT_KEYMAP_CTRL_WORDS]
+        };
+        for text in Some(DEFAULT_KEYMAP).into_iter().chain(words){
+            if let Err(err) = keymap.load_bindings(text){
+                panic!("error in default keymap: {}", err);
+            }
+        }
+        keymap
+    }
+
+    // parses bindings in the keymap format and adds them on top of the ones we have.
+    // nothing is added if a line has an error
+    pub fn load_bindings(&mut self, text:&str)->Result<(), String>{
+        let mut bindings = Vec::new();
+        for (index, line) in text.lines().enumerate(){
+            let words:Vec<&str> = line.split('#').next().unwrap_or("").split_whitespace().collect();
+            if words.len() == 0{
+                continue
+            }
+            if words.len() < 3{
+                return Err(format!("line {}: expected a context, keys and a command", index + 1))
+            }
+            let context = match words[0]{
+                "editor"=>KeyContext::Editor,
+                "file_tree"=>KeyContext::FileTree,
+                "dock"=>KeyContext::Dock,
+                context=>return Err(format!("line {}: unknown context {}", index + 1, context))
+            };
+            let command = match KeyCommand::from_name(words[words.len() - 1]){
+                Some(command)=>command,
+                None=>return Err(format!("line {}: unknown command {}", index + 1, words[words.len() - 1]))
+            };
+            let mut chords = Vec::new();
+            for word in &words[1..words.len() - 1]{
+                match self.parse_chord(word){
+                    Some(chord)=>chords.push(chord),
+                    None=>return Err(format!("line {}: can't read keys {}", index + 1, word))
+                }
+            }
+            bindings.push(KeyBinding{context:context, chords:chords, command:command});
+        }
+        self.bindings.extend(bindings);
+        Ok(())
+    }
+
+    fn parse_chord(&self, text:&str)->Option<KeyChord>{
+        let mut modifiers = KeyModifiers::default();
+        let mut parts:Vec<&str> = text.split('-').collect();
+        let key = parts.pop()?;
+        for part in parts{
+            match part{
+                "cmd"=>if self.platform == KeymapPlatform::Mac{modifiers.logo = true}else{modifiers.control = true},
+                "ctrl"=>modifiers.control = true,
+                "alt"=>modifiers.alt = true,
+                "shift"=>modifiers.shift = true,
+                "logo"=>if self.platform == KeymapPlatform::Web{modifiers.control = true}else{modifiers.logo = true},
+                _=>return None
+            }
+        }
+        Some(KeyChord{key_code:key_code_from_name(key)?, modifiers:modifiers})
+    }
+
+    pub fn chord_for(&self, ke:&KeyEvent)->KeyChord{
+        let mut modifiers = ke.modifiers.clone();
+        if self.platform == KeymapPlatform::Web && modifiers.logo{
+            modifiers.logo = false;
+            modifiers.control = true;
+        }
+        KeyChord{key_code:ke.key_code.clone(), modifiers:modifiers}
+    }
+
+    // adds a key to the sequence typed so far and returns the command it completes in the
+    // first of the contexts that binds it. keys that start a longer binding wait for the next
+    // one, and a key that doesn't continue the sequence starts a new one
+    pub fn resolve(&self, contexts:&[KeyContext], sequence:&mut Vec<KeyChord>, ke:&KeyEvent)->Option<KeyCommand>{
+        if is_modifier_key(&ke.key_code){
+            return None
+        }
+        sequence.push(self.chord_for(ke));
+        loop{
+            for context in contexts{
+                if let Some(binding) = self.bindings.iter().rev().find(|binding| binding.context == *context && binding.chords == *sequence){
+                    sequence.clear();
+                    if binding.command == KeyCommand::None{
+                        return None
+                    }
+                    return Some(binding.command)
+                }
+            }
+            let is_prefix = self.bindings.iter().any(|binding|{
+                contexts.contains(&binding.context) && binding.chords.len() > sequence.len() && binding.chords.starts_with(sequence)
+            });
+            if is_prefix{
+                return None
+            }
+            if sequence.len() > 1{
+                sequence.drain(0..sequence.len() - 1);
+            }
+            else{
+                sequence.clear();
+                return None
+            }
+        }
+    }
+}
+
+fn is_modifier_key(key_code:&KeyCode)->bool{
+    match key_code{
+        KeyCode::LeftControl | KeyCode::LeftAlt | KeyCode::LeftShift | KeyCode::LeftLogo |
+        KeyCode::RightControl | KeyCode::RightAlt | KeyCode::RightShift | KeyCode::RightLogo=>true,
+        _=>false
+    }
+}
+
+pub fn key_code_from_name(name:&str)->Option<KeyCode>{
+    let mut chars = name.chars();
+    if let (Some(ch), None) = (chars.next(), chars.next()){
+        return match ch{
+            'a'=>Some(KeyCode::KeyA), 'b'=>Some(KeyCode::KeyB), 'c'=>Some(KeyCode::KeyC), 'd'=>Some(KeyCode::KeyD),
+            'e'=>Some(KeyCode::KeyE), 'f'=>Some(KeyCode::KeyF), 'g'=>Some(KeyCode::KeyG), 'h'=>Some(KeyCode::KeyH),
+            'i'=>Some(KeyCode::KeyI), 'j'=>Some(KeyCode::KeyJ), 'k'=>Some(KeyCode::KeyK), 'l'=>Some(KeyCode::KeyL),
+            'm'=>Some(KeyCode::KeyM), 'n'=>Some(KeyCode::KeyN), 'o'=>Some(KeyCode::KeyO), 'p'=>Some(KeyCode::KeyP),
+            'q'=>Some(KeyCode::KeyQ), 'r'=>Some(KeyCode::KeyR), 's'=>Some(KeyCode::KeyS), 't'=>Some(KeyCode::KeyT),
+            'u'=>Some(KeyCode::KeyU), 'v'=>Some(KeyCode::KeyV), 'w'=>Some(KeyCode::KeyW), 'x'=>Some(KeyCode::KeyX),
+            'y'=>Some(KeyCode::KeyY), 'z'=>Some(KeyCode::KeyZ),
+            '0'=>Some(KeyCode::Key0), '1'=>Some(KeyCode::Key1), '2'=>Some(KeyCode::Key2), '3'=>Some(KeyCode::Key3),
+            '4'=>Some(KeyCode::Key4), '5'=>Some(KeyCode::Key5), '6'=>Some(KeyCode::Key6), '7'=>Some(KeyCode::Key7),
+            '8'=>Some(KeyCode::Key8), '9'=>Some(KeyCode::Key9),
+            '`'=>Some(KeyCode::Backtick), '['=>Some(KeyCode::LBracket), ']'=>Some(KeyCode::RBracket),
+            ';'=>Some(KeyCode::Semicolon), '\''=>Some(KeyCode::Quote), '\\'=>Some(KeyCode::Backslash),
+            ','=>Some(KeyCode::Comma), '.'=>Some(KeyCode::Period), '/'=>Some(KeyCode::Slash),
+            '='=>Some(KeyCode::Equals),
+            _=>None
+        }
+    }
+    match name{
+        "minus"=>Some(KeyCode::Minus),
+        "equals"=>Some(KeyCode::Equals),
+        "escape"=>Some(KeyCode::Escape),
+        "backspace"=>Some(KeyCode::Backspace),
+        "tab"=>Some(KeyCode::Tab),
+        "return"=>Some(KeyCode::Return),
+        "space"=>Some(KeyCode::Space),
+        "insert"=>Some(KeyCode::Insert),
+        "delete"=>Some(KeyCode::Delete),
+        "home"=>Some(KeyCode::Home),
+        "end"=>Some(KeyCode::End),
+        "pageup"=>Some(KeyCode::PageUp),
+        "pagedown"=>Some(KeyCode::PageDown),
+        "up"=>Some(KeyCode::ArrowUp),
+        "down"=>Some(KeyCode::ArrowDown),
+        "left"=>Some(KeyCode::ArrowLeft),
+        "right"=>Some(KeyCode::ArrowRight),
+        "f1"=>Some(KeyCode::F1), "f2"=>Some(KeyCode::F2), "f3"=>Some(KeyCode::F3), "f4"=>Some(KeyCode::F4),
+        "f5"=>Some(KeyCode::F5), "f6"=>Some(KeyCode::F6), "f7"=>Some(KeyCode::F7), "f8"=>Some(KeyCode::F8),
+        "f9"=>Some(KeyCode::F9), "f10"=>Some(KeyCode::F10), "f11"=>Some(KeyCode::F11), "f12"=>Some(KeyCode::F12),
+        _=>None
+    }
+}
@@ -0,0 +1,342 @@
+use crate::textbuffer::*;
+
+// One change of a line command, in offsets of the text from before any of its changes.
+#[derive(Clone, Debug)]
+pub struct LineEdit{
+    pub start:usize,
+    pub end:usize,
+    pub text:String
+}
+
+impl LineEdit{
+    fn new(start:usize, end:usize, text:String)->LineEdit{
+        LineEdit{start:start, end:end, text:text}
+    }
+}
+
+impl CursorSet{
+    // the rows the cursors are on as (first, last), sorted and with touching ones merged. a
+    // selection that ends at the start of a line doesn't take that line along
+    pub fn line_blocks(&self, text_buffer:&TextBuffer)->Vec<(usize, usize)>{
+        let mut rows:Vec<(usize, usize)> = self.set.iter().map(|cursor| Self::cursor_rows(cursor, text_buffer)).collect();
+        rows.sort();
+        let mut blocks:Vec<(usize, usize)> = Vec::new();
+        for (first, last) in rows{
+            if let Some(block) = blocks.last_mut(){
+                if first <= block.1 + 1{
+                    block.1 = block.1.max(last);
+                    continue
+                }
+            }
+            blocks.push((first, last));
+        }
+        blocks
+    }
+
+    fn cursor_rows(cursor:&Cursor, text_buffer:&TextBuffer)->(usize, usize){
+        let (start, end) = cursor.order();
+        let (first, _) = text_buffer.offset_to_row_col(start);
+        let (last, last_col) = text_buffer.offset_to_row_col(end);
+        if last > first && last_col == 0{(first, last - 1)}else{(first, last)}
+    }
+
+    fn line_start(row:usize, text_buffer:&TextBuffer)->usize{
+        text_buffer.row_col_to_offset(row, 0)
+    }
+
+    fn line_end(row:usize, text_buffer:&TextBuffer)->usize{
+        text_buffer.row_col_to_offset(row, 0) + text_buffer.get_line_len(row)
+    }
+
+    fn rows_as_string(first:usize, last:usize, text_buffer:&TextBuffer)->String{
+        let start = Self::line_start(first, text_buffer);
+        let end = Self::line_end(last, text_buffer);
+        text_buffer.rope.chars_at(start).take(end - start).collect()
+    }
+
+    // where an offset ends up after the edits. an offset in a replaced range stays in the new
+    // text as far as it can, text inserted at an offset goes before it
+    pub fn map_offset(offset:usize, edits:&[LineEdit])->usize{
+        let mut delta:isize = 0;
+        for edit in edits{
+            if edit.start > offset{
+                break
+            }
+            let new_len = edit.text.chars().count();
+            if edit.end <= offset{
+                delta += new_len as isize - (edit.end - edit.start) as isize;
+            }
+            else{
+                return ((edit.start as isize + delta) as usize) + (offset - edit.start).min(new_len)
+            }
+        }
+        (offset as isize + delta) as usize
+    }
+
+    // applies sorted edits that don't overlap as one undo step
+    pub fn apply_line_edits(&mut self, edits:&[LineEdit], cursors_clone:CursorSet, text_buffer:&mut TextBuffer){
+        if edits.len() == 0{
+            return
+        }
+        let mut delta:isize = 0;
+        let mut ops = Vec::new();
+        for edit in edits{
+            let start = (edit.start as isize + delta) as usize;
+            let op = text_buffer.replace_with_string(start, edit.end - edit.start, &edit.text);
+            delta += op.len as isize - (edit.end - edit.start) as isize;
+            ops.push(op);
+        }
+        text_buffer.push_undo(ops, TextUndoGrouping::Block, cursors_clone);
+    }
+
+    // applies the edits and moves the cursors along with the text
+    fn edit_lines(&mut self, edits:Vec<LineEdit>, text_buffer:&mut TextBuffer){
+        if edits.len() == 0{
+            return
+        }
+        let cursors_clone = self.clone();
+        for cursor in &mut self.set{
+            cursor.head = Self::map_offset(cursor.head, &edits);
+            cursor.tail = Self::map_offset(cursor.tail, &edits);
+        }
+        self.apply_line_edits(&edits, cursors_clone, text_buffer);
+        for cursor in &mut self.set{
+            cursor.calc_max(text_buffer);
+        }
+        self.fuse_adjacent(text_buffer);
+    }
+
+    // moves the cursors on the rows of each block by its shift
+    fn shift_block_cursors(&mut self, blocks:&[(usize, usize)], shifts:&[isize], text_buffer:&TextBuffer)->Vec<Cursor>{
+        self.set.iter().map(|cursor|{
+            let (first, _) = Self::cursor_rows(cursor, text_buffer);
+            let index = blocks.iter().position(|(block_first, block_last)| first >= *block_first && first <= *block_last).unwrap_or(0);
+            let shift = shifts[index];
+            Cursor{
+                head:(cursor.head as isize + shift) as usize,
+                tail:(cursor.tail as isize + shift) as usize,
+                max:cursor.max
+            }
+        }).collect()
+    }
+
+    // puts indent_size spaces in front of every line with text on it
+    pub fn indent_lines(&mut self, indent_size:usize, text_buffer:&mut TextBuffer){
+        let indent = " ".repeat(indent_size);
+        let mut edits = Vec::new();
+        for (first, last) in self.line_blocks(text_buffer){
+            for row in first..(last + 1){
+                if text_buffer.get_line_len(row) > 0{
+                    let start = Self::line_start(row, text_buffer);
+                    edits.push(LineEdit::new(start, start, indent.clone()));
+                }
+            }
+        }
+        self.edit_lines(edits, text_buffer);
+    }
+
+    // takes up to indent_size spaces, or a tab, from the front of every line
+    pub fn outdent_lines(&mut self, indent_size:usize, text_buffer:&mut TextBuffer){
+        let mut edits = Vec::new();
+        for (first, last) in self.line_blocks(text_buffer){
+            for row in first..(last + 1){
+                let mut remove = 0;
+                for ch in text_buffer.line_chars(row).take(indent_size){
+                    if ch == '\t'{
+                        if remove == 0{
+                            remove = 1;
+                        }
+                        break
+                    }
+                    if ch != ' '{
+                        break
+                    }
+                    remove += 1;
+                }
+                if remove > 0{
+                    let start = Self::line_start(row, text_buffer);
+                    edits.push(LineEdit::new(start, start + remove, String::new()));
+                }
+            }
+        }
+        self.edit_lines(edits, text_buffer);
+    }
+
+    // the tab key without a selection, spaces up to the next indent stop at every cursor
+    pub fn insert_tab(&mut self, indent_size:usize, text_buffer:&mut TextBuffer){
+        let texts:Vec<String> = self.set.iter().map(|cursor|{
+            let (start, _) = cursor.order();
+            let (_, col) = text_buffer.offset_to_row_col(start);
+            " ".repeat(indent_size - col % indent_size)
+        }).collect();
+        self.replace_texts(&texts, text_buffer);
+    }
+
+    // swaps the lines of every block with the line above or below it, does nothing if a block
+    // is already at the top or bottom
+    pub fn move_lines(&mut self, up:bool, text_buffer:&mut TextBuffer){
+        let blocks = self.line_blocks(text_buffer);
+        let line_count = text_buffer.get_line_count();
+        if up && blocks[0].0 == 0 || !up && blocks[blocks.len() - 1].1 + 1 >= line_count{
+            return
+        }
+        let mut edits = Vec::new();
+        let mut shifts = Vec::new();
+        for (first, last) in &blocks{
+            let block = Self::rows_as_string(*first, *last, text_buffer);
+            if up{
+                let other = Self::rows_as_string(first - 1, first - 1, text_buffer);
+                shifts.push(-(other.chars().count() as isize + 1));
+                edits.push(LineEdit::new(
+                    Self::line_start(first - 1, text_buffer),
+                    Self::line_end(*last, text_buffer),
+                    format!("{}\n{}", block, other)
+                ));
+            }
+            else{
+                let other = Self::rows_as_string(last + 1, last + 1, text_buffer);
+                shifts.push(other.chars().count() as isize + 1);
+                edits.push(LineEdit::new(
+                    Self::line_start(*first, text_buffer),
+                    Self::line_end(last + 1, text_buffer),
+                    format!("{}\n{}", other, block)
+                ));
+            }
+        }
+        // blocks one row apart swap with the same row, which would overlap
+        if edits.windows(2).any(|pair| pair[0].end >= pair[1].start){
+            return
+        }
+        let cursors_clone = self.clone();
+        self.set = self.shift_block_cursors(&blocks, &shifts, text_buffer);
+        self.apply_line_edits(&edits, cursors_clone, text_buffer);
+    }
+
+    // puts a copy of the lines of every block below it, the cursors move to the copy
+    pub fn duplicate_lines(&mut self, text_buffer:&mut TextBuffer){
+        let blocks = self.line_blocks(text_buffer);
+        let mut edits = Vec::new();
+        let mut shifts = Vec::new();
+        let mut delta = 0;
+        for (first, last) in &blocks{
+            let block = Self::rows_as_string(*first, *last, text_buffer);
+            let len = block.chars().count() as isize + 1;
+            shifts.push(delta + len);
+            delta += len;
+            let end = Self::line_end(*last, text_buffer);
+            edits.push(LineEdit::new(end, end, format!("\n{}", block)));
+        }
+        let cursors_clone = self.clone();
+        self.set = self.shift_block_cursors(&blocks, &shifts, text_buffer);
+        self.apply_line_edits(&edits, cursors_clone, text_buffer);
+    }
+
+    // joins the lines of every block, or a line with the one below it, with a single space
+    pub fn join_lines(&mut self, text_buffer:&mut TextBuffer){
+        let line_count = text_buffer.get_line_count();
+        let mut edits = Vec::new();
+        for (first, last) in self.line_blocks(text_buffer){
+            let last = if last == first{first + 1}else{last};
+            for row in (first + 1)..(last + 1).min(line_count){
+                let start = Self::line_end(row - 1, text_buffer);
+                let indent = text_buffer.line_chars(row).take_while(|ch| *ch == ' ' || *ch == '\t').count();
+                let end = Self::line_start(row, text_buffer) + indent;
+                let glue = if indent == text_buffer.get_line_len(row) || text_buffer.get_line_len(row - 1) == 0{""}else{" "};
+                edits.push(LineEdit::new(start, end, glue.to_string()));
+            }
+        }
+        self.edit_lines(edits, text_buffer);
+    }
+
+    pub fn delete_lines(&mut self, text_buffer:&mut TextBuffer){
+        let line_count = text_buffer.get_line_count();
+        let mut edits = Vec::new();
+        for (first, last) in self.line_blocks(text_buffer){
+            if last + 1 < line_count{
+                edits.push(LineEdit::new(Self::line_start(first, text_buffer), Self::line_start(last + 1, text_buffer), String::new()));
+            }
+            else if first > 0{ // the last line has no newline, take the one before it
+                edits.push(LineEdit::new(Self::line_end(first - 1, text_buffer), Self::line_end(last, text_buffer), String::new()));
+            }
+            else{
+                edits.push(LineEdit::new(0, text_buffer.get_char_count(), String::new()));
+            }
+        }
+        for cursor in &mut self.set{
+            cursor.tail = cursor.head;
+        }
+        self.edit_lines(edits, text_buffer);
+    }
+
+    // comments out the lines of every block at their smallest indent, or uncomments them if
+    // all of them are commented
+    pub fn toggle_line_comment(&mut self, marker:&str, text_buffer:&mut TextBuffer){
+        let marker:Vec<char> = marker.chars().collect();
+        let mut edits = Vec::new();
+        for (first, last) in self.line_blocks(text_buffer){
+            let lines:Vec<(usize, Vec<char>)> = (first..(last + 1)).map(|row| (row, text_buffer.get_line(row)))
+                .filter(|(_, line)| line.iter().any(|ch| *ch != ' ' && *ch != '\t')).collect();
+            if lines.len() == 0{
+                continue
+            }
+            let indent_of = |line:&Vec<char>| line.iter().take_while(|ch| **ch == ' ' || **ch == '\t').count();
+            let commented = lines.iter().all(|(_, line)| line[indent_of(line)..].starts_with(&marker));
+            let min_indent = lines.iter().map(|(_, line)| indent_of(line)).min().unwrap();
+            for (row, line) in &lines{
+                let start = Self::line_start(*row, text_buffer);
+                if commented{
+                    let at = indent_of(line);
+                    let mut len = marker.len();
+                    if line.get(at + len) == Some(&' '){
+                        len += 1;
+                    }
+                    edits.push(LineEdit::new(start + at, start + at + len, String::new()));
+                }
+                else{
+                    let mut text:String = marker.iter().collect();
+                    text.push(' ');
+                    edits.push(LineEdit::new(start + min_indent, start + min_indent, text));
+                }
+            }
+        }
+        self.edit_lines(edits, text_buffer);
+    }
+
+    // puts block comment markers around every selection, or around the text of the line of a
+    // cursor without one. removes them if they are there already
+    pub fn toggle_block_comment(&mut self, open:&str, close:&str, text_buffer:&mut TextBuffer){
+        let open_len = open.chars().count();
+        let close_len = close.chars().count();
+        let mut ranges:Vec<(usize, usize)> = self.set.iter().map(|cursor|{
+            if cursor.has_selection(){
+                cursor.order()
+            }
+            else{
+                let (row, _) = text_buffer.offset_to_row_col(cursor.head);
+                let line = text_buffer.get_line(row);
+                let indent = line.iter().take_while(|ch| **ch == ' ' || **ch == '\t').count();
+                let trailing = line[indent..].iter().rev().take_while(|ch| **ch == ' ' || **ch == '\t').count();
+                let start = Self::line_start(row, text_buffer);
+                (start + indent, start + line.len() - trailing)
+            }
+        }).collect();
+        ranges.sort();
+        ranges.dedup();
+        let mut edits = Vec::new();
+        for (start, end) in ranges{
+            let text:String = text_buffer.rope.chars_at(start).take(end - start).collect();
+            if end - start >= open_len + close_len && text.starts_with(open) && text.ends_with(close){
+                edits.push(LineEdit::new(start, start + open_len, String::new()));
+                edits.push(LineEdit::new(end - close_len, end, String::new()));
+            }
+            else{
+                edits.push(LineEdit::new(start, start, open.to_string()));
+                edits.push(LineEdit::new(end, end, close.to_string()));
+            }
+        }
+        if edits.windows(2).any(|pair| pair[0].end > pair[1].start){
+            return
+        }
+        self.edit_lines(edits, text_buffer);
+    }
+}
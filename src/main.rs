@@ -3,17 +3,55 @@
 use widgets::*;
 use std::collections::HashMap;
 
+mod rope;
+pub use crate::rope::*;
 mod textbuffer;
 pub use crate::textbuffer::*;
+mod undotree;
+pub use crate::undotree::*;
+mod undofile;
+pub use crate::undofile::*;
+mod regex;
+pub use crate::regex::*;
+mod textsearch;
+pub use crate::textsearch::*;
+mod multicursor;
+mod lineedit;
+pub use crate::lineedit::*;
+mod autoedit;
+pub use crate::autoedit::*;
+mod keymap;
+pub use crate::keymap::*;
+mod vim;
+pub use crate::vim::*;
+mod gutter;
+pub use crate::gutter::*;
+mod minimap;
+pub use crate::minimap::*;
+mod findbar;
+pub use crate::findbar::*;
+mod searchpanel;
+pub use crate::searchpanel::*;
+mod tokenizer;
+pub use crate::tokenizer::*;
+mod brackets;
+pub use crate::brackets::*;
+mod folds;
+pub use crate::folds::*;
+mod softwrap;
+pub use crate::softwrap::*;
 mod codeeditor;
 pub use crate::codeeditor::*;
 mod rusteditor;
 pub use crate::rusteditor::*;
+mod plaineditor;
+pub use crate::plaineditor::*;
 
 #[derive(Clone)]
 enum Panel{
     Color(Color), 
     FileTree,
+    Search,
     FileEditorTarget,
     FileEditor{path:String, editor_id:u64}
 }
@@ -22,12 +60,22 @@ struct App{
     view:View<ScrollBar>,
     dock:Dock<Panel>,
     file_tree:FileTree,
+    search_panel:SearchPanel,
 
     file_editors:Elements<u64, FileEditor, FileEditorTemplates>,
     file_editor_id_alloc:u64,
 
     text_buffers:HashMap<String, TextBuffer>,
+    // a selection to apply to an editor once its buffer is loaded, (editor_id, start, end)
+    pending_selection:Option<(u64, usize, usize)>,
+    // files replace all reads from disk again before editing them, (load_id, path)
+    replace_loads:Vec<(u64, String)>,
+    // the search and replace they get, and the files and matches replaced so far
+    replace_pending:Option<(TextSearch, String, usize, usize)>,
     tree_load_id:u64,
+    keymap:Keymap,
+    keymap_load_id:u64,
+    key_sequence:Vec<KeyChord>,
     quad:Quad
 }
 
@@ -54,9 +102,19 @@ impl Style for App{
             file_tree:FileTree{
                 ..Style::style(cx)
             },
+            search_panel:SearchPanel{
+                ..Style::style(cx)
+            },
+            pending_selection:None,
+            replace_loads:Vec::new(),
+            replace_pending:None,
             tree_load_id:0,
+            keymap:Keymap::default_for(KeymapPlatform::current()),
+            keymap_load_id:0,
+            key_sequence:Vec::new(),
             file_editors:Elements::new(FileEditorTemplates{
-                rust_editor:RustEditor{..Style::style(cx)}
+                rust_editor:RustEditor{..Style::style(cx)},
+                plain_editor:PlainEditor{..Style::style(cx)}
             }),
             dock:Dock{
                 dock_items:Some(DockItem::Splitter{
@@ -99,6 +157,11 @@ impl Style for App{
                                     closeable:true,
                                     title:"Log".to_string(),
                                     item:Panel::Color(color256(30,30,30))
+                                },
+                                DockTab{
+                                    closeable:false,
+                                    title:"Search".to_string(),
+                                    item:Panel::Search
                                 }
                             ]
                         })
@@ -119,11 +182,32 @@ fn path_file_name(path:&str)->String{
     }
 }
 
+fn path_file_extension(path:&str)->String{
+    let file_name = path_file_name(path);
+    if let Some(pos) = file_name.rfind('.'){
+        file_name[pos+1..file_name.len()].to_string()
+    }
+    else{
+        "".to_string()
+    }
+}
+
+// the undo history of /dir/file.rs is kept next to it in /dir/.file.rs.undo
+fn undo_history_path(path:&str)->String{
+    if let Some(pos) = path.rfind('/'){
+        format!(".{}.{}.undo", &path[0..pos+1], &path[pos+1..])
+    }
+    else{
+        format!("./.{}.undo", path)
+    }
+}
+
 impl App{
     fn handle_app(&mut self, cx:&mut Cx, event:&mut Event){
         match event{
             Event::Construct=>{
                 self.tree_load_id = cx.read_file("./index.json");
+                self.keymap_load_id = cx.read_file("./keymap.txt");
             },
             Event::FileRead(fr)=>{
                 // lets see which file we loaded
@@ -134,16 +218,76 @@ impl App{
                         }
                     }
                 }
-                for (_path, text_buffer) in &mut self.text_buffers{
+                if fr.id == self.keymap_load_id{ // no keymap file just means the defaults
+                    if let Ok(data) = &fr.data{
+                        let result = match std::str::from_utf8(&data){
+                            Ok(text)=>self.keymap.load_bindings(text),
+                            Err(_)=>Err("not utf8".to_string())
+                        };
+                        if let Err(err) = result{
+                            cx.log(&format!("Error loading keymap.txt: {}\n", err));
+                        }
+                    }
+                }
+                if self.search_panel.handle_file_read(cx, fr){
+                    return
+                }
+                if let Some(index) = self.replace_loads.iter().position(|(load_id, _)| *load_id == fr.id){
+                    let (_, path) = self.replace_loads.remove(index);
+                    self.replace_in_loaded_file(cx, &path, &fr.data);
+                    return
+                }
+                for (path, text_buffer) in &mut self.text_buffers{
                     if text_buffer.load_id == fr.id{
                         text_buffer.load_id = 0;
-                        if let Ok(str_data) = &fr.data{
-                            text_buffer.load_buffer(str_data);
-                            cx.redraw_area(Area::All);
+                        let result = match &fr.data{
+                            Ok(str_data)=>text_buffer.load_buffer(str_data),
+                            Err(err)=>Err(err.clone())
+                        };
+                        if let Err(err) = result{
+                            cx.log(&format!("Error loading {}: {}\n", path, err));
+                            text_buffer.load_error = Some(err);
+                        }
+                        else{ // see if we have undo history for this file
+                            text_buffer.undo_load_id = cx.read_file(&undo_history_path(path));
+                        }
+                        cx.redraw_area(Area::All);
+                    }
+                    else if text_buffer.undo_load_id == fr.id{
+                        text_buffer.undo_load_id = 0;
+                        if let Ok(data) = &fr.data{
+                            text_buffer.load_undo_history(data);
                         }
                     }
                 }
-            }
+            },
+            Event::FileWrite(fw)=>{
+                for (path, text_buffer) in &mut self.text_buffers{
+                    if text_buffer.save_id != 0 && text_buffer.save_id == fw.id{
+                        text_buffer.save_id = 0;
+                        if let Some(error) = &fw.error{
+                            cx.log(&format!("Error saving {}: {}\n", path, error));
+                        }
+                        else{
+                            text_buffer.save_done();
+                            Self::save_undo_history(cx, path, text_buffer);
+                        }
+                    }
+                }
+            },
+            Event::CloseRequested=>{
+                for (path, text_buffer) in &self.text_buffers{
+                    Self::save_undo_history(cx, path, text_buffer);
+                }
+            },
+            Event::KeyDown(ke)=>{
+                let contexts:&[KeyContext] = if self.file_tree.has_key_focus(cx){&[KeyContext::FileTree, KeyContext::Dock]}else{&[KeyContext::Dock]};
+                if let Some(command) = self.keymap.resolve(contexts, &mut self.key_sequence, ke){
+                    if self.handle_app_command(cx, command){
+                        return
+                    }
+                }
+            },
             _=>()
         }
 
@@ -151,6 +295,9 @@ impl App{
         
         let mut dock_walker =  self.dock.walker();
         let mut file_tree_event = FileTreeEvent::None;
+        let mut search_panel_event = SearchPanelEvent::None;
+        let mut changed_editor = None;
+        let mut split_editor = None;
         while let Some(item) = dock_walker.walk_handle_dock(cx, event){
             match item{
                 Panel::Color(_)=>{}
@@ -158,16 +305,41 @@ impl App{
                 Panel::FileTree=>{
                     file_tree_event = self.file_tree.handle_file_tree(cx, event);
                 },
+                Panel::Search=>{
+                    search_panel_event = self.search_panel.handle_search_panel(cx, event);
+                },
                 Panel::FileEditor{path, editor_id}=>{
                     if let Some(file_editor) = &mut self.file_editors.get(*editor_id){
                         let text_buffer = self.text_buffers.get_mut(path);
                         if let Some(text_buffer) = text_buffer{
-                            file_editor.handle_file_editor(cx, event, text_buffer);
+                            let change_id = text_buffer.change_id;
+                            match file_editor.handle_file_editor(cx, event, text_buffer, &self.keymap){
+                                FileEditorEvent::Save=>{
+                                    if text_buffer.is_dirty() && text_buffer.load_id == 0 && text_buffer.load_error.is_none(){
+                                        let data = text_buffer.save_buffer();
+                                        text_buffer.save_id = cx.write_file(&format!(".{}",path), &data);
+                                    }
+                                },
+                                FileEditorEvent::SplitEditor=>{
+                                    split_editor = Some((path.clone(), *editor_id));
+                                },
+                                _=>()
+                            }
+                            if text_buffer.change_id != change_id{
+                                changed_editor = Some((path.clone(), *editor_id));
+                            }
                         }
                     }
                 }
             }
         }
+        // the other views on the buffer move their cursors along when they draw
+        if let Some((path, editor_id)) = changed_editor{
+            self.redraw_file_editors(cx, &path, editor_id);
+        }
+        if let Some((path, editor_id)) = split_editor{
+            self.split_file_editor(cx, &path, editor_id);
+        }
         match file_tree_event{
             FileTreeEvent::DragMove{fe, ..}=>{
                 self.dock.dock_drag_move(cx, fe);
@@ -191,10 +363,24 @@ impl App{
             },
             _=>{}
         }
+        match search_panel_event{
+            SearchPanelEvent::Search=>{
+                let paths = self.file_tree.get_all_file_paths();
+                self.search_panel.start_search(cx, paths, &self.text_buffers);
+            },
+            SearchPanelEvent::ReplaceAll=>{
+                self.replace_in_files(cx);
+            },
+            SearchPanelEvent::OpenMatch{path, start, end}=>{
+                self.open_search_match(cx, &path, start, end);
+            },
+            _=>{}
+        }
 
         // handle the dock events        
         match self.dock.handle_dock(cx, event){
             DockEvent::DockChanged=>{ // thats a bit bland event. lets let the thing know which file closed
+                self.close_unused_text_buffers(cx);
             },
             _=>()
         }
@@ -223,6 +409,9 @@ impl App{
                 Panel::FileTree=>{
                     self.file_tree.draw_file_tree(cx);
                 },
+                Panel::Search=>{
+                    self.search_panel.draw_search_panel(cx);
+                },
                 Panel::FileEditor{path, editor_id}=>{
                     //let text_buffer = self.text_buffers.get_mut(path).unwrap();
                     let text_buffer = self.text_buffers.entry(path.to_string()).or_insert_with(||{
@@ -231,15 +420,246 @@ impl App{
                             ..Default::default()
                         }
                     });
-                    self.file_editors.get_draw(cx, *editor_id, |_cx, tmpl|{
+                    let file_editor = self.file_editors.get_draw(cx, *editor_id, |_cx, tmpl|{
                         FileEditor::create_file_editor_for_path(path, tmpl)
-                    }).draw_file_editor(cx, text_buffer);
+                    });
+                    if let Some((select_editor_id, start, end)) = self.pending_selection{
+                        if select_editor_id == *editor_id && text_buffer.load_id == 0{
+                            self.pending_selection = None;
+                            file_editor.select_range(cx, start, end, text_buffer);
+                        }
+                    }
+                    file_editor.draw_file_editor(cx, text_buffer);
                 }
             }
         }
         self.view.end_view(cx);
     }
 
+    // the commands of the dock and file tree contexts, returns if the key was used
+    fn handle_app_command(&mut self, cx:&mut Cx, command:KeyCommand)->bool{
+        match command{
+            KeyCommand::OpenFile=>{
+                if let Some(path) = self.file_tree.get_marked_file_path(){
+                    if let Some(target_ctrl_id) = self.focus_editor_or_find_editor_target(cx, &path){
+                        self.open_new_editor_in_target_ctrl(cx, target_ctrl_id, &path);
+                    }
+                }
+                true
+            },
+            KeyCommand::SaveAll=>{
+                for (path, text_buffer) in &mut self.text_buffers{
+                    if text_buffer.is_dirty() && text_buffer.load_id == 0 && text_buffer.load_error.is_none(){
+                        let data = text_buffer.save_buffer();
+                        text_buffer.save_id = cx.write_file(&format!(".{}",path), &data);
+                    }
+                }
+                true
+            },
+            KeyCommand::SearchInFiles=>{
+                cx.set_key_focus(self.search_panel._bg_area);
+                self.search_panel.view.redraw_view_area(cx);
+                true
+            },
+            _=>false
+        }
+    }
+
+    fn save_undo_history(cx:&mut Cx, path:&str, text_buffer:&TextBuffer){
+        if text_buffer.load_id != 0 || text_buffer.load_error.is_some(){
+            return
+        }
+        let data = text_buffer.serialize_undo_history();
+        cx.write_file(&undo_history_path(path), &data);
+    }
+
+    // the paths with an editor tab
+    fn open_editor_paths(&mut self)->Vec<String>{
+        let mut open_paths = Vec::new();
+        let mut dock_walker = self.dock.walker();
+        while let Some(dock_item) = dock_walker.walk_dock_item(){
+            if let DockItem::TabControl{tabs, ..} = dock_item{
+                for tab in tabs.iter(){
+                    if let Panel::FileEditor{path, ..} = &tab.item{
+                        open_paths.push(path.clone());
+                    }
+                }
+            }
+        }
+        open_paths
+    }
+
+    // buffers without an editor tab get their undo history written out, and are dropped if clean
+    fn close_unused_text_buffers(&mut self, cx:&mut Cx){
+        let open_paths = self.open_editor_paths();
+        let closed_paths:Vec<String> = self.text_buffers.keys().filter(|path| !open_paths.contains(path)).cloned().collect();
+        for path in closed_paths{
+            let text_buffer = &self.text_buffers[&path];
+            Self::save_undo_history(cx, &path, text_buffer);
+            if !text_buffer.is_dirty() && text_buffer.save_id == 0{
+                self.text_buffers.remove(&path);
+            }
+        }
+    }
+
+    // shows the file in an editor tab, and selects the match once the editor draws
+    fn open_search_match(&mut self, cx:&mut Cx, path:&str, start:usize, end:usize){
+        if let Some(target_ctrl_id) = self.focus_editor_or_find_editor_target(cx, path){
+            self.open_new_editor_in_target_ctrl(cx, target_ctrl_id, path);
+        }
+        let mut dock_walker = self.dock.walker();
+        while let Some(dock_item) = dock_walker.walk_dock_item(){
+            if let DockItem::TabControl{current, tabs} = dock_item{
+                if let Some(tab) = tabs.get(*current){
+                    if let Panel::FileEditor{path:tab_path, editor_id} = &tab.item{
+                        if tab_path == path{
+                            self.pending_selection = Some((*editor_id, start, end));
+                            break
+                        }
+                    }
+                }
+            }
+        }
+        cx.redraw_area(Area::All);
+    }
+
+    // applies the replace to every file in the search results, one undo step per file. open
+    // buffers are left dirty, the others are read again so changes on disk since the search
+    // aren't lost, and saved
+    fn replace_in_files(&mut self, cx:&mut Cx){
+        let search = if let Some(search) = &self.search_panel.search{search.clone()}else{return};
+        let replace = self.search_panel.replace.clone();
+        let open_paths = self.open_editor_paths();
+        let mut file_count = 0;
+        let mut match_count = 0;
+        let results:Vec<String> = self.search_panel.results.iter().map(|result| result.path.clone()).collect();
+        for path in results{
+            let is_open = open_paths.contains(&path) || self.text_buffers.get(&path).map_or(false, |text_buffer| text_buffer.is_dirty());
+            if !is_open{
+                self.text_buffers.remove(&path);
+                self.replace_loads.push((cx.read_file(&format!(".{}", path)), path));
+                continue
+            }
+            if let Some(text_buffer) = self.text_buffers.get_mut(&path){
+                let count = Self::replace_in_buffer(&search, &replace, text_buffer);
+                if count > 0{
+                    file_count += 1;
+                    match_count += count;
+                }
+            }
+        }
+        self.replace_pending = Some((search, replace, file_count, match_count));
+        self.replace_loads_done(cx);
+        cx.redraw_area(Area::All);
+    }
+
+    // replaces in a file replace_in_files read again, and saves it
+    fn replace_in_loaded_file(&mut self, cx:&mut Cx, path:&str, data:&Result<Vec<u8>, String>){
+        if let Some((search, replace, file_count, match_count)) = &mut self.replace_pending{
+            if let Some(text_buffer) = self.text_buffers.get_mut(path){
+                // it was opened in the meantime, that buffer gets the edit
+                if text_buffer.load_id == 0 && text_buffer.load_error.is_none(){
+                    let count = Self::replace_in_buffer(search, replace, text_buffer);
+                    if count > 0{
+                        *file_count += 1;
+                        *match_count += count;
+                    }
+                }
+            }
+            else if let Ok(data) = data{
+                let mut text_buffer = TextBuffer::default();
+                match text_buffer.load_buffer(data){
+                    Ok(_)=>{
+                        let count = Self::replace_in_buffer(search, replace, &mut text_buffer);
+                        if count > 0{
+                            let data = text_buffer.save_buffer();
+                            text_buffer.save_id = cx.write_file(&format!(".{}", path), &data);
+                            self.text_buffers.insert(path.to_string(), text_buffer);
+                            *file_count += 1;
+                            *match_count += count;
+                        }
+                    },
+                    Err(err)=>cx.log(&format!("Error loading {}: {}\n", path, err))
+                }
+            }
+        }
+        self.replace_loads_done(cx);
+        cx.redraw_area(Area::All);
+    }
+
+    fn replace_loads_done(&mut self, cx:&mut Cx){
+        if self.replace_loads.len() != 0{
+            return
+        }
+        if let Some((_, _, file_count, match_count)) = self.replace_pending.take(){
+            self.search_panel.replace_done(cx, file_count, match_count);
+        }
+    }
+
+    // gives back the number of matches replaced
+    fn replace_in_buffer(search:&TextSearch, replace:&str, text_buffer:&mut TextBuffer)->usize{
+        // search again, the buffer may have changed since
+        let chars = text_buffer.rope.to_vec();
+        let matches = search.find_all(&chars);
+        if matches.len() == 0{
+            return 0
+        }
+        let replaces:Vec<String> = matches.iter().map(|search_match| search.expand_replace(replace, search_match, &chars)).collect();
+        // not made in a view, so every view moves its cursors along
+        text_buffer.view_id = 0;
+        let mut cursors = CursorSet::new();
+        cursors.select_matches(&matches, text_buffer);
+        cursors.replace_texts(&replaces, text_buffer);
+        matches.len()
+    }
+
+    // redraws the editors on path other than editor_id
+    fn redraw_file_editors(&mut self, cx:&mut Cx, path:&str, editor_id:u64){
+        let mut editor_ids = Vec::new();
+        let mut dock_walker = self.dock.walker();
+        while let Some(dock_item) = dock_walker.walk_dock_item(){
+            if let DockItem::TabControl{tabs, ..} = dock_item{
+                for tab in tabs.iter(){
+                    if let Panel::FileEditor{path:tab_path, editor_id:tab_editor_id} = &tab.item{
+                        if tab_path == path && *tab_editor_id != editor_id{
+                            editor_ids.push(*tab_editor_id);
+                        }
+                    }
+                }
+            }
+        }
+        for editor_id in editor_ids{
+            if let Some(file_editor) = self.file_editors.get(editor_id){
+                file_editor.redraw_file_editor(cx);
+            }
+        }
+    }
+
+    // puts a second editor on the same buffer right of the tab control editor_id is in
+    fn split_file_editor(&mut self, cx:&mut Cx, path:&str, editor_id:u64){
+        let new_tab = self.new_file_editor_tab(path);
+        let mut dock_walker = self.dock.walker();
+        while let Some(dock_item) = dock_walker.walk_dock_item(){
+            let has_editor = if let DockItem::TabControl{tabs, ..} = dock_item{
+                tabs.iter().any(|tab| if let Panel::FileEditor{editor_id:tab_editor_id, ..} = &tab.item{*tab_editor_id == editor_id}else{false})
+            }
+            else{
+                false
+            };
+            if has_editor{
+                *dock_item = DockItem::Splitter{
+                    align:SplitterAlign::Weighted,
+                    pos:0.5,
+                    axis:Axis::Vertical,
+                    first:Box::new(dock_item.clone()),
+                    last:Box::new(DockItem::TabControl{current:0, tabs:vec![new_tab]})
+                };
+                cx.redraw_area(Area::All);
+                break
+            }
+        }
+    }
+
     fn new_file_editor_tab(&mut self, path:&str)->DockTab<Panel>{
         let editor_id = self.file_editor_id_alloc;
         self.file_editor_id_alloc += 1;
@@ -307,51 +727,81 @@ impl App{
 
 struct FileEditorTemplates{
     rust_editor:RustEditor,
+    plain_editor:PlainEditor
 }
 
 #[derive(Clone)]
 enum FileEditor{
-    Rust(RustEditor)
+    Rust(RustEditor),
+    Plain(PlainEditor)
 }
 
 impl ElementLife for FileEditor{
     fn construct(&mut self, cx:&mut Cx){
         match self{
             FileEditor::Rust(re)=>re.construct(cx),
+            FileEditor::Plain(pe)=>pe.construct(cx),
         }
     }
     fn destruct(&mut self, cx:&mut Cx){
         match self{
             FileEditor::Rust(re)=>re.destruct(cx),
+            FileEditor::Plain(pe)=>pe.destruct(cx),
         }
     }
 }
 
 enum FileEditorEvent{
-    None
+    None,
+    Save,
+    SplitEditor
 }
 
 impl FileEditor{
-    fn handle_file_editor(&mut self, cx:&mut Cx, event:&mut Event, text_buffer:&mut TextBuffer)->FileEditorEvent{
+    fn handle_file_editor(&mut self, cx:&mut Cx, event:&mut Event, text_buffer:&mut TextBuffer, keymap:&Keymap)->FileEditorEvent{
+        let code_editor_event = match self{
+            FileEditor::Rust(re)=>re.handle_rust_editor(cx, event, text_buffer, keymap),
+            FileEditor::Plain(pe)=>pe.handle_plain_editor(cx, event, text_buffer, keymap),
+        };
+        match code_editor_event{
+            CodeEditorEvent::Save=>FileEditorEvent::Save,
+            CodeEditorEvent::SplitEditor=>FileEditorEvent::SplitEditor,
+            _=>FileEditorEvent::None
+        }
+    }
+
+    fn select_range(&mut self, cx:&mut Cx, start:usize, end:usize, text_buffer:&TextBuffer){
         match self{
-            FileEditor::Rust(re)=>{
-                re.handle_rust_editor(cx, event, text_buffer);
-                FileEditorEvent::None
-            },
+            FileEditor::Rust(re)=>re.select_range(cx, start, end, text_buffer),
+            FileEditor::Plain(pe)=>pe.select_range(cx, start, end, text_buffer),
+        }
+    }
+
+    fn redraw_file_editor(&mut self, cx:&mut Cx){
+        match self{
+            FileEditor::Rust(re)=>re.code_editor.view.redraw_view_area(cx),
+            FileEditor::Plain(pe)=>pe.code_editor.view.redraw_view_area(cx),
         }
     }
 
     fn draw_file_editor(&mut self, cx:&mut Cx, text_buffer:&mut TextBuffer){
         match self{
             FileEditor::Rust(re)=>re.draw_rust_editor(cx, text_buffer),
+            FileEditor::Plain(pe)=>pe.draw_plain_editor(cx, text_buffer),
         }
     }
 
     fn create_file_editor_for_path(path:&str, template:&FileEditorTemplates)->FileEditor{
         // check which file extension we have to spawn a new editor
-        FileEditor::Rust(RustEditor{
-            path:path.to_string(),
-            ..template.rust_editor.clone()
-        })
+        match path_file_extension(path).as_ref(){
+            "rs"=>FileEditor::Rust(RustEditor{
+                path:path.to_string(),
+                ..template.rust_editor.clone()
+            }),
+            _=>FileEditor::Plain(PlainEditor{
+                path:path.to_string(),
+                ..template.plain_editor.clone()
+            })
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,303 @@
+use widgets::*;
+use crate::textbuffer::*;
+use crate::tokenizer::*;
+
+// An overview of the whole buffer on the right of the editor. Every token is a small block
+// of its color, one line of blocks per line on screen without folds. Only the lines that fit
+// in the column are tokenized and drawn, as quad instances, so its cost doesn't grow with the
+// buffer. When the buffer doesn't fit the map scrolls along with the editor, and the slider
+// over the lines on screen can be dragged to scroll.
+
+#[derive(Clone, Copy)]
+pub struct MinimapBlock{
+    pub col:usize,
+    pub len:usize,
+    pub color:Color
+}
+
+// the blocks of the rows in the column, found with the tokenizer
+#[derive(Clone, Default)]
+pub struct MinimapLines{
+    pub blocks:Vec<MinimapBlock>,
+    // where the blocks of every row from first_row start, with one more at the end
+    pub row_starts:Vec<usize>,
+    pub first_row:usize,
+    // the change_id and rows they were built for
+    pub built_for:Option<(u64, usize, usize)>
+}
+
+impl MinimapLines{
+    // starts at the line state the buffer cached for the first row, like the editor does. gives
+    // back the line states it found so the cache can be updated after
+    pub fn build<T:Tokenizer, F>(text_buffer:&TextBuffer, tokenizer:&mut T, first_row:usize, last_row:usize, max_cols:usize, token_color:F)->(MinimapLines, Vec<(usize, u64)>)
+    where F:Fn(TokenType)->Color{
+        let mut blocks = Vec::new();
+        let mut row_starts = Vec::new();
+        let mut line_states = Vec::new();
+        let (start_row, line_state) = text_buffer.token_cache.start_for_row(first_row);
+        let mut state = TokenizerState::new_at_row(text_buffer, start_row);
+        let mut chunk = Vec::new();
+        let mut row = start_row;
+        let mut col = 0;
+        tokenizer.set_line_state(line_state);
+        if row == first_row{
+            row_starts.push(0);
+        }
+        while row < last_row{
+            let token_type = tokenizer.next_token(&mut state, &mut chunk);
+            match token_type{
+                TokenType::Eof=>break,
+                TokenType::Newline=>{
+                    row += 1;
+                    line_states.push((row, tokenizer.line_state()));
+                    if row >= first_row{
+                        row_starts.push(blocks.len());
+                    }
+                    col = 0;
+                },
+                TokenType::Whitespace=>{
+                    col += chunk.len();
+                },
+                _=>{
+                    if row >= first_row && col < max_cols{
+                        blocks.push(MinimapBlock{col:col, len:chunk.len().min(max_cols - col), color:token_color(token_type)});
+                    }
+                    col += chunk.len();
+                }
+            }
+            chunk.truncate(0);
+        }
+        row_starts.push(blocks.len());
+        (MinimapLines{
+            blocks:blocks,
+            row_starts:row_starts,
+            first_row:first_row,
+            built_for:Some((text_buffer.change_id, first_row, last_row))
+        }, line_states)
+    }
+
+    pub fn is_current(&self, text_buffer:&TextBuffer, first_row:usize, last_row:usize)->bool{
+        self.built_for == Some((text_buffer.change_id, first_row, last_row))
+    }
+
+    pub fn row_blocks(&self, row:usize)->&[MinimapBlock]{
+        if row < self.first_row || row - self.first_row + 1 >= self.row_starts.len(){
+            return &[]
+        }
+        let index = row - self.first_row;
+        &self.blocks[self.row_starts[index]..self.row_starts[index + 1]]
+    }
+}
+
+#[derive(Clone)]
+pub struct Minimap{
+    pub bg:Quad,
+    pub block:Quad,
+    pub width:f32,
+    // the size of a char on the map
+    pub char_size:Vec2,
+    pub block_alpha:f32,
+    // the vertical scroll bar is drawn over the right edge of the editor
+    pub scroll_bar_size:f32,
+    pub slider_color:Color,
+    pub slider_drag_color:Color,
+    pub selection_color:Color,
+    pub search_color:Color,
+    pub cursor_color:Color,
+    pub lines:MinimapLines,
+    // where the map is in the view, without scrolling
+    pub _rect:Rect,
+    pub _slider_y:f32,
+    pub _slider_h:f32,
+    // the first map line at the top of the column
+    pub _map_scroll:f32,
+    pub _line_count:usize,
+    // the map lines and rows in the column
+    pub _first_line:usize,
+    pub _last_line:usize,
+    pub _first_row:usize,
+    pub _last_row:usize,
+    // where on the slider it was grabbed
+    pub _drag:Option<f32>
+}
+
+impl Style for Minimap{
+    fn style(cx:&mut Cx)->Self{
+        Self{
+            bg:Quad{
+                color:color256(30,30,30),
+                ..Style::style(cx)
+            },
+            block:Style::style(cx),
+            width:110.,
+            char_size:Vec2{x:1., y:2.},
+            block_alpha:0.6,
+            scroll_bar_size:12.,
+            slider_color:color("#ffffff14"),
+            slider_drag_color:color("#ffffff28"),
+            selection_color:color("#264f78c8"),
+            search_color:color256(210,160,40),
+            cursor_color:color256(220,220,220),
+            lines:MinimapLines::default(),
+            _rect:Rect::zero(),
+            _slider_y:0.,
+            _slider_h:0.,
+            _map_scroll:0.,
+            _line_count:0,
+            _first_line:0,
+            _last_line:0,
+            _first_row:0,
+            _last_row:0,
+            _drag:None
+        }
+    }
+}
+
+impl Minimap{
+    // the width it takes from the text
+    pub fn total_width(&self)->f32{
+        self.width + self.scroll_bar_size
+    }
+
+    pub fn contains(&self, rel:Vec2)->bool{
+        rel.x >= self._rect.x && rel.x < self._rect.x + self._rect.w && rel.y >= self._rect.y && rel.y < self._rect.y + self._rect.h
+    }
+
+    // grabs the slider, a press outside it centers it there first. gives back the map line the
+    // top of the editor should show
+    pub fn begin_drag(&mut self, rel:Vec2)->Option<usize>{
+        let y = rel.y - self._rect.y;
+        if y >= self._slider_y && y < self._slider_y + self._slider_h{
+            self._drag = Some(y - self._slider_y);
+            return None
+        }
+        self._drag = Some(self._slider_h * 0.5);
+        self.drag_to(rel)
+    }
+
+    pub fn drag_to(&self, rel:Vec2)->Option<usize>{
+        let grab = self._drag?;
+        let top = rel.y - self._rect.y - grab;
+        let visible_lines = (self._slider_h / self.char_size.y).round() as usize;
+        if self._line_count as f32 * self.char_size.y > self._rect.h{
+            // the map scrolls, the slider goes over the column as the editor goes over the buffer
+            let ratio = (top / (self._rect.h - self._slider_h).max(1.)).max(0.).min(1.);
+            Some((ratio * self._line_count.saturating_sub(visible_lines) as f32).round() as usize)
+        }
+        else{
+            Some((top / self.char_size.y).max(0.) as usize)
+        }
+    }
+
+    pub fn end_drag(&mut self){
+        self._drag = None;
+    }
+
+    // top_line and bottom_line are the map lines at the top and bottom of the editor, ratio is
+    // how far it is scrolled. gives back the rows in the column, lines has to cover them
+    pub fn layout_minimap(&mut self, cx:&mut Cx, text_buffer:&TextBuffer, top_line:usize, bottom_line:usize, ratio:f32)->(usize, usize){
+        let view_w = cx.width_total(false);
+        let view_h = cx.height_total(false);
+        let line_h = self.char_size.y;
+        let folds = &text_buffer.folds;
+        let line_count = text_buffer.get_line_count();
+        self._line_count = folds.row_to_visual(line_count - 1) + 1;
+        self._rect = Rect{x:view_w - self.total_width(), y:0., w:self.width, h:view_h};
+        self._slider_h = ((bottom_line - top_line + 1) as f32 * line_h).min(view_h);
+        let map_h = self._line_count as f32 * line_h;
+        if map_h > view_h{
+            self._slider_y = ratio.max(0.).min(1.) * (view_h - self._slider_h);
+            self._map_scroll = (top_line as f32 * line_h - self._slider_y).max(0.).min(map_h - view_h);
+            self._slider_y = top_line as f32 * line_h - self._map_scroll;
+        }
+        else{
+            self._map_scroll = 0.;
+            self._slider_y = top_line as f32 * line_h;
+        }
+        self._first_line = (self._map_scroll / line_h) as usize;
+        self._last_line = (((self._map_scroll + view_h) / line_h) as usize + 1).min(self._line_count);
+        self._first_row = folds.visual_to_row(self._first_line);
+        self._last_row = if self._last_line >= self._line_count{line_count}else{folds.visual_to_row(self._last_line)};
+        (self._first_row, self._last_row)
+    }
+
+    // selections, search matches and diagnostics are marked on it
+    pub fn draw_minimap(&mut self, cx:&mut Cx, text_buffer:&TextBuffer, scroll_pos:Vec2, cursors:&[Cursor], matches:&[Cursor], diagnostics:&[(usize, Color)]){
+        let view_h = self._rect.h;
+        let line_h = self.char_size.y;
+        let folds = &text_buffer.folds;
+
+        let x = scroll_pos.x + self._rect.x;
+        let y = scroll_pos.y - self._map_scroll;
+        cx.new_instance_layer(self.bg.shader_id, 0);
+        self.bg.draw_quad(cx, Rect{x:x, y:scroll_pos.y, w:self.width, h:view_h});
+
+        let first_line = self._first_line;
+        let first_row = self._first_row;
+        let last_row = self._last_row;
+        let line_y = |row:usize| y + folds.row_to_visual(row) as f32 * line_h;
+        let row_visible = |row:usize| row >= first_row && row < last_row && !folds.is_hidden(row);
+
+        cx.new_instance_layer(self.block.shader_id, 0);
+        for (first, last) in Self::cursor_rows(text_buffer, cursors){
+            self.block.color = self.selection_color;
+            for row in first.max(first_row)..(last + 1).min(last_row){
+                if !folds.is_hidden(row){
+                    self.block.draw_quad(cx, Rect{x:x, y:line_y(row), w:self.width, h:line_h});
+                }
+            }
+        }
+        let mut line = first_line;
+        let mut row = first_row;
+        while row < last_row{
+            let by = line_y(row);
+            for block in self.lines.row_blocks(row){
+                self.block.color = block.color;
+                self.block.color.a *= self.block_alpha;
+                self.block.draw_quad(cx, Rect{
+                    x:x + block.col as f32 * self.char_size.x,
+                    y:by,
+                    w:block.len as f32 * self.char_size.x,
+                    h:line_h * 0.75
+                });
+            }
+            line += 1;
+            row = folds.visual_to_row(line);
+        }
+
+        // marks on the right edge, over the blocks
+        let mark_w = 4.;
+        self.block.color = self.search_color;
+        for search_match in matches{
+            let row = text_buffer.offset_to_row_col(search_match.tail.min(search_match.head)).0;
+            if row_visible(row){
+                self.block.draw_quad(cx, Rect{x:x + self.width - mark_w * 2., y:line_y(row), w:mark_w, h:line_h});
+            }
+        }
+        for (row, color) in diagnostics{
+            if row_visible(*row){
+                self.block.color = *color;
+                self.block.draw_quad(cx, Rect{x:x + self.width - mark_w, y:line_y(*row) - 1., w:mark_w, h:line_h + 2.});
+            }
+        }
+        self.block.color = self.cursor_color;
+        for cursor in cursors{
+            let row = text_buffer.offset_to_row_col(cursor.head).0;
+            if row_visible(row){
+                self.block.draw_quad(cx, Rect{x:x, y:line_y(row), w:2., h:line_h});
+            }
+        }
+
+        self.block.color = if self._drag.is_some(){self.slider_drag_color}else{self.slider_color};
+        self.block.draw_quad(cx, Rect{x:x, y:scroll_pos.y + self._slider_y, w:self.width, h:self._slider_h});
+    }
+
+    // the rows the selections are on
+    fn cursor_rows(text_buffer:&TextBuffer, cursors:&[Cursor])->Vec<(usize, usize)>{
+        cursors.iter().filter(|cursor| cursor.head != cursor.tail).map(|cursor|{
+            let first = text_buffer.offset_to_row_col(cursor.head.min(cursor.tail)).0;
+            let last = text_buffer.offset_to_row_col(cursor.head.max(cursor.tail)).0;
+            (first, last)
+        }).collect()
+    }
+}
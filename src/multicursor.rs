@@ -0,0 +1,112 @@
+use crate::textbuffer::*;
+use crate::textsearch::*;
+
+impl CursorSet{
+    // selects the word at the last cursor, or adds a selection on the next occurrence of what
+    // it selects, wrapping around at the end of the buffer
+    pub fn add_next_occurrence(&mut self, text_buffer:&TextBuffer){
+        if !self.set[self.last_cursor].has_selection(){
+            self.select_last_cursor_word(text_buffer);
+            return
+        }
+        let matches = Self::find_occurrences(&self.get_last_cursor_as_string(text_buffer), text_buffer);
+        let (_, end) = self.set[self.last_cursor].order();
+        let next = matches.iter().filter(|search_match| search_match.start >= end)
+            .chain(matches.iter().filter(|search_match| search_match.start < end))
+            .find(|search_match| !self.set.iter().any(|cursor| cursor.order() == (search_match.start, search_match.end)))
+            .cloned();
+        if let Some(search_match) = next{
+            let mut cursor = Cursor{
+                head:search_match.end,
+                tail:search_match.start,
+                max:0
+            };
+            cursor.calc_max(text_buffer);
+            self.set.push(cursor);
+            self.last_cursor = self.set.len() - 1;
+            self.fuse_adjacent(text_buffer);
+        }
+    }
+
+    // selects every occurrence of what the last cursor selects, or of the word it is on
+    pub fn select_all_occurrences(&mut self, text_buffer:&TextBuffer){
+        if !self.set[self.last_cursor].has_selection(){
+            self.select_last_cursor_word(text_buffer);
+        }
+        let matches = Self::find_occurrences(&self.get_last_cursor_as_string(text_buffer), text_buffer);
+        self.select_matches(&matches, text_buffer);
+    }
+
+    fn select_last_cursor_word(&mut self, text_buffer:&TextBuffer){
+        let cursor = &mut self.set[self.last_cursor];
+        let (start, end) = text_buffer.word_range(cursor.head);
+        cursor.tail = start;
+        cursor.head = end;
+        cursor.calc_max(text_buffer);
+        self.fuse_adjacent(text_buffer);
+    }
+
+    fn find_occurrences(pattern:&str, text_buffer:&TextBuffer)->Vec<SearchMatch>{
+        match TextSearch::new(pattern, &SearchOptions::default()){
+            Ok(text_search)=>text_search.find_in_text_buffer(text_buffer),
+            Err(_)=>Vec::new()
+        }
+    }
+
+    // adds a cursor on the line above the last cursor, repeating it keeps going up
+    pub fn add_cursor_above(&mut self, text_buffer:&TextBuffer){
+        let (row, _) = text_buffer.offset_to_row_col(self.set[self.last_cursor].head);
+        let row = text_buffer.folds.visible_row_above(row, 1);
+        self.add_cursor_on_row(row, text_buffer);
+    }
+
+    pub fn add_cursor_below(&mut self, text_buffer:&TextBuffer){
+        let (row, _) = text_buffer.offset_to_row_col(self.set[self.last_cursor].head);
+        let row = text_buffer.folds.visible_row_below(row, 1, text_buffer.get_line_count());
+        self.add_cursor_on_row(row, text_buffer);
+    }
+
+    fn add_cursor_on_row(&mut self, row:Option<usize>, text_buffer:&TextBuffer){
+        if let Some(row) = row{
+            // the new cursor keeps the column the last one wants to be at
+            let max = self.set[self.last_cursor].max;
+            let head = text_buffer.row_col_to_offset(row, max);
+            self.set.push(Cursor{head:head, tail:head, max:max});
+            self.last_cursor = self.set.len() - 1;
+            self.fuse_adjacent(text_buffer);
+        }
+    }
+
+    // turns every selection over several lines into one selection per line
+    pub fn split_into_lines(&mut self, text_buffer:&TextBuffer){
+        let mut set = Vec::new();
+        let mut last_cursor = 0;
+        for (index, cursor) in self.set.iter().enumerate(){
+            let (start, end) = cursor.order();
+            let (start_row, _) = text_buffer.offset_to_row_col(start);
+            let (end_row, end_col) = text_buffer.offset_to_row_col(end);
+            // a selection that ends at the start of a line doesn't take that line along
+            let end_row = if end_row > start_row && end_col == 0{end_row - 1}else{end_row};
+            for row in start_row..(end_row + 1){
+                if row != start_row && text_buffer.folds.is_hidden(row){
+                    continue
+                }
+                let line_start = text_buffer.row_col_to_offset(row, 0);
+                let line_end = line_start + text_buffer.get_line_len(row);
+                let mut cursor = Cursor{
+                    head:end.min(line_end),
+                    tail:start.max(line_start),
+                    max:0
+                };
+                cursor.calc_max(text_buffer);
+                set.push(cursor);
+            }
+            if index == self.last_cursor{
+                last_cursor = set.len() - 1;
+            }
+        }
+        self.set = set;
+        self.last_cursor = last_cursor;
+        self.fuse_adjacent(text_buffer);
+    }
+}
@@ -0,0 +1,42 @@
+use widgets::*;
+use crate::textbuffer::*;
+use crate::codeeditor::*;
+use crate::tokenizer::*;
+use crate::keymap::*;
+
+// the editor for files we have no tokenizer for
+#[derive(Clone)]
+pub struct PlainEditor{
+    pub path:String,
+    pub code_editor:CodeEditor
+}
+
+impl ElementLife for PlainEditor{
+    fn construct(&mut self, _cx:&mut Cx){}
+    fn destruct(&mut self, _cx:&mut Cx){}
+}
+
+impl Style for PlainEditor{
+    fn style(cx:&mut Cx)->Self{
+        Self{
+            path:"".to_string(),
+            code_editor:CodeEditor{
+                ..Style::style(cx)
+            }
+        }
+    }
+}
+
+impl PlainEditor{
+    pub fn handle_plain_editor(&mut self, cx:&mut Cx, event:&mut Event, text_buffer:&mut TextBuffer, keymap:&Keymap)->CodeEditorEvent{
+        self.code_editor.handle_code_editor(cx, event, text_buffer, keymap)
+    }
+
+    pub fn select_range(&mut self, cx:&mut Cx, start:usize, end:usize, text_buffer:&TextBuffer){
+        self.code_editor.select_range(cx, start, end, text_buffer);
+    }
+
+    pub fn draw_plain_editor(&mut self, cx:&mut Cx, text_buffer:&mut TextBuffer){
+        self.code_editor.draw_code_editor(cx, text_buffer, &mut PlainTokenizer::default());
+    }
+}
@@ -0,0 +1,572 @@
+// A small regex engine for searching text. Patterns compile to a program for a pike VM which
+// runs all alternatives in lockstep, so a search is linear in the length of the text whatever
+// the pattern looks like, and there is no backtracking blowup on a big file.
+// Supported: literals, . [abc] [^a-z] \d \w \s \D \W \S \b \B, ^ and $ (at line starts and ends),
+// groups ( ) and (?: ), alternation | and the quantifiers * + ? {n} {n,} {n,m}, lazy with a ? suffix.
+
+const REGEX_MAX_PROGRAM:usize = 10000;
+const REGEX_MAX_REPEAT:usize = 1000;
+
+#[derive(Clone, Debug)]
+enum ClassItem{
+    Range(char, char),
+    Digit,
+    Word,
+    Space
+}
+
+#[derive(Clone, Debug)]
+struct CharClass{
+    items:Vec<ClassItem>,
+    negated:bool
+}
+
+impl CharClass{
+    fn single(item:ClassItem, negated:bool)->CharClass{
+        CharClass{
+            items:vec![item],
+            negated:negated
+        }
+    }
+
+    fn matches(&self, ch:char, ignore_case:bool)->bool{
+        let mut found = self.matches_exact(ch);
+        if !found && ignore_case{
+            found = ch.to_lowercase().any(|ch| self.matches_exact(ch)) || ch.to_uppercase().any(|ch| self.matches_exact(ch));
+        }
+        found != self.negated
+    }
+
+    fn matches_exact(&self, ch:char)->bool{
+        self.items.iter().any(|item| match item{
+            ClassItem::Range(start, end)=>ch >= *start && ch <= *end,
+            ClassItem::Digit=>ch.is_ascii_digit(),
+            ClassItem::Word=>is_word_char(ch),
+            ClassItem::Space=>ch.is_whitespace()
+        })
+    }
+}
+
+pub fn is_word_char(ch:char)->bool{
+    ch.is_alphanumeric() || ch == '_'
+}
+
+fn chars_equal(a:char, b:char, ignore_case:bool)->bool{
+    a == b || ignore_case && a.to_lowercase().eq(b.to_lowercase())
+}
+
+#[derive(Clone, Debug)]
+enum RegexNode{
+    Empty,
+    Char(char),
+    Any,
+    Class(CharClass),
+    LineStart,
+    LineEnd,
+    WordBoundary(bool),
+    Group(Box<RegexNode>, Option<usize>),
+    Concat(Vec<RegexNode>),
+    Alternate(Vec<RegexNode>),
+    Repeat{
+        node:Box<RegexNode>,
+        min:usize,
+        max:Option<usize>,
+        greedy:bool
+    }
+}
+
+struct RegexParser{
+    chars:Vec<char>,
+    pos:usize,
+    groups:usize
+}
+
+impl RegexParser{
+    fn peek(&self)->Option<char>{
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn eat(&mut self, ch:char)->bool{
+        if self.peek() == Some(ch){
+            self.pos += 1;
+            return true
+        }
+        false
+    }
+
+    fn parse_alternate(&mut self)->Result<RegexNode, String>{
+        let mut alternates = vec![self.parse_concat()?];
+        while self.eat('|'){
+            alternates.push(self.parse_concat()?);
+        }
+        if alternates.len() == 1{
+            return Ok(alternates.pop().unwrap())
+        }
+        Ok(RegexNode::Alternate(alternates))
+    }
+
+    fn parse_concat(&mut self)->Result<RegexNode, String>{
+        let mut nodes = Vec::new();
+        while let Some(ch) = self.peek(){
+            if ch == '|' || ch == ')'{
+                break
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_quantifier(atom)?);
+        }
+        match nodes.len(){
+            0=>Ok(RegexNode::Empty),
+            1=>Ok(nodes.pop().unwrap()),
+            _=>Ok(RegexNode::Concat(nodes))
+        }
+    }
+
+    fn parse_atom(&mut self)->Result<RegexNode, String>{
+        let ch = self.peek().unwrap();
+        self.pos += 1;
+        match ch{
+            '('=>{
+                let index = if self.eat('?'){
+                    if !self.eat(':'){
+                        return Err("Only (?: groups are supported".to_string())
+                    }
+                    None
+                }
+                else{
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                let node = self.parse_alternate()?;
+                if !self.eat(')'){
+                    return Err("Missing )".to_string())
+                }
+                Ok(RegexNode::Group(Box::new(node), index))
+            },
+            '['=>self.parse_class(),
+            '.'=>Ok(RegexNode::Any),
+            '^'=>Ok(RegexNode::LineStart),
+            '$'=>Ok(RegexNode::LineEnd),
+            '*' | '+' | '?'=>Err(format!("Nothing to repeat before {}", ch)),
+            '\\'=>{
+                match self.parse_escape()?{
+                    Ok(node)=>Ok(node),
+                    Err(item)=>Ok(RegexNode::Class(CharClass::single(item, false)))
+                }
+            },
+            _=>Ok(RegexNode::Char(ch))
+        }
+    }
+
+    // an escape is either a node of its own, or a class item that can also go inside []
+    fn parse_escape(&mut self)->Result<Result<RegexNode, ClassItem>, String>{
+        let ch = match self.peek(){
+            Some(ch)=>ch,
+            None=>return Err("Pattern ends with a \\".to_string())
+        };
+        self.pos += 1;
+        Ok(match ch{
+            'd'=>Err(ClassItem::Digit),
+            'w'=>Err(ClassItem::Word),
+            's'=>Err(ClassItem::Space),
+            'D'=>Ok(RegexNode::Class(CharClass::single(ClassItem::Digit, true))),
+            'W'=>Ok(RegexNode::Class(CharClass::single(ClassItem::Word, true))),
+            'S'=>Ok(RegexNode::Class(CharClass::single(ClassItem::Space, true))),
+            'b'=>Ok(RegexNode::WordBoundary(true)),
+            'B'=>Ok(RegexNode::WordBoundary(false)),
+            'n'=>Ok(RegexNode::Char('\n')),
+            't'=>Ok(RegexNode::Char('\t')),
+            'r'=>Ok(RegexNode::Char('\r')),
+            _=>{
+                if ch.is_alphanumeric(){
+                    return Err(format!("Unknown escape \\{}", ch))
+                }
+                Ok(RegexNode::Char(ch))
+            }
+        })
+    }
+
+    fn parse_class(&mut self)->Result<RegexNode, String>{
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop{
+            let ch = match self.peek(){
+                Some(ch)=>ch,
+                None=>return Err("Missing ]".to_string())
+            };
+            self.pos += 1;
+            if ch == ']' && !first{
+                break
+            }
+            first = false;
+            let start = if ch == '\\'{
+                match self.parse_escape()?{
+                    Ok(RegexNode::Char(ch))=>ch,
+                    Ok(_)=>return Err("Only \\d \\w \\s can be used inside []".to_string()),
+                    Err(item)=>{
+                        items.push(item);
+                        continue
+                    }
+                }
+            }
+            else{
+                ch
+            };
+            // a range, unless the - is the last thing in the class
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).map_or(false, |ch| *ch != ']'){
+                self.pos += 1;
+                let mut end = self.chars[self.pos];
+                self.pos += 1;
+                if end == '\\'{
+                    end = match self.parse_escape()?{
+                        Ok(RegexNode::Char(ch))=>ch,
+                        _=>return Err("Invalid range in []".to_string())
+                    }
+                }
+                if end < start{
+                    return Err(format!("Invalid range {}-{}", start, end))
+                }
+                items.push(ClassItem::Range(start, end));
+            }
+            else{
+                items.push(ClassItem::Range(start, start));
+            }
+        }
+        Ok(RegexNode::Class(CharClass{
+            items:items,
+            negated:negated
+        }))
+    }
+
+    fn parse_quantifier(&mut self, atom:RegexNode)->Result<RegexNode, String>{
+        let mut node = atom;
+        loop{
+            let (min, max) = match self.peek(){
+                Some('*')=>{self.pos += 1; (0, None)},
+                Some('+')=>{self.pos += 1; (1, None)},
+                Some('?')=>{self.pos += 1; (0, Some(1))},
+                Some('{')=>{
+                    if let Some(range) = self.parse_counted()?{
+                        range
+                    }
+                    else{ // not a quantifier, the { is a literal
+                        return Ok(node)
+                    }
+                },
+                _=>return Ok(node)
+            };
+            let greedy = !self.eat('?');
+            node = RegexNode::Repeat{
+                node:Box::new(node),
+                min:min,
+                max:max,
+                greedy:greedy
+            };
+        }
+    }
+
+    fn parse_number(&mut self)->Option<usize>{
+        let start = self.pos;
+        let mut value:usize = 0;
+        while let Some(digit) = self.peek().and_then(|ch| ch.to_digit(10)){
+            value = value.saturating_mul(10).saturating_add(digit as usize);
+            self.pos += 1;
+        }
+        if self.pos == start{
+            return None
+        }
+        Some(value)
+    }
+
+    fn parse_counted(&mut self)->Result<Option<(usize, Option<usize>)>, String>{
+        let start = self.pos;
+        self.pos += 1;
+        let range = if let Some(min) = self.parse_number(){
+            if self.eat(','){
+                Some((min, self.parse_number()))
+            }
+            else{
+                Some((min, Some(min)))
+            }
+        }
+        else{
+            None
+        };
+        match range{
+            Some((min, max)) if self.eat('}')=>{
+                if min > REGEX_MAX_REPEAT || max.map_or(false, |max| max > REGEX_MAX_REPEAT){
+                    return Err(format!("Repeat count above {}", REGEX_MAX_REPEAT))
+                }
+                if max.map_or(false, |max| max < min){
+                    return Err("Invalid repeat count".to_string())
+                }
+                Ok(Some((min, max)))
+            },
+            _=>{
+                self.pos = start;
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum RegexInst{
+    Char(char),
+    Any,
+    Class(CharClass),
+    LineStart,
+    LineEnd,
+    WordBoundary(bool),
+    Save(usize),
+    Split(usize, usize),
+    Jump(usize),
+    Match
+}
+
+fn compile_node(node:&RegexNode, prog:&mut Vec<RegexInst>)->Result<(), String>{
+    if prog.len() > REGEX_MAX_PROGRAM{
+        return Err("Pattern is too large".to_string())
+    }
+    match node{
+        RegexNode::Empty=>(),
+        RegexNode::Char(ch)=>prog.push(RegexInst::Char(*ch)),
+        RegexNode::Any=>prog.push(RegexInst::Any),
+        RegexNode::Class(class)=>prog.push(RegexInst::Class(class.clone())),
+        RegexNode::LineStart=>prog.push(RegexInst::LineStart),
+        RegexNode::LineEnd=>prog.push(RegexInst::LineEnd),
+        RegexNode::WordBoundary(is_boundary)=>prog.push(RegexInst::WordBoundary(*is_boundary)),
+        RegexNode::Group(node, index)=>{
+            if let Some(index) = index{
+                prog.push(RegexInst::Save(index * 2));
+                compile_node(node, prog)?;
+                prog.push(RegexInst::Save(index * 2 + 1));
+            }
+            else{
+                compile_node(node, prog)?;
+            }
+        },
+        RegexNode::Concat(nodes)=>{
+            for node in nodes{
+                compile_node(node, prog)?;
+            }
+        },
+        RegexNode::Alternate(nodes)=>{
+            let mut jumps = Vec::new();
+            for (index, node) in nodes.iter().enumerate(){
+                if index == nodes.len() - 1{
+                    compile_node(node, prog)?;
+                }
+                else{
+                    let split = prog.len();
+                    prog.push(RegexInst::Split(split + 1, 0));
+                    compile_node(node, prog)?;
+                    jumps.push(prog.len());
+                    prog.push(RegexInst::Jump(0));
+                    prog[split] = RegexInst::Split(split + 1, prog.len());
+                }
+            }
+            let end = prog.len();
+            for jump in jumps{
+                prog[jump] = RegexInst::Jump(end);
+            }
+        },
+        RegexNode::Repeat{node, min, max, greedy}=>{
+            for _ in 0..*min{
+                compile_node(node, prog)?;
+            }
+            let split_to = |body:usize, out:usize|{
+                if *greedy{RegexInst::Split(body, out)}else{RegexInst::Split(out, body)}
+            };
+            if let Some(max) = max{
+                // optional copies that all skip to the end
+                let mut splits = Vec::new();
+                for _ in *min..*max{
+                    splits.push(prog.len());
+                    prog.push(RegexInst::Match);
+                    compile_node(node, prog)?;
+                }
+                let end = prog.len();
+                for split in splits{
+                    prog[split] = split_to(split + 1, end);
+                }
+            }
+            else{
+                let split = prog.len();
+                prog.push(RegexInst::Match);
+                compile_node(node, prog)?;
+                prog.push(RegexInst::Jump(split));
+                prog[split] = split_to(split + 1, prog.len());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+struct RegexThread{
+    pc:usize,
+    slots:Vec<Option<usize>>
+}
+
+struct RegexThreadList{
+    threads:Vec<RegexThread>,
+    seen:Vec<usize>,
+    generation:usize
+}
+
+impl RegexThreadList{
+    fn new(prog_len:usize)->RegexThreadList{
+        RegexThreadList{
+            threads:Vec::new(),
+            seen:vec![0; prog_len],
+            generation:1
+        }
+    }
+
+    fn clear(&mut self){
+        self.threads.truncate(0);
+        self.generation += 1;
+    }
+}
+
+#[derive(Clone)]
+pub struct Regex{
+    prog:Vec<RegexInst>,
+    slot_count:usize,
+    ignore_case:bool,
+    // the char every match starts with, so we can skip ahead when no thread is running
+    first_char:Option<char>
+}
+
+impl Regex{
+    pub fn new(pattern:&str, ignore_case:bool)->Result<Regex, String>{
+        let mut parser = RegexParser{
+            chars:pattern.chars().collect(),
+            pos:0,
+            groups:0
+        };
+        let node = parser.parse_alternate()?;
+        if parser.pos < parser.chars.len(){
+            return Err("Unmatched )".to_string())
+        }
+        Self::compile(RegexNode::Group(Box::new(node), Some(0)), parser.groups + 1, ignore_case)
+    }
+
+    // a regex that matches the pattern as plain text
+    pub fn literal(pattern:&str, ignore_case:bool)->Result<Regex, String>{
+        let node = RegexNode::Concat(pattern.chars().map(|ch| RegexNode::Char(ch)).collect());
+        Self::compile(RegexNode::Group(Box::new(node), Some(0)), 1, ignore_case)
+    }
+
+    fn compile(node:RegexNode, group_count:usize, ignore_case:bool)->Result<Regex, String>{
+        let mut prog = Vec::new();
+        compile_node(&node, &mut prog)?;
+        prog.push(RegexInst::Match);
+        let first_char = match prog.get(1){
+            Some(RegexInst::Char(ch))=>Some(*ch),
+            _=>None
+        };
+        Ok(Regex{
+            prog:prog,
+            slot_count:group_count * 2,
+            ignore_case:ignore_case,
+            first_char:first_char
+        })
+    }
+
+    pub fn group_count(&self)->usize{
+        self.slot_count / 2
+    }
+
+    // leftmost match at or after start, as the (start, end) of every group with group 0 the whole match
+    pub fn find_at(&self, text:&[char], start:usize)->Option<Vec<Option<(usize, usize)>>>{
+        let mut clist = RegexThreadList::new(self.prog.len());
+        let mut nlist = RegexThreadList::new(self.prog.len());
+        let mut slots = vec![None; self.slot_count];
+        let mut matched = None;
+        let mut pos = start;
+        while pos <= text.len(){
+            if matched.is_none(){
+                if clist.threads.len() == 0{
+                    if let Some(first_char) = self.first_char{
+                        while pos < text.len() && !chars_equal(text[pos], first_char, self.ignore_case){
+                            pos += 1;
+                        }
+                        if pos == text.len(){
+                            break
+                        }
+                    }
+                }
+                // a match starting here has the lowest priority
+                self.add_thread(&mut clist, 0, pos, text, &mut slots);
+            }
+            if clist.threads.len() == 0 && matched.is_some(){
+                break
+            }
+            nlist.clear();
+            for thread in &mut clist.threads{
+                let advance = match &self.prog[thread.pc]{
+                    RegexInst::Match=>{
+                        matched = Some(std::mem::replace(&mut thread.slots, Vec::new()));
+                        break // lower priority threads are cut off
+                    },
+                    RegexInst::Char(ch)=>pos < text.len() && chars_equal(text[pos], *ch, self.ignore_case),
+                    RegexInst::Any=>pos < text.len() && text[pos] != '\n',
+                    RegexInst::Class(class)=>pos < text.len() && class.matches(text[pos], self.ignore_case),
+                    _=>false
+                };
+                if advance{
+                    self.add_thread(&mut nlist, thread.pc + 1, pos + 1, text, &mut thread.slots);
+                }
+            }
+            std::mem::swap(&mut clist, &mut nlist);
+            pos += 1;
+        }
+        let slots = matched?;
+        Some((0..self.group_count()).map(|group| {
+            match (slots[group * 2], slots[group * 2 + 1]){
+                (Some(start), Some(end))=>Some((start, end)),
+                _=>None
+            }
+        }).collect())
+    }
+
+    // follows jumps, splits and zero width instructions, and queues the threads that consume a char
+    fn add_thread(&self, list:&mut RegexThreadList, pc:usize, pos:usize, text:&[char], slots:&mut Vec<Option<usize>>){
+        if list.seen[pc] == list.generation{
+            return
+        }
+        list.seen[pc] = list.generation;
+        match &self.prog[pc]{
+            RegexInst::Jump(to)=>self.add_thread(list, *to, pos, text, slots),
+            RegexInst::Split(first, second)=>{
+                self.add_thread(list, *first, pos, text, slots);
+                self.add_thread(list, *second, pos, text, slots);
+            },
+            RegexInst::Save(slot)=>{
+                let old = slots[*slot];
+                slots[*slot] = Some(pos);
+                self.add_thread(list, pc + 1, pos, text, slots);
+                slots[*slot] = old;
+            },
+            RegexInst::LineStart=>if pos == 0 || text[pos - 1] == '\n'{
+                self.add_thread(list, pc + 1, pos, text, slots);
+            },
+            RegexInst::LineEnd=>if pos == text.len() || text[pos] == '\n'{
+                self.add_thread(list, pc + 1, pos, text, slots);
+            },
+            RegexInst::WordBoundary(is_boundary)=>{
+                let before = pos > 0 && is_word_char(text[pos - 1]);
+                let after = pos < text.len() && is_word_char(text[pos]);
+                if (before != after) == *is_boundary{
+                    self.add_thread(list, pc + 1, pos, text, slots);
+                }
+            },
+            _=>list.threads.push(RegexThread{
+                pc:pc,
+                slots:slots.clone()
+            })
+        }
+    }
+}
@@ -0,0 +1,379 @@
+// A rope of chars. Leaves hold up to ROPE_LEAF_MAX chars, every node caches the char and
+// newline count of its subtree. Those counts are our line index: finding an offset, or the
+// start of a row, is a walk down the tree instead of a scan, and an edit only recounts the
+// leaves it touched.
+// Inserts split overfull leaves, removes fold small subtrees back into one leaf, and when
+// an edit hotspot makes the tree lopsided we flatten the leaves and rebuild it balanced.
+
+const ROPE_LEAF_MAX:usize = 1024;
+const ROPE_LEAF_FILL:usize = ROPE_LEAF_MAX / 2;
+
+#[derive(Clone)]
+pub enum RopeNode{
+    Leaf{
+        newlines:usize,
+        data:Vec<char>
+    },
+    Branch{
+        chars:usize,
+        newlines:usize,
+        depth:usize,
+        left:Box<RopeNode>,
+        right:Box<RopeNode>
+    }
+}
+
+impl Default for RopeNode{
+    fn default()->RopeNode{
+        RopeNode::leaf(Vec::new())
+    }
+}
+
+impl RopeNode{
+    fn leaf(data:Vec<char>)->RopeNode{
+        RopeNode::Leaf{
+            newlines:count_newlines(&data),
+            data:data
+        }
+    }
+
+    fn from_chars(data:&[char])->RopeNode{
+        let mut leaves = Vec::new();
+        for chunk in data.chunks(ROPE_LEAF_FILL){
+            leaves.push(chunk.to_vec());
+        }
+        Self::from_leaves(leaves)
+    }
+
+    fn from_leaves(mut leaves:Vec<Vec<char>>)->RopeNode{
+        if leaves.len() == 0{
+            return RopeNode::default()
+        }
+        if leaves.len() == 1{
+            return RopeNode::leaf(leaves.pop().unwrap())
+        }
+        let right = leaves.split_off(leaves.len() / 2);
+        Self::branch(Self::from_leaves(leaves), Self::from_leaves(right))
+    }
+
+    fn branch(left:RopeNode, right:RopeNode)->RopeNode{
+        RopeNode::Branch{
+            chars:left.chars() + right.chars(),
+            newlines:left.newlines() + right.newlines(),
+            depth:left.depth().max(right.depth()) + 1,
+            left:Box::new(left),
+            right:Box::new(right)
+        }
+    }
+
+    fn chars(&self)->usize{
+        match self{
+            RopeNode::Leaf{data, ..}=>data.len(),
+            RopeNode::Branch{chars, ..}=>*chars
+        }
+    }
+
+    fn newlines(&self)->usize{
+        match self{
+            RopeNode::Leaf{newlines, ..}=>*newlines,
+            RopeNode::Branch{newlines, ..}=>*newlines
+        }
+    }
+
+    fn depth(&self)->usize{
+        match self{
+            RopeNode::Leaf{..}=>0,
+            RopeNode::Branch{depth, ..}=>*depth
+        }
+    }
+
+    fn into_leaves(self, leaves:&mut Vec<Vec<char>>){
+        match self{
+            RopeNode::Leaf{data, ..}=>if data.len() > 0{
+                leaves.push(data)
+            },
+            RopeNode::Branch{left, right, ..}=>{
+                left.into_leaves(leaves);
+                right.into_leaves(leaves);
+            }
+        }
+    }
+
+    fn append_to(&self, out:&mut Vec<char>){
+        match self{
+            RopeNode::Leaf{data, ..}=>out.extend_from_slice(data),
+            RopeNode::Branch{left, right, ..}=>{
+                left.append_to(out);
+                right.append_to(out);
+            }
+        }
+    }
+
+    fn insert(&mut self, offset:usize, data:&[char]){
+        match self{
+            RopeNode::Leaf{newlines, data:leaf}=>{
+                if leaf.len() + data.len() <= ROPE_LEAF_MAX{
+                    leaf.splice(offset..offset, data.iter().cloned());
+                    *newlines += count_newlines(data);
+                    return
+                }
+                // overfull, turn this leaf into a subtree
+                let mut all = Vec::with_capacity(leaf.len() + data.len());
+                all.extend_from_slice(&leaf[0..offset]);
+                all.extend_from_slice(data);
+                all.extend_from_slice(&leaf[offset..]);
+                *self = Self::from_chars(&all);
+            },
+            RopeNode::Branch{chars, newlines, depth, left, right}=>{
+                let left_chars = left.chars();
+                if offset <= left_chars{
+                    left.insert(offset, data);
+                }
+                else{
+                    right.insert(offset - left_chars, data);
+                }
+                *chars += data.len();
+                *newlines = left.newlines() + right.newlines();
+                *depth = left.depth().max(right.depth()) + 1;
+            }
+        }
+    }
+
+    fn remove(&mut self, start:usize, end:usize, out:&mut Vec<char>){
+        let collapse = match self{
+            RopeNode::Leaf{newlines, data}=>{
+                *newlines -= count_newlines(&data[start..end]);
+                out.extend(data.drain(start..end));
+                return
+            },
+            RopeNode::Branch{chars, newlines, depth, left, right}=>{
+                let left_chars = left.chars();
+                if start < left_chars{
+                    left.remove(start, end.min(left_chars), out);
+                }
+                if end > left_chars{
+                    right.remove(start.max(left_chars) - left_chars, end - left_chars, out);
+                }
+                *chars -= end - start;
+                *newlines = left.newlines() + right.newlines();
+                *depth = left.depth().max(right.depth()) + 1;
+                *chars <= ROPE_LEAF_FILL || left.chars() == 0 || right.chars() == 0
+            }
+        };
+        if collapse{ // fold small or one-sided subtrees back up
+            let node = std::mem::replace(self, RopeNode::default());
+            *self = match node{
+                RopeNode::Branch{chars, left, right, ..}=>{
+                    if chars <= ROPE_LEAF_FILL{
+                        let mut leaf = Vec::with_capacity(chars);
+                        left.append_to(&mut leaf);
+                        right.append_to(&mut leaf);
+                        RopeNode::leaf(leaf)
+                    }
+                    else if left.chars() == 0{
+                        *right
+                    }
+                    else{
+                        *left
+                    }
+                },
+                leaf=>leaf
+            }
+        }
+    }
+}
+
+fn count_newlines(data:&[char])->usize{
+    data.iter().filter(|ch| **ch == '\n').count()
+}
+
+#[derive(Clone, Default)]
+pub struct Rope{
+    root:RopeNode
+}
+
+impl Rope{
+    pub fn from_chars(data:&[char])->Rope{
+        Rope{
+            root:RopeNode::from_chars(data)
+        }
+    }
+
+    pub fn len(&self)->usize{
+        self.root.chars()
+    }
+
+    pub fn newlines(&self)->usize{
+        self.root.newlines()
+    }
+
+    // the row the char at offset is on, which is the number of newlines before it
+    pub fn offset_to_row(&self, offset:usize)->usize{
+        let mut node = &self.root;
+        let mut offset = offset.min(self.len());
+        let mut row = 0;
+        loop{
+            match node{
+                RopeNode::Leaf{data, ..}=>{
+                    return row + count_newlines(&data[0..offset])
+                },
+                RopeNode::Branch{left, right, ..}=>{
+                    let left_chars = left.chars();
+                    if offset < left_chars{
+                        node = &**left;
+                    }
+                    else{
+                        offset -= left_chars;
+                        row += left.newlines();
+                        node = &**right;
+                    }
+                }
+            }
+        }
+    }
+
+    // the offset of the first char of a row, or the rope length if there is no such row
+    pub fn row_to_offset(&self, row:usize)->usize{
+        if row == 0{
+            return 0
+        }
+        if row > self.newlines(){
+            return self.len()
+        }
+        // find the row'th newline and return the offset right after it
+        let mut node = &self.root;
+        let mut row = row;
+        let mut offset = 0;
+        loop{
+            match node{
+                RopeNode::Leaf{data, ..}=>{
+                    for (index, ch) in data.iter().enumerate(){
+                        if *ch == '\n'{
+                            row -= 1;
+                            if row == 0{
+                                return offset + index + 1
+                            }
+                        }
+                    }
+                    return offset + data.len()
+                },
+                RopeNode::Branch{left, right, ..}=>{
+                    let left_newlines = left.newlines();
+                    if row <= left_newlines{
+                        node = &**left;
+                    }
+                    else{
+                        row -= left_newlines;
+                        offset += left.chars();
+                        node = &**right;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn insert(&mut self, offset:usize, data:&[char]){
+        if data.len() == 0{
+            return
+        }
+        self.root.insert(offset.min(self.len()), data);
+        self.check_balance();
+    }
+
+    pub fn remove(&mut self, start:usize, len:usize, out:&mut Vec<char>){
+        let end = (start + len).min(self.len());
+        if start >= end{
+            return
+        }
+        self.root.remove(start, end, out);
+    }
+
+    pub fn char_at(&self, offset:usize)->Option<char>{
+        self.chars_at(offset).next()
+    }
+
+    pub fn chars_at(&self, offset:usize)->RopeChars<'_>{
+        let mut stack = Vec::new();
+        let mut node = &self.root;
+        let mut offset = offset;
+        loop{
+            match node{
+                RopeNode::Leaf{data, ..}=>{
+                    return RopeChars{
+                        stack:stack,
+                        leaf:&data[..],
+                        index:offset.min(data.len())
+                    }
+                },
+                RopeNode::Branch{left, right, ..}=>{
+                    let left_chars = left.chars();
+                    if offset < left_chars{
+                        stack.push(&**right);
+                        node = &**left;
+                    }
+                    else{
+                        offset -= left_chars;
+                        node = &**right;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn to_vec(&self)->Vec<char>{
+        let mut out = Vec::with_capacity(self.len());
+        self.root.append_to(&mut out);
+        out
+    }
+
+    fn check_balance(&mut self){
+        // a balanced tree has depth log2(leaves), allow for some slack before rebuilding
+        let mut leaves = self.len() / ROPE_LEAF_FILL + 1;
+        let mut ideal_depth = 0;
+        while leaves > 1{
+            leaves >>= 1;
+            ideal_depth += 1;
+        }
+        if self.root.depth() > 2 * ideal_depth + 8{
+            let mut leaves = Vec::new();
+            std::mem::replace(&mut self.root, RopeNode::default()).into_leaves(&mut leaves);
+            self.root = RopeNode::from_leaves(leaves);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RopeChars<'a>{
+    stack:Vec<&'a RopeNode>,
+    leaf:&'a [char],
+    index:usize
+}
+
+impl<'a> Iterator for RopeChars<'a>{
+    type Item = char;
+
+    fn next(&mut self)->Option<char>{
+        loop{
+            if self.index < self.leaf.len(){
+                let ch = self.leaf[self.index];
+                self.index += 1;
+                return Some(ch)
+            }
+            // walk to the next leaf
+            let mut node = self.stack.pop()?;
+            loop{
+                match node{
+                    RopeNode::Leaf{data, ..}=>{
+                        self.leaf = &data[..];
+                        self.index = 0;
+                        break
+                    },
+                    RopeNode::Branch{left, right, ..}=>{
+                        self.stack.push(&**right);
+                        node = &**left;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,23 +1,13 @@
 use widgets::*;
 use crate::textbuffer::*;
 use crate::codeeditor::*;
+use crate::tokenizer::*;
+use crate::keymap::*;
 
 #[derive(Clone)]
 pub struct RustEditor{
     pub path:String,
-    pub code_editor:CodeEditor,
-    pub col_whitespace:Color,
-    pub col_keyword:Color,
-    pub col_flow_keyword:Color,
-    pub col_identifier:Color,
-    pub col_operator:Color,
-    pub col_function:Color,
-    pub col_number:Color,
-    pub col_paren:Color,
-    pub col_comment:Color,
-    pub col_string:Color,
-    pub col_delim:Color,
-    pub col_type:Color
+    pub code_editor:CodeEditor
 }
 
 impl ElementLife for RustEditor{
@@ -31,20 +21,7 @@ impl Style for RustEditor{
             path:"".to_string(),
             code_editor:CodeEditor{
                 ..Style::style(cx)
-            },
-            // syntax highlighting colors
-            col_whitespace:color256(110,110,110),
-            col_keyword:color256(91,155,211),
-            col_flow_keyword:color256(196,133,190),
-            col_identifier:color256(212,212,212),
-            col_operator:color256(212,212,212),
-            col_function:color256(220,220,174),
-            col_type:color256(86,201,177),
-            col_number:color256(182,206,170),
-            col_comment:color256(99,141,84),
-            col_paren:color256(212,212,212),
-            col_string:color256(204,145,123),
-            col_delim:color256(212,212,212)
+            }
         };
         //tab.animator.default = tab.anim_default(cx);
         rust_editor
@@ -57,215 +34,374 @@ pub enum RustEditorEvent{
     Change
 }
 
-
 impl RustEditor{
-    pub fn handle_rust_editor(&mut self, cx:&mut Cx, event:&mut Event, text_buffer:&mut TextBuffer)->CodeEditorEvent{
-        match self.code_editor.handle_code_editor(cx, event, text_buffer){
-            _=>()
-        }
-        CodeEditorEvent::None
+    pub fn handle_rust_editor(&mut self, cx:&mut Cx, event:&mut Event, text_buffer:&mut TextBuffer, keymap:&Keymap)->CodeEditorEvent{
+        self.code_editor.handle_code_editor(cx, event, text_buffer, keymap)
+    }
+
+    pub fn select_range(&mut self, cx:&mut Cx, start:usize, end:usize, text_buffer:&TextBuffer){
+        self.code_editor.select_range(cx, start, end, text_buffer);
+    }
+
+    pub fn draw_rust_editor(&mut self, cx:&mut Cx, text_buffer:&mut TextBuffer){
+        self.code_editor.draw_code_editor(cx, text_buffer, &mut RustTokenizer::default());
     }
+}
+
+// Lexes Rust a line at a time. Block comments, strings and raw strings can run over
+// multiple lines, the mode says which one we are in at the start of a line.
+#[derive(Clone, Default)]
+pub struct RustTokenizer{
+    pub mode:RustTokenizerMode
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RustTokenizerMode{
+    Code,
+    BlockComment{depth:u32, is_doc:bool},
+    String,
+    RawString{hashes:u32}
+}
+
+impl Default for RustTokenizerMode{
+    fn default()->Self{
+        RustTokenizerMode::Code
+    }
+}
 
-    pub fn draw_rust_editor(&mut self, cx:&mut Cx, text_buffer:&TextBuffer){
-        if !self.code_editor.begin_code_editor(cx, text_buffer){
-            return
+impl Tokenizer for RustTokenizer{
+    fn next_token<'a>(&mut self, state:&mut TokenizerState<'a>, chunk:&mut Vec<char>)->TokenType{
+        state.advance_with_cur();
+
+        match state.cur{
+            '\0'=>{ // eof
+                return TokenType::Eof
+            },
+            '\n'=>{
+                chunk.push('\n');
+                return TokenType::Newline
+            },
+            _=>()
         }
 
-        let mut chunk = Vec::new();
-        let mut state = TokenizerState::new(text_buffer);
-        
-        let mut after_newline = true; 
-        let mut last_tabs = 0;
-        let mut newline_tabs = 0;
+        // continue what the previous line left open
+        match self.mode{
+            RustTokenizerMode::Code=>(),
+            RustTokenizerMode::BlockComment{mut depth, is_doc}=>{
+                Self::parse_block_comment_tail(state, chunk, &mut depth);
+                self.mode = if depth > 0{RustTokenizerMode::BlockComment{depth:depth, is_doc:is_doc}}else{RustTokenizerMode::Code};
+                return if is_doc{TokenType::DocComment}else{TokenType::Comment}
+            },
+            RustTokenizerMode::String=>{
+                if Self::parse_string_tail(state, chunk){
+                    self.mode = RustTokenizerMode::Code;
+                }
+                return TokenType::String
+            },
+            RustTokenizerMode::RawString{hashes}=>{
+                if Self::parse_raw_string_tail(state, chunk, hashes){
+                    self.mode = RustTokenizerMode::Code;
+                }
+                return TokenType::String
+            }
+        }
 
-        loop{
-            //let bit = rust_colorizer.next(&mut chunk, &self.rust_colors);
-            let mut do_newline = false;
-            let color;
-            state.advance_with_cur();
-            
-            match state.cur{
-                '\0'=>{ // eof
-                    break;
-                },
-                '\n'=>{
-                    color = self.col_whitespace;
-                    // do a newline
-                    if after_newline{
-                        self.code_editor.draw_tab_lines(cx, last_tabs);
-                    }
-                    else {
-                        last_tabs = newline_tabs;
-                    }
-                    chunk.push('\n');
-                    do_newline = true;
-                    after_newline = true;
-                    newline_tabs = 0;
-                    // spool up the next char
-                },
-                ' ' | '\t'=>{ // eat as many spaces as possible
-                    color = self.col_whitespace;
-                    if after_newline{ // consume spaces in groups of 4
-                        chunk.push(state.cur);
-                        
-                        let mut counter = 1;
-                        while state.next == ' ' || state.next == '\t'{
-                            chunk.push(state.next);
-                            counter += 1;
-                            state.advance();
-                        }
-                        let tabs = counter >> 2;
-                        last_tabs = tabs;
-                        newline_tabs = tabs;
-                        self.code_editor.draw_tab_lines(cx, tabs);
-                    }
-                    else{
-                        chunk.push(state.cur);
-                        while state.next == ' '{
-                            chunk.push(state.next);
-                            state.advance();
-                        }
+        match state.cur{
+            ' ' | '\t'=>{ // eat as many spaces as possible
+                chunk.push(state.cur);
+                while state.next == ' ' || state.next == '\t'{
+                    chunk.push(state.next);
+                    state.advance();
+                }
+                TokenType::Whitespace
+            },
+            '/'=>{ // parse comment
+                chunk.push(state.cur);
+                if state.next == '/'{
+                    chunk.push(state.next);
+                    state.advance();
+                    // doc comments are /// and //!, but //// is a normal comment again
+                    let is_doc = state.next == '!' || state.next == '/' && state.peek_after_next() != '/';
+                    while state.next != '\n' && state.next != '\0'{
+                        chunk.push(state.next);
+                        state.advance();
                     }
-                },
-                '/'=>{ // parse comment
-                    after_newline = false;
+                    if is_doc{TokenType::DocComment}else{TokenType::Comment}
+                }
+                else if state.next == '*'{
+                    state.advance_with_cur();
                     chunk.push(state.cur);
-                    if state.next == '/'{
-                        while state.next != '\n' && state.next != '\0'{
-                            chunk.push(state.next);
-                            state.advance();
-                        }
-                        color = self.col_comment;
-                    }
-                    else{
-                        color = self.col_operator;
+                    // same for /** and /*!, where /**/ and /*** are normal comments
+                    let is_doc = state.next == '!' || state.next == '*' && state.peek_after_next() != '*' && state.peek_after_next() != '/';
+                    let mut depth = 1;
+                    if state.next != '\n' && state.next != '\0'{
+                        state.advance_with_cur();
+                        Self::parse_block_comment_tail(state, chunk, &mut depth);
                     }
-                },
-                '\''=>{ // parse char literal or lifetime annotation
-
-                    after_newline = false;
-                    chunk.push(state.cur);
-
-                    if Self::parse_rust_escape_char(&mut state, &mut chunk){ // escape char or unicode
-                        if state.next == '\''{ // parsed to closing '
-                            chunk.push(state.next);
-                            state.advance();
-                            color = self.col_string;
-                        }
-                        else{
-                            color = self.col_comment;
-                        }
+                    if depth > 0{
+                        self.mode = RustTokenizerMode::BlockComment{depth:depth, is_doc:is_doc};
                     }
-                    else{ // parse a single char or lifetime
-                        let offset = state.offset;
-                        if Self::parse_rust_ident_tail(&mut state, &mut chunk) && ((state.offset - offset) > 1 || state.next != '\''){
-                            color = self.col_keyword;
+                    if is_doc{TokenType::DocComment}else{TokenType::Comment}
+                }
+                else{
+                    TokenType::Operator
+                }
+            },
+            '\''=>{ // parse char literal or lifetime annotation
+                chunk.push(state.cur);
+                Self::parse_rust_char_or_lifetime(state, chunk)
+            },
+            '"'=>{ // parse string
+                self.parse_rust_string(state, chunk)
+            },
+            '0'..='9'=>{ // try to parse numbers
+                chunk.push(state.cur);
+                Self::parse_rust_number_tail(state, chunk);
+                TokenType::Number
+            },
+            '(' | ')' | '{' | '}' | '[' | ']'=>{
+                chunk.push(state.cur);
+                TokenType::Paren
+            },
+            'r' if state.next == '"' || state.next == '#' && (state.peek_after_next() == '"' || state.peek_after_next() == '#')=>{
+                chunk.push(state.cur);
+                self.parse_rust_raw_string(state, chunk)
+            },
+            'b' if state.next == '"'=>{ // byte string
+                chunk.push(state.cur);
+                state.advance_with_cur();
+                self.parse_rust_string(state, chunk)
+            },
+            'b' if state.next == '\''=>{ // byte char
+                chunk.push(state.cur);
+                state.advance_with_cur();
+                chunk.push(state.cur);
+                Self::parse_rust_char_or_lifetime(state, chunk)
+            },
+            'b' if state.next == 'r' && (state.peek_after_next() == '"' || state.peek_after_next() == '#')=>{ // raw byte string
+                chunk.push(state.cur);
+                state.advance_with_cur();
+                chunk.push(state.cur);
+                self.parse_rust_raw_string(state, chunk)
+            },
+            'a'..='z' | '_'=>{ // try to parse keywords or identifiers
+                chunk.push(state.cur);
+                Self::parse_rust_ident_tail(state, chunk);
+                let ident:String = chunk.iter().collect();
+                match ident.as_ref(){
+                    "break" | "continue" | "else" | "for" | "if" | "loop" | "match" | "return" | "while" | "yield"=>{
+                        TokenType::Flow
+                    },
+                    "as" | "async" | "await" | "const" | "crate" | "dyn" | "enum" | "extern" | "false" | "fn" | "impl" | "in" |
+                    "let" | "mod" | "move" | "mut" | "pub" | "ref" | "self" | "static" | "struct" | "super" | "trait" | "true" |
+                    "type" | "union" | "unsafe" | "use" | "where"=>{
+                        TokenType::Keyword
+                    },
+                    _=>{
+                        if state.next == '(' || state.next == '!'{
+                            TokenType::Call
                         }
                         else{
-                            if (state.offset - offset) == 0{ // not an identifier char
-                                chunk.push(state.next);
-                                state.advance();
-                            }
-                            if state.next == '\''{ // lifetime identifier
-                                chunk.push(state.next);
-                                state.advance();
-                            }
-                            color = self.col_string;
+                            TokenType::Identifier
                         }
                     }
-                },
-                '"'=>{ // parse string
-                    after_newline = false;
-                    chunk.push(state.cur);
-                    state.prev = '\0';
-                    while state.next != '\0' && state.next!='\n' && (state.next != '"' || state.prev != '\\' && state.cur == '\\' && state.next == '"'){
-                        chunk.push(state.next);
-                        state.advance_with_prev();
-                    };
+                }
+            },
+            'A'..='Z'=>{
+                chunk.push(state.cur);
+                Self::parse_rust_ident_tail(state, chunk);
+                if chunk[..] == ['S', 'e', 'l', 'f']{
+                    TokenType::Keyword
+                }
+                else{
+                    TokenType::TypeName
+                }
+            },
+            ',' | ';' | ':' | '.'=>{
+                chunk.push(state.cur);
+                TokenType::Delimiter
+            },
+            '+' | '-' | '*' | '%' | '^' | '!' | '&' | '|' | '=' | '<' | '>' | '@' | '#' | '$' | '?' | '~'=>{
+                chunk.push(state.cur);
+                TokenType::Operator
+            },
+            _=>{
+                chunk.push(state.cur);
+                // unknown type
+                TokenType::Identifier
+            }
+        }
+    }
+
+    fn line_state(&self)->u64{
+        match self.mode{
+            RustTokenizerMode::Code=>0,
+            RustTokenizerMode::BlockComment{depth, is_doc}=>1 | (is_doc as u64) << 8 | (depth as u64) << 16,
+            RustTokenizerMode::String=>2,
+            RustTokenizerMode::RawString{hashes}=>3 | (hashes as u64) << 16
+        }
+    }
+
+    fn set_line_state(&mut self, line_state:u64){
+        let value = (line_state >> 16) as u32;
+        self.mode = match line_state & 0xff{
+            1=>RustTokenizerMode::BlockComment{depth:value, is_doc:line_state & 0x100 != 0},
+            2=>RustTokenizerMode::String,
+            3=>RustTokenizerMode::RawString{hashes:value},
+            _=>RustTokenizerMode::Code
+        };
+    }
+
+    fn line_comment(&self)->Option<&'static str>{
+        Some("//")
+    }
+
+    fn block_comment(&self)->Option<(&'static str, &'static str)>{
+        Some(("/*", "*/"))
+    }
+
+    // no ' because of lifetimes
+    fn auto_close_pairs(&self)->&'static [(char, char)]{
+        &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"')]
+    }
+}
+
+impl RustTokenizer{
+    // the tail functions start at state.cur and stop at the end of the line
+
+    // returns with depth 0 if the comment closed
+    fn parse_block_comment_tail<'a>(state:&mut TokenizerState<'a>, chunk:&mut Vec<char>, depth:&mut u32){
+        let mut last = '\0';
+        loop{
+            let ch = state.cur;
+            chunk.push(ch);
+            if last == '/' && ch == '*'{ // comments nest
+                *depth += 1;
+                last = '\0';
+            }
+            else if last == '*' && ch == '/'{
+                *depth -= 1;
+                last = '\0';
+                if *depth == 0{
+                    return
+                }
+            }
+            else{
+                last = ch;
+            }
+            if state.next == '\n' || state.next == '\0'{
+                return
+            }
+            state.advance_with_cur();
+        }
+    }
+
+    // returns true if the string closed
+    fn parse_string_tail<'a>(state:&mut TokenizerState<'a>, chunk:&mut Vec<char>)->bool{
+        loop{
+            let ch = state.cur;
+            chunk.push(ch);
+            if ch == '\\'{ // a \ at the end of the line continues the string
+                if state.next != '\n' && state.next != '\0'{
                     chunk.push(state.next);
                     state.advance();
-                    color = self.col_string;
-                },
-                '0'...'9'=>{ // try to parse numbers
-                    after_newline = false;
-                    color = self.col_number;
-                    chunk.push(state.cur);
-                    Self::parse_rust_number_tail(&mut state, &mut chunk);
-                },
-                '(' | ')'=>{
-                    after_newline = false;
-                    chunk.push(state.cur);
-                    color = self.col_paren;
-                },
-                '{' | '}'=>{
-                    after_newline = false;
-                    chunk.push(state.cur);
-                    color = self.col_paren;
-                },
-                '[' | ']'=>{
-                    after_newline = false;
-                    chunk.push(state.cur);
-                    color = self.col_paren;
-                },
-                'a'...'z'=>{ // try to parse keywords or identifiers
-                    after_newline = false;
-                    chunk.push(state.cur);
-                    let mut keyword_type = Self::parse_rust_lc_keyword(&mut state, &mut chunk);
-
-                    if Self::parse_rust_ident_tail(&mut state, &mut chunk){
-                        keyword_type = KeywordType::None;
-                    }
-                    match keyword_type{
-                        KeywordType::Normal=>{
-                            color = self.col_keyword;
-                        },
-                        KeywordType::Flow=>{
-                            color = self.col_flow_keyword;
-                        },
-                        KeywordType::None=>{
-                            if state.next == '(' || state.next == '!'{
-                                color = self.col_function;
-                            }
-                            else{
-                                color = self.col_identifier;
-                            }
-                        }
-                    }
-                },
-                'A'...'Z'=>{
-                    after_newline = false;
-                    chunk.push(state.cur);
-                    let mut is_keyword = false;
-                    if state.cur == 'S'{
-                        if state.keyword(&mut chunk, "elf"){
-                            is_keyword = true;
-                        }
-                    }
-                    if Self::parse_rust_ident_tail(&mut state, &mut chunk){
-                        is_keyword = false;
-                    }
-                    if is_keyword{
-                        color = self.col_keyword;
-                    }
-                    else{
-                        color = self.col_type;
-                    }
-                },
-                _=>{
-                    after_newline = false;
-                    chunk.push(state.cur);
-                    // unknown type
-                    color = self.col_identifier;
                 }
             }
-            self.code_editor.draw_text(cx, &chunk, state.offset, color);
-            chunk.truncate(0);
-            if do_newline{
-                self.code_editor.new_line(cx);
+            else if ch == '"'{
+                return true
             }
+            if state.next == '\n' || state.next == '\0'{
+                return false
+            }
+            state.advance_with_cur();
         }
-        
-        self.code_editor.end_code_editor(cx, text_buffer);
+    }
+
+    // a raw string ends at a " followed by as many # as it started with
+    fn parse_raw_string_tail<'a>(state:&mut TokenizerState<'a>, chunk:&mut Vec<char>, hashes:u32)->bool{
+        let mut closing = None;
+        loop{
+            let ch = state.cur;
+            chunk.push(ch);
+            closing = if ch == '"'{Some(0)}else if ch == '#'{closing.map(|count| count + 1)}else{None};
+            if closing == Some(hashes){
+                return true
+            }
+            if state.next == '\n' || state.next == '\0'{
+                return false
+            }
+            state.advance_with_cur();
+        }
+    }
+
+    // state.cur is the opening "
+    fn parse_rust_string<'a>(&mut self, state:&mut TokenizerState<'a>, chunk:&mut Vec<char>)->TokenType{
+        chunk.push(state.cur);
+        let closed = if state.next == '\n' || state.next == '\0'{
+            false
+        }
+        else{
+            state.advance_with_cur();
+            Self::parse_string_tail(state, chunk)
+        };
+        if !closed{
+            self.mode = RustTokenizerMode::String;
+        }
+        TokenType::String
+    }
+
+    // the r is in the chunk, the #'s and opening " are next
+    fn parse_rust_raw_string<'a>(&mut self, state:&mut TokenizerState<'a>, chunk:&mut Vec<char>)->TokenType{
+        let mut hashes = 0;
+        while state.next == '#'{
+            chunk.push(state.next);
+            state.advance();
+            hashes += 1;
+        }
+        if state.next != '"'{ // r#ident or a typo
+            return TokenType::Identifier
+        }
+        state.advance_with_cur();
+        chunk.push(state.cur);
+        let closed = if state.next == '\n' || state.next == '\0'{
+            false
+        }
+        else{
+            state.advance_with_cur();
+            Self::parse_raw_string_tail(state, chunk, hashes)
+        };
+        if !closed{
+            self.mode = RustTokenizerMode::RawString{hashes:hashes};
+        }
+        TokenType::String
+    }
+
+    // the ' is in the chunk
+    fn parse_rust_char_or_lifetime<'a>(state:&mut TokenizerState<'a>, chunk:&mut Vec<char>)->TokenType{
+        if Self::parse_rust_escape_char(state, chunk){ // escape char or unicode
+            if state.next == '\''{ // parsed to closing '
+                chunk.push(state.next);
+                state.advance();
+                return TokenType::String
+            }
+            return TokenType::Comment
+        }
+        if state.next == '\n' || state.next == '\0'{
+            return TokenType::String
+        }
+        let is_ident = state.next_is_letter() || state.next == '_';
+        chunk.push(state.next);
+        state.advance();
+        if state.next == '\''{ // a single char
+            chunk.push(state.next);
+            state.advance();
+            return TokenType::String
+        }
+        if is_ident{ // a lifetime
+            Self::parse_rust_ident_tail(state, chunk);
+            return TokenType::Keyword
+        }
+        TokenType::String
     }
 
     fn parse_rust_ident_tail<'a>(state:&mut TokenizerState<'a>, chunk:&mut Vec<char>)->bool{
@@ -298,7 +434,7 @@ impl RustEditor{
                     }
                 }
             }
-            else{
+            else if state.next != '\n' && state.next != '\0'{
                 // its a single char escape TODO limit this to valid escape chars
                 chunk.push(state.next);
                 state.advance();
@@ -309,7 +445,7 @@ impl RustEditor{
     }
 
     fn parse_rust_number_tail<'a>(state:&mut TokenizerState<'a>, chunk:&mut Vec<char>){
-        if state.next == 'x'{ // parse a hex number
+        if state.cur == '0' && (state.next == 'x' || state.next == 'o' || state.next == 'b'){ // hex, octal or binary
             chunk.push(state.next);
             state.advance();
             while state.next_is_hex() || state.next == '_'{
@@ -317,203 +453,165 @@ impl RustEditor{
                 state.advance();
             }
         }
-        else if state.next == 'b'{ // parse a binary
-            chunk.push(state.next);
-            state.advance();
-            while state.next == '0' || state.next =='1' || state.next == '_'{
-                chunk.push(state.next);
-                state.advance();
-            }
-        }
         else{
             while state.next_is_digit() || state.next == '_'{
                 chunk.push(state.next);
                 state.advance();
             }
-            if state.next == 'u' || state.next == 'i'{
+            // 1. is a float, but 1..2 is a range and 1.max(2) a method call
+            let after_dot = state.peek_after_next();
+            if state.next == '.' && after_dot != '.' && after_dot != '_' && !after_dot.is_ascii_alphabetic(){
                 chunk.push(state.next);
                 state.advance();
-                if state.keyword(chunk, "8"){
-                }
-                else if state.keyword(chunk, "16"){
-                }
-                else if state.keyword(chunk,"32"){
-                }
-                else if state.keyword(chunk,"64"){
+                while state.next_is_digit() || state.next == '_'{
+                    chunk.push(state.next);
+                    state.advance();
                 }
             }
-            else if state.next == '.'{
+            if state.next == 'e' || state.next == 'E'{ // exponent
                 chunk.push(state.next);
                 state.advance();
-                // again eat as many numbers as possible
-                while state.next_is_digit() || state.next == '_'{
+                if state.next == '+' || state.next == '-'{
                     chunk.push(state.next);
                     state.advance();
                 }
-                if state.next == 'f' { // the f32, f64 postfix
+                while state.next_is_digit() || state.next == '_'{
                     chunk.push(state.next);
                     state.advance();
-                    if state.keyword(chunk,"32"){
-                    }
-                    else if state.keyword(chunk,"64"){
-                    }
                 }
             }
         }
+        // the type suffix, like u8 or f32
+        Self::parse_rust_ident_tail(state, chunk);
     }
+}
 
-    fn parse_rust_lc_keyword<'a>(state:&mut TokenizerState<'a>, chunk:&mut Vec<char>)->KeywordType{
-        match state.cur{
-            'a'=>{
-                if state.keyword(chunk,"s"){
-                    return KeywordType::Normal
-                }
-            },
-            'b'=>{ 
-                if state.keyword(chunk,"reak"){
-                    return KeywordType::Flow
-                }
-            },
-            'c'=>{
-                if state.keyword(chunk,"o"){
-                    if state.keyword(chunk,"nst"){
-                        return KeywordType::Normal
-                    }
-                    else if state.keyword(chunk,"ntinue"){
-                        return KeywordType::Flow
-                    }
-                }
-                else if state.keyword(chunk,"rate"){
-                    return KeywordType::Normal
-                }
-            },
-            'e'=>{
-                if state.keyword(chunk,"lse"){
-                    return KeywordType::Flow
-                }
-                else if state.keyword(chunk,"num"){
-                    return KeywordType::Normal
-                }
-                else if state.keyword(chunk,"xtern"){
-                    return KeywordType::Normal
-                }
-            },
-            'f'=>{
-                if state.keyword(chunk,"alse"){
-                    return KeywordType::Normal
-                }
-                else if state.keyword(chunk,"n"){
-                    return KeywordType::Normal
-                }
-                else if state.keyword(chunk,"or"){
-                    return KeywordType::Flow
-                }
-            },
-            'i'=>{
-                if state.keyword(chunk,"f"){
-                    return KeywordType::Flow
-                }
-                else if state.keyword(chunk,"mpl"){
-                    return KeywordType::Normal
-                }
-                else if state.keyword(chunk,"in"){
-                    return KeywordType::Normal
-                }
-            },
-            'l'=>{
-                if state.keyword(chunk,"et"){
-                    return KeywordType::Normal
-                }
-                else if state.keyword(chunk,"oop"){
-                    return KeywordType::Flow
-                }
-            },
-            'm'=>{
-                if state.keyword(chunk,"atc"){
-                    return KeywordType::Flow
-                }
-                else if state.keyword(chunk,"o"){
-                    if state.keyword(chunk,"d"){
-                        return KeywordType::Normal
-                    }
-                    else if state.keyword(chunk,"ve"){
-                        return KeywordType::Normal
-                    }
-                }
-                else if state.keyword(chunk,"ut"){
-                    return KeywordType::Normal
-                }
-            },
-            'p'=>{ // pub
-                if state.keyword(chunk,"ub"){ 
-                    return KeywordType::Normal
-                }
-            },
-            'r'=>{
-                if state.keyword(chunk,"e"){
-                    if state.keyword(chunk,"f"){
-                        return KeywordType::Normal
-                    }
-                    else if state.keyword(chunk,"turn"){
-                        return KeywordType::Flow
-                    }
-                }
-            },
-            's'=>{
-                if state.keyword(chunk,"elf"){
-                    return KeywordType::Normal
-                }
-                if state.keyword(chunk,"uper"){
-                    return KeywordType::Normal
-                }
-                else if state.keyword(chunk,"t"){
-                    if state.keyword(chunk,"atic"){
-                        return KeywordType::Normal
-                    }
-                    else if state.keyword(chunk,"ruct"){
-                        return KeywordType::Normal
-                    }
-                }
-            },
-            't'=>{
-                if state.keyword(chunk,"ype"){
-                    return KeywordType::Normal
-                }
-                else if state.keyword(chunk,"r"){
-                    if state.keyword(chunk,"rait"){
-                        return KeywordType::Normal
-                    }
-                    else if state.keyword(chunk,"ue"){
-                        return KeywordType::Normal
-                    }
-                }
-            },
-            'u'=>{ // use
-                if state.keyword(chunk,"se"){ 
-                    return KeywordType::Normal
-                }
-                else if state.keyword(chunk,"nsafe"){ 
-                    return KeywordType::Normal
-                }
-            },
-            'w'=>{ // use
-                if state.keyword(chunk,"h"){
-                    if state.keyword(chunk,"ere"){
-                        return KeywordType::Normal
-                    }
-                    else if state.keyword(chunk,"ile"){
-                        return KeywordType::Flow
-                    }
-                }
-            }, 
-            _=>{}
-        }     
-        KeywordType::None
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    // the tokens without whitespace and newlines, and the mode every line starts in
+    fn lex(source:&str)->(Vec<(TokenType, String)>, Vec<RustTokenizerMode>){
+        let mut text_buffer = TextBuffer::default();
+        text_buffer.load_buffer(&source.as_bytes().to_vec()).unwrap();
+        let mut tokenizer = RustTokenizer::default();
+        let mut state = TokenizerState::new(&text_buffer);
+        let mut chunk = Vec::new();
+        let mut tokens = Vec::new();
+        let mut modes = vec![tokenizer.mode];
+        loop{
+            let token_type = tokenizer.next_token(&mut state, &mut chunk);
+            match token_type{
+                TokenType::Eof=>break,
+                TokenType::Newline=>{
+                    // the line state round trips through the cache as a number
+                    let mut restored = RustTokenizer::default();
+                    restored.set_line_state(tokenizer.line_state());
+                    assert_eq!(restored.mode, tokenizer.mode);
+                    modes.push(tokenizer.mode);
+                },
+                TokenType::Whitespace=>(),
+                _=>tokens.push((token_type, chunk.iter().collect()))
+            }
+            chunk.truncate(0);
+        }
+        (tokens, modes)
     }
-}
 
-enum KeywordType{
-    None,
-    Normal,
-    Flow,
-}
\ No newline at end of file
+    fn token(token_type:TokenType, text:&str)->(TokenType, String){
+        (token_type, text.to_string())
+    }
+
+    #[test]
+    fn nested_block_comments(){
+        let (tokens, modes) = lex("/* a /* b */ c */ x");
+        assert_eq!(tokens, vec![token(TokenType::Comment, "/* a /* b */ c */"), token(TokenType::Identifier, "x")]);
+        assert_eq!(modes, vec![RustTokenizerMode::Code]);
+
+        let (tokens, modes) = lex("/* a /*\nb */\nc */ x");
+        assert_eq!(tokens, vec![
+            token(TokenType::Comment, "/* a /*"),
+            token(TokenType::Comment, "b */"),
+            token(TokenType::Comment, "c */"),
+            token(TokenType::Identifier, "x")
+        ]);
+        assert_eq!(modes, vec![
+            RustTokenizerMode::Code,
+            RustTokenizerMode::BlockComment{depth:2, is_doc:false},
+            RustTokenizerMode::BlockComment{depth:1, is_doc:false}
+        ]);
+    }
+
+    #[test]
+    fn raw_strings(){
+        let (tokens, _) = lex("r#\"a \"b\" c\"# x");
+        assert_eq!(tokens, vec![token(TokenType::String, "r#\"a \"b\" c\"#"), token(TokenType::Identifier, "x")]);
+
+        let (tokens, _) = lex("r##\"a \"# b\"## x");
+        assert_eq!(tokens, vec![token(TokenType::String, "r##\"a \"# b\"##"), token(TokenType::Identifier, "x")]);
+
+        let (tokens, modes) = lex("r##\"a\n\"#\nb\"## x");
+        assert_eq!(tokens, vec![
+            token(TokenType::String, "r##\"a"),
+            token(TokenType::String, "\"#"),
+            token(TokenType::String, "b\"##"),
+            token(TokenType::Identifier, "x")
+        ]);
+        assert_eq!(modes, vec![
+            RustTokenizerMode::Code,
+            RustTokenizerMode::RawString{hashes:2},
+            RustTokenizerMode::RawString{hashes:2}
+        ]);
+    }
+
+    #[test]
+    fn byte_literals(){
+        let (tokens, _) = lex("b\"a\\\"b\" b'x' b'\\n' br\"c\\\" d");
+        assert_eq!(tokens, vec![
+            token(TokenType::String, "b\"a\\\"b\""),
+            token(TokenType::String, "b'x'"),
+            token(TokenType::String, "b'\\n'"),
+            token(TokenType::String, "br\"c\\\""),
+            token(TokenType::Identifier, "d")
+        ]);
+    }
+
+    #[test]
+    fn doc_comments(){
+        let (tokens, _) = lex("/// doc\n//! inner\n//// not doc\n// plain");
+        assert_eq!(tokens, vec![
+            token(TokenType::DocComment, "/// doc"),
+            token(TokenType::DocComment, "//! inner"),
+            token(TokenType::Comment, "//// not doc"),
+            token(TokenType::Comment, "// plain")
+        ]);
+    }
+
+    #[test]
+    fn multiline_string(){
+        let (tokens, modes) = lex("let s = \"a\nb\\\"\nc\"; x");
+        assert_eq!(tokens, vec![
+            token(TokenType::Keyword, "let"),
+            token(TokenType::Identifier, "s"),
+            token(TokenType::Operator, "="),
+            token(TokenType::String, "\"a"),
+            token(TokenType::String, "b\\\""),
+            token(TokenType::String, "c\""),
+            token(TokenType::Delimiter, ";"),
+            token(TokenType::Identifier, "x")
+        ]);
+        assert_eq!(modes, vec![RustTokenizerMode::Code, RustTokenizerMode::String, RustTokenizerMode::String]);
+    }
+
+    #[test]
+    fn lifetimes_and_chars(){
+        let (tokens, modes) = lex("fn f<'a>(x:&'a str)->char{'a'}\nx");
+        let lifetimes:Vec<&(TokenType, String)> = tokens.iter().filter(|(_, text)| text.starts_with('\'')).collect();
+        assert_eq!(lifetimes, vec![
+            &token(TokenType::Keyword, "'a"),
+            &token(TokenType::Keyword, "'a"),
+            &token(TokenType::String, "'a'")
+        ]);
+        assert_eq!(modes, vec![RustTokenizerMode::Code, RustTokenizerMode::Code]);
+    }
+}
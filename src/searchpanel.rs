@@ -0,0 +1,486 @@
+use widgets::*;
+use std::collections::HashMap;
+use crate::textbuffer::*;
+use crate::textsearch::*;
+use crate::findbar::*;
+
+// Find in files. Files are read a few at a time and their matches stream into the list as
+// they come in, open buffers are searched as they are so unsaved edits are found too.
+#[derive(Clone)]
+pub struct SearchPanel{
+    pub view:View<ScrollBar>,
+    pub bg:Quad,
+    pub header_bg:Quad,
+    pub marker:Quad,
+    pub text:Text,
+    pub row_height:f32,
+    pub file_color:Color,
+    pub line_color:Color,
+    pub preview_color:Color,
+    pub match_color:Color,
+    pub match_marker_color:Color,
+    pub selected_marker_color:Color,
+    pub query:String,
+    pub replace:String,
+    pub field:FindBarField,
+    pub options:SearchOptions,
+    pub search:Option<TextSearch>,
+    pub error:Option<String>,
+    pub status:String,
+    pub results:Vec<SearchFileResult>,
+    pub _rows:Vec<SearchPanelRow>,
+    pub _selected_row:Option<usize>,
+    pub _pending_paths:Vec<String>,
+    pub _reads:Vec<(u64, String)>,
+    pub _hit_state:HitState,
+    pub _bg_area:Area
+}
+
+#[derive(Clone)]
+pub struct SearchFileResult{
+    pub path:String,
+    pub matches:Vec<SearchFileMatch>
+}
+
+#[derive(Clone)]
+pub struct SearchFileMatch{
+    pub start:usize,
+    pub end:usize,
+    pub row:usize,
+    pub preview:Vec<char>,
+    pub preview_start:usize,
+    pub preview_end:usize
+}
+
+#[derive(Clone)]
+pub enum SearchPanelRow{
+    File{result:usize},
+    Match{result:usize, index:usize}
+}
+
+#[derive(Clone, PartialEq)]
+pub enum SearchPanelEvent{
+    None,
+    Search,
+    ReplaceAll,
+    OpenMatch{path:String, start:usize, end:usize}
+}
+
+const SEARCH_READS_IN_FLIGHT:usize = 8;
+const SEARCH_MAX_MATCHES_PER_FILE:usize = 1000;
+const SEARCH_PREVIEW_CONTEXT:usize = 40;
+const SEARCH_PREVIEW_MAX:usize = 200;
+
+impl ElementLife for SearchPanel{
+    fn construct(&mut self, _cx:&mut Cx){}
+    fn destruct(&mut self, _cx:&mut Cx){}
+}
+
+impl Style for SearchPanel{
+    fn style(cx:&mut Cx)->Self{
+        Self{
+            view:View{
+                scroll_h:Some(ScrollBar{
+                    ..Style::style(cx)
+                }),
+                scroll_v:Some(ScrollBar{
+                    smoothing:Some(0.15),
+                    ..Style::style(cx)
+                }),
+                ..Style::style(cx)
+            },
+            bg:Quad{
+                color:color256(30,30,30),
+                do_scroll:false,
+                ..Style::style(cx)
+            },
+            header_bg:Quad{
+                color:color256(45,45,45),
+                ..Style::style(cx)
+            },
+            marker:Quad{
+                ..Style::style(cx)
+            },
+            text:Text{
+                font_id:cx.load_font(&cx.font("mono_font")),
+                font_size:11.0,
+                brightness:1.05,
+                line_spacing:1.4,
+                wrapping:Wrapping::None,
+                ..Style::style(cx)
+            },
+            row_height:18.,
+            file_color:cx.color("text_selected_focus"),
+            line_color:color("#6"),
+            preview_color:color("#a"),
+            match_color:color("#f"),
+            match_marker_color:color256(90,78,40),
+            selected_marker_color:color256(42,78,117),
+            query:String::new(),
+            replace:String::new(),
+            field:FindBarField::Find,
+            options:SearchOptions::default(),
+            search:None,
+            error:None,
+            status:String::new(),
+            results:Vec::new(),
+            _rows:Vec::new(),
+            _selected_row:None,
+            _pending_paths:Vec::new(),
+            _reads:Vec::new(),
+            _hit_state:HitState{no_scrolling:true, ..Default::default()},
+            _bg_area:Area::Empty
+        }
+    }
+}
+
+impl SearchPanel{
+    fn header_height(&self, cx:&Cx)->f32{
+        self.text.get_monospace_size(cx, None).y * 2. + 8.
+    }
+
+    pub fn handle_search_panel(&mut self, cx:&mut Cx, event:&mut Event)->SearchPanelEvent{
+        match self.view.handle_scroll_bars(cx, event){
+            (_,ScrollBarEvent::Scroll{..}) | (ScrollBarEvent::Scroll{..},_)=>{
+                // only the visible rows are drawn
+                self.view.redraw_view_area(cx);
+            },
+            _=>()
+        }
+        match event.hits(cx, self._bg_area, &mut self._hit_state){
+            Event::FingerDown(fe)=>{
+                cx.set_key_focus(self._bg_area);
+                let header_height = self.header_height(cx);
+                self.view.redraw_view_area(cx);
+                if fe.rel.y < header_height{
+                    self.field = if fe.rel.y < header_height * 0.5{FindBarField::Find}else{FindBarField::Replace};
+                    return SearchPanelEvent::None
+                }
+                let y = fe.rel.y + self.view.get_scroll_pos(cx).y - header_height;
+                let row = (y / self.row_height) as usize;
+                if let Some(SearchPanelRow::Match{result, index}) = self._rows.get(row){
+                    self._selected_row = Some(row);
+                    let result = &self.results[*result];
+                    let search_match = &result.matches[*index];
+                    return SearchPanelEvent::OpenMatch{
+                        path:result.path.clone(),
+                        start:search_match.start,
+                        end:search_match.end
+                    }
+                }
+            },
+            Event::FingerHover(fe)=>{
+                if fe.rel.y < self.header_height(cx){
+                    cx.set_hover_mouse_cursor(MouseCursor::Text);
+                }
+                else{
+                    cx.set_hover_mouse_cursor(MouseCursor::Hand);
+                }
+            },
+            Event::KeyDown(ke)=>{
+                self.view.redraw_view_area(cx);
+                let command = ke.modifiers.logo || ke.modifiers.control;
+                if command && ke.modifiers.alt{ // option toggles, same keys as the find bar
+                    match ke.key_code{
+                        KeyCode::KeyC=>self.options.ignore_case = !self.options.ignore_case,
+                        KeyCode::KeyW=>self.options.whole_word = !self.options.whole_word,
+                        KeyCode::KeyR=>self.options.regex = !self.options.regex,
+                        KeyCode::Return=>return SearchPanelEvent::ReplaceAll,
+                        _=>()
+                    }
+                    return SearchPanelEvent::None
+                }
+                match ke.key_code{
+                    KeyCode::Tab=>{
+                        self.field = if self.field == FindBarField::Find{FindBarField::Replace}else{FindBarField::Find};
+                    },
+                    KeyCode::Backspace=>{
+                        match self.field{
+                            FindBarField::Find=>self.query.pop(),
+                            FindBarField::Replace=>self.replace.pop()
+                        };
+                    },
+                    KeyCode::Escape=>{
+                        self.cancel_search();
+                    },
+                    KeyCode::Return=>{
+                        return SearchPanelEvent::Search
+                    },
+                    _=>()
+                }
+            },
+            Event::TextInput(TextInputEvent{input, ..}) | Event::TextPaste(TextPasteEvent{text:input})=>{
+                let input:String = input.chars().filter(|ch| !ch.is_control()).collect();
+                match self.field{
+                    FindBarField::Find=>self.query.push_str(&input),
+                    FindBarField::Replace=>self.replace.push_str(&input)
+                }
+                self.view.redraw_view_area(cx);
+            },
+            _=>()
+        }
+        SearchPanelEvent::None
+    }
+
+    // searches the given files, buffers we have loaded are searched right away
+    pub fn start_search(&mut self, cx:&mut Cx, paths:Vec<String>, text_buffers:&HashMap<String, TextBuffer>){
+        self.cancel_search();
+        self.results.truncate(0);
+        self._rows.truncate(0);
+        self._selected_row = None;
+        self.error = None;
+        self.search = None;
+        self.view.redraw_view_area(cx);
+        if self.query.len() == 0{
+            self.status = String::new();
+            return
+        }
+        match TextSearch::new(&self.query, &self.options){
+            Ok(search)=>self.search = Some(search),
+            Err(err)=>{
+                self.status = String::new();
+                self.error = Some(err);
+                return
+            }
+        }
+        for path in paths{
+            match text_buffers.get(&path){
+                Some(text_buffer) if text_buffer.load_id == 0 && text_buffer.load_error.is_none()=>{
+                    self.add_result(&path, text_buffer);
+                },
+                _=>self._pending_paths.push(path)
+            }
+        }
+        // read in order
+        self._pending_paths.reverse();
+        self.read_pending(cx);
+        self.update_status();
+    }
+
+    pub fn cancel_search(&mut self){
+        self._pending_paths.truncate(0);
+        self._reads.truncate(0);
+        self.update_status();
+    }
+
+    fn read_pending(&mut self, cx:&mut Cx){
+        while self._reads.len() < SEARCH_READS_IN_FLIGHT{
+            if let Some(path) = self._pending_paths.pop(){
+                let read_id = cx.read_file(&format!(".{}", path));
+                self._reads.push((read_id, path));
+            }
+            else{
+                break
+            }
+        }
+    }
+
+    // returns true if the read was ours
+    pub fn handle_file_read(&mut self, cx:&mut Cx, fr:&FileReadEvent)->bool{
+        let index = if let Some(index) = self._reads.iter().position(|(read_id, _)| *read_id == fr.id){index}else{return false};
+        let (_, path) = self._reads.remove(index);
+        if let Ok(data) = &fr.data{
+            // decode it the same way an editor would, so the offsets line up when it is opened
+            let mut text_buffer = TextBuffer::default();
+            if text_buffer.load_buffer(data).is_ok(){
+                self.add_result(&path, &text_buffer);
+            }
+        }
+        self.read_pending(cx);
+        self.update_status();
+        self.view.redraw_view_area(cx);
+        true
+    }
+
+    fn add_result(&mut self, path:&str, text_buffer:&TextBuffer){
+        let search = if let Some(search) = &self.search{search}else{return};
+        let mut matches = search.find_in_text_buffer(text_buffer);
+        if matches.len() == 0{
+            return
+        }
+        matches.truncate(SEARCH_MAX_MATCHES_PER_FILE);
+        let result_index = self.results.len();
+        self._rows.push(SearchPanelRow::File{result:result_index});
+        let mut file_matches = Vec::new();
+        for search_match in matches{
+            let (row, col) = text_buffer.offset_to_row_col(search_match.start);
+            let line = text_buffer.get_line(row);
+            // skip the indent, and cut long lines down to the part around the match
+            let mut preview_offset = line.iter().position(|ch| !ch.is_whitespace()).unwrap_or(0).min(col);
+            if col - preview_offset > SEARCH_PREVIEW_CONTEXT{
+                preview_offset = col - SEARCH_PREVIEW_CONTEXT;
+            }
+            let preview_len = (line.len() - preview_offset).min(SEARCH_PREVIEW_MAX);
+            let match_end = (col + search_match.end - search_match.start).min(line.len());
+            self._rows.push(SearchPanelRow::Match{result:result_index, index:file_matches.len()});
+            file_matches.push(SearchFileMatch{
+                start:search_match.start,
+                end:search_match.end,
+                row:row,
+                preview:line[preview_offset..preview_offset + preview_len].to_vec(),
+                preview_start:(col - preview_offset).min(preview_len),
+                preview_end:(match_end - preview_offset).min(preview_len)
+            });
+        }
+        self.results.push(SearchFileResult{
+            path:path.to_string(),
+            matches:file_matches
+        });
+    }
+
+    fn update_status(&mut self){
+        let match_count:usize = self.results.iter().map(|result| result.matches.len()).sum();
+        let files_left = self._pending_paths.len() + self._reads.len();
+        self.status = if files_left > 0{
+            format!("{} matches in {} files, {} files to go", match_count, self.results.len(), files_left)
+        }
+        else if self.search.is_none(){
+            String::new()
+        }
+        else if match_count == 0{
+            "No results".to_string()
+        }
+        else{
+            format!("{} matches in {} files", match_count, self.results.len())
+        };
+    }
+
+    // the results are stale once replaced
+    pub fn replace_done(&mut self, cx:&mut Cx, file_count:usize, match_count:usize){
+        self.cancel_search();
+        self.results.truncate(0);
+        self._rows.truncate(0);
+        self._selected_row = None;
+        self.search = None;
+        self.status = format!("Replaced {} matches in {} files", match_count, file_count);
+        self.view.redraw_view_area(cx);
+    }
+
+    pub fn draw_search_panel(&mut self, cx:&mut Cx){
+        self.view.begin_view(cx, &Layout{..Default::default()});
+
+        let bg_area = self.bg.draw_quad(cx, Rect{x:0., y:0., w:cx.width_total(false), h:cx.height_total(false)}).into_area();
+        cx.update_area_refs(self._bg_area, bg_area);
+        self._bg_area = bg_area;
+
+        let origin = cx.turtle_origin();
+        let scroll_pos = self.view.get_scroll_pos(cx);
+        let view_height = cx.height_total(false);
+        let mono_size = self.text.get_monospace_size(cx, None);
+        let header_height = self.header_height(cx);
+        let text_y = (self.row_height - mono_size.y) * 0.5;
+
+        // only the rows in view are drawn
+        let first_row = ((scroll_pos.y - header_height).max(0.) / self.row_height) as usize;
+        let last_row = (((scroll_pos.y + view_height - header_height) / self.row_height).max(0.) as usize + 1).min(self._rows.len());
+        let first_row = first_row.min(last_row);
+
+        cx.new_instance_layer(self.marker.shader_id, 0);
+        for row in first_row..last_row{
+            let y = header_height + row as f32 * self.row_height;
+            if self._selected_row == Some(row){
+                self.marker.color = self.selected_marker_color;
+                self.marker.draw_quad(cx, Rect{x:0., y:y, w:cx.width_total(false) + scroll_pos.x, h:self.row_height});
+            }
+            if let SearchPanelRow::Match{result, index} = &self._rows[row]{
+                let search_match = &self.results[*result].matches[*index];
+                let x = mono_size.x * (Self::line_label(search_match).len() + search_match.preview_start) as f32 + 6.;
+                let w = mono_size.x * (search_match.preview_end - search_match.preview_start) as f32;
+                self.marker.color = self.match_marker_color;
+                self.marker.draw_quad(cx, Rect{x:x, y:y + text_y, w:w, h:mono_size.y});
+            }
+        }
+
+        cx.new_instance_layer(self.text.shader_id, 0);
+        let mut inst = self.text.begin_text(cx);
+        let mut max_width:f32 = 0.;
+        for row in first_row..last_row{
+            let x = origin.x + 6.;
+            let y = origin.y + header_height + row as f32 * self.row_height + text_y;
+            let end_x = match &self._rows[row]{
+                SearchPanelRow::File{result}=>{
+                    let result = &self.results[*result];
+                    let fx = Self::draw_label(cx, &mut self.text, &mut inst, x, y, &result.path, self.file_color);
+                    Self::draw_label(cx, &mut self.text, &mut inst, fx + mono_size.x, y, &format!("{}", result.matches.len()), self.line_color)
+                },
+                SearchPanelRow::Match{result, index}=>{
+                    let search_match = &self.results[*result].matches[*index];
+                    let preview = &search_match.preview;
+                    let mut fx = Self::draw_label(cx, &mut self.text, &mut inst, x, y, &Self::line_label(search_match), self.line_color);
+                    fx = Self::draw_chars(cx, &mut self.text, &mut inst, fx, y, &preview[0..search_match.preview_start], self.preview_color);
+                    fx = Self::draw_chars(cx, &mut self.text, &mut inst, fx, y, &preview[search_match.preview_start..search_match.preview_end], self.match_color);
+                    Self::draw_chars(cx, &mut self.text, &mut inst, fx, y, &preview[search_match.preview_end..], self.preview_color)
+                }
+            };
+            max_width = max_width.max(end_x - origin.x);
+        }
+        self.text.end_text(cx, &inst);
+
+        // the list is as high as all rows, even though we only drew a few
+        cx.walk_turtle(
+            Bounds::Fix(max_width + mono_size.x * 3.),
+            Bounds::Fix(header_height + self._rows.len() as f32 * self.row_height + self.row_height),
+            Margin::zero(),
+            None
+        );
+
+        self.draw_header(cx, scroll_pos);
+
+        self.view.end_view(cx);
+    }
+
+    // the query fields stay on top, like the find bar in an editor
+    fn draw_header(&mut self, cx:&mut Cx, scroll_pos:Vec2){
+        let origin = cx.turtle_origin();
+        let mono_size = self.text.get_monospace_size(cx, None);
+        let has_focus = self._bg_area == cx.key_focus;
+
+        cx.new_instance_layer(self.header_bg.shader_id, 0);
+        self.header_bg.draw_quad(cx, Rect{x:scroll_pos.x, y:scroll_pos.y, w:cx.width_total(false), h:self.header_height(cx)});
+
+        cx.new_instance_layer(self.text.shader_id, 0);
+        let mut inst = self.text.begin_text(cx);
+        let x = origin.x + scroll_pos.x + 6.;
+        let mut y = origin.y + scroll_pos.y + 4.;
+
+        let field_color = |on:bool|{if on{color("#f")}else{color("#a")}};
+        let find_focus = has_focus && self.field == FindBarField::Find;
+        let mut fx = Self::draw_label(cx, &mut self.text, &mut inst, x, y, "Find    ", color("#8"));
+        fx = Self::draw_label(cx, &mut self.text, &mut inst, fx, y, &format!("{}{} ", self.query, if find_focus{"|"}else{""}), field_color(find_focus));
+
+        let option_color = |on:bool|{if on{color("#f")}else{color("#5")}};
+        fx = Self::draw_label(cx, &mut self.text, &mut inst, fx + mono_size.x, y, "Aa ", option_color(self.options.ignore_case));
+        fx = Self::draw_label(cx, &mut self.text, &mut inst, fx, y, "W ", option_color(self.options.whole_word));
+        fx = Self::draw_label(cx, &mut self.text, &mut inst, fx, y, ".*", option_color(self.options.regex));
+        if let Some(error) = &self.error{
+            Self::draw_label(cx, &mut self.text, &mut inst, fx + mono_size.x * 2., y, error, color("#c66"));
+        }
+        else{
+            Self::draw_label(cx, &mut self.text, &mut inst, fx + mono_size.x * 2., y, &self.status, color("#8"));
+        }
+
+        y += mono_size.y;
+        let replace_focus = has_focus && self.field == FindBarField::Replace;
+        let rx = Self::draw_label(cx, &mut self.text, &mut inst, x, y, "Replace ", color("#8"));
+        Self::draw_label(cx, &mut self.text, &mut inst, rx, y, &format!("{}{}", self.replace, if replace_focus{"|"}else{""}), field_color(replace_focus));
+        self.text.end_text(cx, &inst);
+    }
+
+    fn line_label(search_match:&SearchFileMatch)->String{
+        format!("  {:>5}: ", search_match.row + 1)
+    }
+
+    fn draw_label(cx:&mut Cx, text:&mut Text, inst:&mut AlignedInstance, x:f32, y:f32, label:&str, color:Color)->f32{
+        let chunk:Vec<char> = label.chars().collect();
+        Self::draw_chars(cx, text, inst, x, y, &chunk, color)
+    }
+
+    fn draw_chars(cx:&mut Cx, text:&mut Text, inst:&mut AlignedInstance, x:f32, y:f32, chunk:&[char], color:Color)->f32{
+        let mono_size = text.get_monospace_size(cx, None);
+        if chunk.len() > 0{
+            text.color = color;
+            text.add_text(cx, x, y, 0, inst, chunk, |_,_,_,_|{0.0});
+        }
+        x + mono_size.x * chunk.len() as f32
+    }
+}
@@ -0,0 +1,131 @@
+use crate::textbuffer::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SoftWrap{
+    Off,
+    // wrap at the width of the view
+    ViewWidth,
+    // wrap at the editors wrap_column
+    Column
+}
+
+// Where long lines break when they are wrapped, and which line on screen every row starts at.
+// A line breaks after the last space that fits, or in the middle of a word that doesn't fit at
+// all. Its continuation lines are indented like the line itself.
+#[derive(Clone, Default)]
+pub struct WrapLayout{
+    pub cols:usize,
+    // the change_id and column count the breaks were found for
+    pub built_for:Option<(u64, usize)>,
+    // per row the columns its continuation lines start at
+    pub breaks:Vec<Vec<usize>>,
+    // per row how far its continuation lines are indented
+    pub indents:Vec<usize>,
+    // per row the index of its first line on screen, folded rows get the one of the row after
+    pub visual_starts:Vec<usize>,
+    pub visual_count:usize
+}
+
+impl WrapLayout{
+    pub fn update(&mut self, text_buffer:&TextBuffer, cols:usize){
+        let line_count = text_buffer.get_line_count();
+        if self.built_for != Some((text_buffer.change_id, cols)){
+            self.built_for = Some((text_buffer.change_id, cols));
+            self.cols = cols;
+            self.breaks.truncate(0);
+            self.indents.truncate(0);
+            let mut line = Vec::new();
+            for row in 0..line_count{
+                line.truncate(0);
+                line.extend(text_buffer.line_chars(row));
+                let (breaks, indent) = Self::wrap_line(&line, cols);
+                self.breaks.push(breaks);
+                self.indents.push(indent);
+            }
+        }
+        // folds change without the text changing, so these are redone every time
+        self.visual_starts.truncate(0);
+        let mut visual = 0;
+        for row in 0..line_count{
+            self.visual_starts.push(visual);
+            if !text_buffer.folds.is_hidden(row){
+                visual += 1 + self.breaks[row].len();
+            }
+        }
+        self.visual_count = visual;
+    }
+
+    pub fn is_current(&self, text_buffer:&TextBuffer)->bool{
+        if let Some((change_id, _)) = self.built_for{
+            return change_id == text_buffer.change_id
+        }
+        false
+    }
+
+    pub fn wrap_line(line:&[char], cols:usize)->(Vec<usize>, usize){
+        let mut breaks = Vec::new();
+        let mut indent = line.iter().take_while(|ch| **ch == ' ' || **ch == '\t').count();
+        if indent > cols / 2{
+            indent = 0;
+        }
+        let mut start = 0;
+        let mut width = cols;
+        while line.len() - start > width{
+            let end = start + width;
+            // don't break in the indent of the first line
+            let lower = if start == 0{indent}else{start};
+            let mut split = end;
+            for i in (lower + 1..end + 1).rev(){
+                if line[i - 1] == ' '{
+                    split = i;
+                    break
+                }
+            }
+            breaks.push(split);
+            start = split;
+            width = (cols - indent).max(1);
+        }
+        (breaks, indent)
+    }
+
+    // the line on screen and the column on it of a position in the text
+    pub fn pos_of(&self, row:usize, col:usize)->(usize, usize){
+        let breaks = &self.breaks[row];
+        let sub = breaks.iter().take_while(|brk| **brk <= col).count();
+        let x_col = if sub == 0{col}else{self.indents[row] + col - breaks[sub - 1]};
+        (self.visual_starts[row] + sub, x_col)
+    }
+
+    // the row a line on screen belongs to, and which of its lines it is
+    pub fn visual_to_pos(&self, visual:usize)->(usize, usize){
+        if self.visual_count == 0{
+            return (0, 0)
+        }
+        let visual = visual.min(self.visual_count - 1);
+        // the last row starting at or before it, which skips folded rows
+        let mut lo = 0;
+        let mut hi = self.visual_starts.len();
+        while lo < hi{
+            let mid = (lo + hi) / 2;
+            if self.visual_starts[mid] <= visual{
+                lo = mid + 1;
+            }
+            else{
+                hi = mid;
+            }
+        }
+        let row = lo - 1;
+        (row, visual - self.visual_starts[row])
+    }
+
+    // the offset closest to a column on one of the lines on screen of a row
+    pub fn offset_at(&self, text_buffer:&TextBuffer, row:usize, sub:usize, x_col:usize)->usize{
+        let breaks = &self.breaks[row];
+        let sub = sub.min(breaks.len());
+        let seg_start = if sub == 0{0}else{breaks[sub - 1]};
+        let seg_end = if sub < breaks.len(){breaks[sub] - 1}else{text_buffer.get_line_len(row)};
+        let indent = if sub == 0{0}else{self.indents[row]};
+        let col = (seg_start + x_col.max(indent) - indent).min(seg_end);
+        text_buffer.row_col_to_offset(row, col)
+    }
+}
@@ -1,17 +1,112 @@
 //use widgets::*;
+use crate::rope::*;
+use crate::undotree::*;
+use crate::undofile::*;
+use crate::tokenizer::*;
+use crate::brackets::*;
+use crate::folds::*;
 
 #[derive(Clone, Default)]
 pub struct TextBuffer{
-    // Vec<Vec<char>> was chosen because, for all practical use (code) most lines are short
-    // Concatenating the total into a utf8 string is trivial, and O(1) for windowing into the lines is handy.
-    // Also inserting lines is pretty cheap even approaching 100k lines.
-    // If you want to load a 100 meg single line file or something with >100k lines
-    // other options are better. But these are not usecases for this editor.
-    pub lines: Vec<Vec<char>>,
-    pub undo_stack: Vec<TextUndo>,
-    pub redo_stack: Vec<TextUndo>,
+    // The text lives in a rope so edits and offset lookups stay logarithmic,
+    // even for generated bindings or logs with hundreds of thousands of lines
+    // or a single 100 meg line. Lines are materialized on demand.
+    // The rope nodes also count newlines, which makes it our line index.
+    pub rope:Rope,
+    // bumped on every change to the text, so anything derived from it can tell it is stale
+    pub change_id: u64,
+    pub token_cache: TokenCache,
+    pub bracket_index: BracketIndex,
+    pub folds: Folds,
+    pub undo_tree: UndoTree,
+    // timestamp for new undo entries where there is no wall clock, editors set it from their events
+    pub undo_time: f64,
+    // the editor view making edits right now, editors set it like undo_time. 0 is no view
+    pub view_id: u64,
+    // the recent edits, so every view on the buffer can move its cursors along
+    pub op_spans: Vec<TextOpSpan>,
+    // views synced before this change_id missed edits that are no longer logged
+    pub op_spans_lost: u64,
+    // cursors to put back in a view, after another view undid one of its edits
+    pub _cursor_restores: Vec<(u64, u64, CursorSet)>,
+    pub _view_id_alloc: u64,
     pub load_id: u64,
-    pub _char_count: usize
+    pub load_error: Option<String>,
+    pub undo_load_id: u64,
+    pub save_id: u64,
+    // how the file looked on disk, the rope always holds '\n' separated unicode
+    pub encoding: TextEncoding,
+    pub has_bom: bool,
+    pub line_ending: LineEnding,
+    // the ending of every line when the file mixes them, empty when they all are line_ending.
+    // edited lines get line_ending
+    pub line_endings: Vec<LineEnding>,
+    // dirty tracking compares the current undo tree node with the one we saved at
+    pub _saving_undo_id: usize,
+    pub _saved_undo_id: usize,
+    // content hash of the file on disk, ties a persisted undo history to it
+    pub _saving_hash: u64,
+    pub _saved_hash: u64
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextEncoding{
+    Utf8,
+    Utf16LE,
+    Utf16BE,
+    Latin1
+}
+
+impl Default for TextEncoding{
+    fn default()->TextEncoding{
+        TextEncoding::Utf8
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineEnding{
+    Lf,
+    CrLf,
+    Cr
+}
+
+impl Default for LineEnding{
+    fn default()->LineEnding{
+        LineEnding::Lf
+    }
+}
+
+impl LineEnding{
+    fn as_str(&self)->&'static str{
+        match self{
+            LineEnding::Lf=>"\n",
+            LineEnding::CrLf=>"\r\n",
+            LineEnding::Cr=>"\r"
+        }
+    }
+}
+
+// what a char is to word movement, a run of the same class is one word
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CharClass{
+    Word,
+    Space,
+    Newline,
+    // runs of these like :: -> => and &mut are one word
+    Operator,
+    // brackets, quotes and separators are a word each
+    Single
+}
+
+impl CharClass{
+    pub fn of(ch:char)->CharClass{
+        match ch{
+            '\n'=>CharClass::Newline,
+            ' ' | '\t'=>CharClass::Space,
+            '(' | ')' | '[' | ']' | '{' | '}' | ',' | ';' | '"' | '\''=>CharClass::Single,
+            _=>if ch.is_alphanumeric() || ch == '_'{CharClass::Word}else{CharClass::Operator}
+        }
+    }
 }
 
 #[derive(Clone,PartialEq)]
@@ -49,165 +144,255 @@ impl TextUndoGrouping{
 
 #[derive(Clone)]
 pub struct TextUndo{
-    ops:Vec<TextOp>,
-    grouping:TextUndoGrouping,
-    cursors:CursorSet
+    pub ops:Vec<TextOp>,
+    pub grouping:TextUndoGrouping,
+    pub cursors:CursorSet,
+    // the view the cursors belong to, 0 restores them in any view
+    pub view_id:u64
 }
 
 #[derive(Clone)]
 pub struct TextOp{
-    start:usize,
-    len:usize,
-    lines:Vec<Vec<char>>,
+    pub start:usize,
+    pub len:usize,
+    pub lines:Vec<Vec<char>>,
 }
 
-impl TextBuffer{
+// where the text changed, removed chars at start were replaced by inserted ones
+#[derive(Clone, Copy, Debug)]
+pub struct TextOpSpan{
+    pub change_id:u64,
+    pub view_id:u64,
+    pub start:usize,
+    pub removed:usize,
+    pub inserted:usize
+}
 
-    pub fn offset_to_row_col(&self, char_offset:usize)->(usize,usize){
-        let mut char_count = 0;
-        for (row,line) in self.lines.iter().enumerate(){
-            let next_char_count = char_count + line.len() + 1;
-            if next_char_count > char_offset{
-                return (row, char_offset - char_count)
-            }
-            char_count = next_char_count;
+impl TextOpSpan{
+    // moves an offset from before the change to after it, offsets in the removed chars go to its start
+    pub fn remap(&self, offset:usize)->usize{
+        if offset <= self.start{
+            offset
+        }
+        else if offset >= self.start + self.removed{
+            offset - self.removed + self.inserted
+        }
+        else{
+            self.start
         }
-        (0,0)
     }
 
-    pub fn offset_len_to_row_col(&self, char_offset:usize, len:usize)->(usize,usize,usize,usize){
-        let mut char_count = 0;
-        let mut start_row = 0;
-        let mut start_col = 0;
-        let mut begin_found = false;
-        for (row,line) in self.lines.iter().enumerate(){
-            let next_char_count = char_count + line.len() + 1;
-            if !begin_found && next_char_count > char_offset{
-                start_row = row;
-                start_col = char_offset - char_count;
-                begin_found = true;
-            }
-            if begin_found && next_char_count > char_offset + len{
-                return (start_row, start_col, row, (char_offset + len) - char_count);
-            }
-            char_count = next_char_count;
+    pub fn remap_cursors(&self, cursors:&mut CursorSet){
+        for cursor in &mut cursors.set{
+            cursor.head = self.remap(cursor.head);
+            cursor.tail = self.remap(cursor.tail);
         }
-        (0,0,0,0)
+    }
+}
+
+const MAX_OP_SPANS:usize = 1024;
+
+impl TextBuffer{
+
+    pub fn offset_to_row_col(&self, char_offset:usize)->(usize,usize){
+        let char_offset = char_offset.min(self.get_char_count());
+        let row = self.rope.offset_to_row(char_offset);
+        (row, char_offset - self.rope.row_to_offset(row))
     }
 
+    pub fn offset_len_to_row_col(&self, char_offset:usize, len:usize)->(usize,usize,usize,usize){
+        let (start_row, start_col) = self.offset_to_row_col(char_offset);
+        let (end_row, end_col) = self.offset_to_row_col(char_offset + len);
+        (start_row, start_col, end_row, end_col)
+    }
 
     pub fn row_col_to_offset(&self, row:usize, col:usize)->usize{
-        let mut char_count = 0;
-        if row >= self.lines.len(){
-            return self._char_count
-        }
-        for (ln_row, line) in self.lines.iter().enumerate(){
-            if ln_row == row{
-                return char_count + line.len().min(col);
-            }
-            char_count += line.len() + 1;
+        if row >= self.get_line_count(){
+            return self.get_char_count()
         }
-        0
+        self.rope.row_to_offset(row) + self.get_line_len(row).min(col)
     }
 
     pub fn get_char_count(&self)->usize{
-        self._char_count
+        self.rope.len()
     }
 
     pub fn get_line_count(&self)->usize{
-        self.lines.len()
-    }
-
-    fn get_range_as_string(&self, start:usize, len:usize, ret:&mut String){
-        let (mut row, mut col) = self.offset_to_row_col(start);
-        for _ in 0..len{
-            let line = &self.lines[row];
-            if col >= line.len(){
-                ret.push('\n');
-                col = 0;
-                row += 1;
-                if row >= self.lines.len(){
-                    return;
-                }
-            }
-            else{
-                ret.push(line[col]);
-                col += 1;
-            }
-        };
+        self.rope.newlines() + 1
     }
 
-    fn replace_range(&mut self, start:usize, len:usize, mut rep_lines:Vec<Vec<char>>)->Vec<Vec<char>>{
-
-        let (start_row, start_col, end_row, end_col) = self.offset_len_to_row_col(start, len);
-
-        if start_row == end_row && rep_lines.len() == 1{ // replace in one line
-            let rep_line_zero = rep_lines.drain(0..1).next().unwrap();
-            let line = self.lines[start_row].splice(start_col..end_col, rep_line_zero).collect();
-            return vec![line];
+    pub fn get_line_len(&self, row:usize)->usize{
+        if row + 1 < self.get_line_count(){
+            self.rope.row_to_offset(row + 1) - self.rope.row_to_offset(row) - 1
         }
         else{
-            if rep_lines.len() == 1{ // we are replacing multiple lines with one line
-                // drain first line
-                let rep_line_zero = rep_lines.drain(0..1).next().unwrap();
-
-                // replace it in the first
-                let first = self.lines[start_row].splice(start_col.., rep_line_zero).collect();
+            self.get_char_count() - self.rope.row_to_offset(row)
+        }
+    }
 
-                // collect the middle ones
-                let mut middle:Vec<Vec<char>> = self.lines.drain((start_row+1)..(end_row)).collect();
+    pub fn line_chars(&self, row:usize)->impl Iterator<Item=char> + '_{
+        self.rope.chars_at(self.rope.row_to_offset(row)).take_while(|ch| *ch != '\n')
+    }
 
-                // cut out the last bit
-                let last:Vec<char> = self.lines[start_row+1].drain(0..end_col).collect();
-                
-                // last line bit
-                let mut last_line = self.lines.drain((start_row+1)..(start_row+2)).next().unwrap();
+    pub fn get_line(&self, row:usize)->Vec<char>{
+        self.line_chars(row).collect()
+    }
 
-                // merge start_row+1 into start_row
-                self.lines[start_row].append(&mut last_line);
+    // where a word move to the right ends, after the spaces and the word after offset
+    pub fn word_right_offset(&self, offset:usize)->usize{
+        let end = self.get_char_count();
+        let mut offset = offset.min(end);
+        if offset == end{
+            return end
+        }
+        if self.rope.char_at(offset) == Some('\n'){
+            return offset + 1
+        }
+        let mut chars = self.rope.chars_at(offset).peekable();
+        while let Some(&' ') | Some(&'\t') = chars.peek(){
+            chars.next();
+            offset += 1;
+        }
+        let class = match chars.next(){
+            Some(ch)=>CharClass::of(ch),
+            None=>return offset
+        };
+        match class{
+            CharClass::Newline=>offset,
+            CharClass::Single=>offset + 1,
+            _=>{
+                offset += 1;
+                for ch in chars{
+                    if CharClass::of(ch) != class{
+                        break
+                    }
+                    offset += 1;
+                }
+                offset
+            }
+        }
+    }
 
-                // concat it all together
-                middle.insert(0, first);
-                middle.push(last);
+    // where a word move to the left ends, at the start of the word before offset
+    pub fn word_left_offset(&self, offset:usize)->usize{
+        let mut offset = offset.min(self.get_char_count());
+        if offset == 0{
+            return 0
+        }
+        if self.rope.char_at(offset - 1) == Some('\n'){
+            return offset - 1
+        }
+        while offset > 0{
+            match self.rope.char_at(offset - 1){
+                Some(' ') | Some('\t')=>offset -= 1,
+                _=>break
+            }
+        }
+        if offset == 0{
+            return 0
+        }
+        let class = CharClass::of(self.rope.char_at(offset - 1).unwrap());
+        match class{
+            CharClass::Newline=>offset,
+            CharClass::Single=>offset - 1,
+            _=>{
+                while offset > 0 && self.rope.char_at(offset - 1).map(CharClass::of) == Some(class){
+                    offset -= 1;
+                }
+                offset
+            }
+        }
+    }
 
-                return middle
+    // the word at offset as (start, end), for double clicks. a cursor right after a word selects it
+    pub fn word_range(&self, offset:usize)->(usize, usize){
+        let end = self.get_char_count();
+        let offset = offset.min(end);
+        let class_at = |offset:usize| self.rope.char_at(offset).map(CharClass::of);
+        let mut pos = offset;
+        match class_at(offset){
+            Some(CharClass::Word)=>(),
+            _=>if offset > 0 && class_at(offset - 1) == Some(CharClass::Word){
+                pos = offset - 1;
             }
-            else if start_row == end_row{ // replacing single line with multiple lines
-                let mut last_bit:Vec<char> =  self.lines[start_row].drain(end_col..).collect();// but we have co drain end_col..
+        }
+        let class = match class_at(pos){
+            Some(CharClass::Newline) | None=>return (offset, offset),
+            Some(class)=>class
+        };
+        if class == CharClass::Single{
+            return (pos, pos + 1)
+        }
+        let mut start = pos;
+        while start > 0 && class_at(start - 1) == Some(class){
+            start -= 1;
+        }
+        let mut stop = pos + 1;
+        while stop < end && class_at(stop) == Some(class){
+            stop += 1;
+        }
+        (start, stop)
+    }
 
-                // replaced first line
-                let rep_lines_len = rep_lines.len();
-                let rep_line_first:Vec<char> = rep_lines.drain(0..1).next().unwrap();
-                let line = self.lines[start_row].splice(start_col.., rep_line_first).collect();
+    // the row offset is on as (start, end), including its newline
+    pub fn line_range(&self, offset:usize)->(usize, usize){
+        let row = self.rope.offset_to_row(offset.min(self.get_char_count()));
+        let start = self.rope.row_to_offset(row);
+        if row + 1 < self.get_line_count(){
+            (start, self.rope.row_to_offset(row + 1))
+        }
+        else{
+            (start, self.get_char_count())
+        }
+    }
+
+    fn get_range_as_string(&self, start:usize, len:usize, ret:&mut String){
+        for ch in self.rope.chars_at(start).take(len){
+            ret.push(ch);
+        }
+    }
 
-                // splice in middle rest
-                let rep_line_mid = rep_lines.drain(0..(rep_lines.len()));
-                self.lines.splice( (start_row+1)..(start_row+1), rep_line_mid);
-                
-                // append last bit
-                self.lines[start_row + rep_lines_len-1].append(&mut last_bit);
+    fn replace_range(&mut self, start:usize, len:usize, rep_lines:Vec<Vec<char>>)->Vec<Vec<char>>{
+        self.change_id += 1;
+        let start_row = self.rope.offset_to_row(start);
+        let mut removed = Vec::new();
+        self.rope.remove(start, len, &mut removed);
+        let removed_rows = removed.iter().filter(|ch| **ch == '\n').count();
+        self.token_cache.invalidate(start_row, removed_rows, rep_lines.len() - 1);
+        self.folds.invalidate(start_row, removed_rows, rep_lines.len() - 1);
+        if self.line_endings.len() > 0{
+            let end_row = (start_row + removed_rows).min(self.line_endings.len());
+            let line_ending = self.line_ending;
+            self.line_endings.splice(start_row.min(end_row)..end_row, std::iter::repeat(line_ending).take(rep_lines.len() - 1));
+        }
 
-                return vec![line];
+        let mut inserted = Vec::new();
+        for (index, line) in rep_lines.iter().enumerate(){
+            if index > 0{
+                inserted.push('\n');
             }
-            else{ // replaceing multiple lines with multiple lines
-                // drain and replace last line
-                let rep_line_last = rep_lines.drain((rep_lines.len()-1)..(rep_lines.len())).next().unwrap();
-                let last = self.lines[end_row].splice(..end_col, rep_line_last).collect();
+            inserted.extend_from_slice(line);
+        }
+        self.rope.insert(start, &inserted);
 
-                // swap out middle lines and drain them
-                let rep_line_mid = rep_lines.drain(1..(rep_lines.len()));
-                let mut middle:Vec<Vec<char>> = self.lines.splice( (start_row+1)..end_row, rep_line_mid).collect();
+        if self.op_spans.len() >= MAX_OP_SPANS{
+            let lost = self.op_spans.remove(0).change_id;
+            self.op_spans_lost = lost;
+            self._cursor_restores.retain(|(_, change_id, _)| *change_id > lost);
+        }
+        self.op_spans.push(TextOpSpan{
+            change_id:self.change_id,
+            view_id:self.view_id,
+            start:start,
+            removed:removed.len(),
+            inserted:inserted.len()
+        });
 
-                // drain and replace first line
-                let rep_line_zero = rep_lines.drain(0..1).next().unwrap();
-                let first = self.lines[start_row].splice(start_col.., rep_line_zero).collect();
+        Self::split_chars_to_lines(&removed)
+    }
 
-                // concat it all together
-                middle.insert(0, first);
-                middle.push(last);
-                return middle
-            }
-        }
+    fn split_chars_to_lines(chars:&[char])->Vec<Vec<char>>{
+        chars.split(|ch| *ch == '\n').map(|s| s.to_vec()).collect()
     }
 
     fn split_string_to_lines(string:&str)->Vec<Vec<char>>{
@@ -227,8 +412,6 @@ impl TextBuffer{
         let rep_lines = Self::split_string_to_lines(string);
         let rep_lines_chars = Self::compute_char_count(&rep_lines);
         let lines = self.replace_range(start, len, rep_lines);
-        // ok now we have to replace start, len with data
-        self._char_count = Self::compute_char_count(&self.lines);
         TextOp{
             start:start,
             len:rep_lines_chars,
@@ -239,7 +422,6 @@ impl TextBuffer{
     pub fn replace_with_textop(&mut self, text_op:TextOp)->TextOp{
         let rep_lines_chars = Self::compute_char_count(&text_op.lines);
         let lines = self.replace_range(text_op.start, text_op.len, text_op.lines);
-        self._char_count = Self::compute_char_count(&self.lines);
         TextOp{
             start:text_op.start,
             len:rep_lines_chars,
@@ -247,20 +429,235 @@ impl TextBuffer{
         }
     }
 
-    pub fn save_buffer(&mut self){
-        //let out = self.lines.join("\n");
+    pub fn push_undo(&mut self, ops:Vec<TextOp>, grouping:TextUndoGrouping, cursors:CursorSet){
+        self.undo_tree.push(TextUndo{
+            ops:ops,
+            grouping:grouping,
+            cursors:cursors,
+            view_id:self.view_id
+        }, wall_time().unwrap_or(self.undo_time))
+    }
+
+    // identifies the current position in the undo history, 0 is the state we loaded
+    pub fn undo_id(&self)->usize{
+        self.undo_tree.current
+    }
+
+    // makes the edits since undo_id undo as one, unless one of them was saved
+    pub fn merge_undo_since(&mut self, undo_id:usize){
+        let current = self.undo_id();
+        let in_range = |id:usize| id > undo_id && id <= current;
+        if in_range(self._saved_undo_id) || (self.save_id != 0 && in_range(self._saving_undo_id)){
+            return
+        }
+        self.undo_tree.merge_since(undo_id);
+    }
+
+    pub fn is_dirty(&self)->bool{
+        self.undo_id() != self._saved_undo_id
+    }
+
+    pub fn save_buffer(&mut self)->Vec<u8>{
+        self._saving_undo_id = self.undo_id();
+        let mut string = String::with_capacity(self.get_char_count());
+        let mut row = 0;
+        for ch in self.rope.chars_at(0){
+            if ch == '\n'{
+                string.push_str(self.line_endings.get(row).unwrap_or(&self.line_ending).as_str());
+                row += 1;
+            }
+            else{
+                string.push(ch);
+            }
+        }
+        // latin-1 can't hold every char, rather than lose them the file becomes utf-8
+        if self.encoding == TextEncoding::Latin1 && string.chars().any(|ch| ch as u32 >= 256){
+            self.encoding = TextEncoding::Utf8;
+            self.has_bom = false;
+        }
+        let data = Self::encode_string(&string, self.encoding, self.has_bom);
+        self._saving_hash = content_hash(&data);
+        data
+    }
+
+    fn encode_string(string:&str, encoding:TextEncoding, has_bom:bool)->Vec<u8>{
+        let mut out = Vec::with_capacity(string.len() + 3);
+        match encoding{
+            TextEncoding::Utf8=>{
+                if has_bom{
+                    out.extend_from_slice(&[0xef, 0xbb, 0xbf]);
+                }
+                out.extend_from_slice(string.as_bytes());
+            },
+            TextEncoding::Utf16LE=>{
+                if has_bom{
+                    out.extend_from_slice(&[0xff, 0xfe]);
+                }
+                for unit in string.encode_utf16(){
+                    out.push((unit & 0xff) as u8);
+                    out.push((unit >> 8) as u8);
+                }
+            },
+            TextEncoding::Utf16BE=>{
+                if has_bom{
+                    out.extend_from_slice(&[0xfe, 0xff]);
+                }
+                for unit in string.encode_utf16(){
+                    out.push((unit >> 8) as u8);
+                    out.push((unit & 0xff) as u8);
+                }
+            },
+            TextEncoding::Latin1=>{
+                // save_buffer made sure every char fits
+                for ch in string.chars(){
+                    out.push(ch as u32 as u8);
+                }
+            }
+        }
+        out
+    }
+
+    // call when the write request of save_buffer came back without error
+    pub fn save_done(&mut self){
+        self._saved_undo_id = self._saving_undo_id;
+        self._saved_hash = self._saving_hash;
+    }
+
+    pub fn load_buffer(&mut self, data:&Vec<u8>)->Result<(), String>{
+        let (encoding, has_bom, chars) = Self::decode_bytes(data)?;
+        self._saved_hash = content_hash(data);
+
+        // find the dominant line ending, and normalize everything to '\n' for the rope
+        let mut lf = 0;
+        let mut crlf = 0;
+        let mut cr = 0;
+        let mut i = 0;
+        while i < chars.len(){
+            if chars[i] == '\r'{
+                if i + 1 < chars.len() && chars[i + 1] == '\n'{
+                    crlf += 1;
+                    i += 1;
+                }
+                else{
+                    cr += 1;
+                }
+            }
+            else if chars[i] == '\n'{
+                lf += 1;
+            }
+            i += 1;
+        }
+        let line_ending = if crlf > lf && crlf >= cr{
+            LineEnding::CrLf
+        }
+        else if cr > lf && cr > crlf{
+            LineEnding::Cr
+        }
+        else{
+            LineEnding::Lf
+        };
+
+        // a \r before a \n always goes, a lone one only ends a line in files that mostly use them
+        let mut normalized = Vec::with_capacity(chars.len());
+        let mut line_endings = Vec::new();
+        let mut i = 0;
+        while i < chars.len(){
+            let ch = chars[i];
+            if ch == '\r' && i + 1 < chars.len() && chars[i + 1] == '\n'{
+                line_endings.push(LineEnding::CrLf);
+                normalized.push('\n');
+                i += 1;
+            }
+            else if ch == '\r' && line_ending != LineEnding::Lf{
+                line_endings.push(LineEnding::Cr);
+                normalized.push('\n');
+            }
+            else{
+                if ch == '\n'{
+                    line_endings.push(LineEnding::Lf);
+                }
+                normalized.push(ch);
+            }
+            i += 1;
+        }
+        if line_endings.iter().all(|ending| *ending == line_ending){
+            line_endings.truncate(0);
+        }
+
+        self.encoding = encoding;
+        self.has_bom = has_bom;
+        self.line_ending = line_ending;
+        self.line_endings = line_endings;
+        self.rope = Rope::from_chars(&normalized);
+        self.change_id += 1;
+        self.op_spans.truncate(0);
+        self.op_spans_lost = self.change_id;
+        self._cursor_restores.truncate(0);
+        self.token_cache.clear();
+        self.folds.clear();
+        Ok(())
+    }
+
+    fn decode_bytes(data:&[u8])->Result<(TextEncoding, bool, Vec<char>), String>{
+        if data.len() >= 3 && data[0] == 0xef && data[1] == 0xbb && data[2] == 0xbf{
+            return match std::str::from_utf8(&data[3..]){
+                Ok(utf8_data)=>Ok((TextEncoding::Utf8, true, utf8_data.chars().collect())),
+                Err(_)=>Err("File has a UTF-8 byte order mark but is not valid UTF-8".to_string())
+            }
+        }
+        if data.len() >= 2 && data[0] == 0xff && data[1] == 0xfe{
+            return Ok((TextEncoding::Utf16LE, true, Self::decode_utf16(&data[2..], false)?))
+        }
+        if data.len() >= 2 && data[0] == 0xfe && data[1] == 0xff{
+            return Ok((TextEncoding::Utf16BE, true, Self::decode_utf16(&data[2..], true)?))
+        }
+        if let Ok(utf8_data) = std::str::from_utf8(data){
+            if !utf8_data.contains('\0'){
+                return Ok((TextEncoding::Utf8, false, utf8_data.chars().collect()))
+            }
+        }
+        // UTF-16 without a BOM shows up as mostly zero high bytes for ascii text
+        let zero_even = data.iter().step_by(2).filter(|b| **b == 0).count();
+        let zero_odd = data.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+        let half = data.len() / 2;
+        if half > 0 && zero_odd > half / 2 && zero_even == 0{
+            return Ok((TextEncoding::Utf16LE, false, Self::decode_utf16(data, false)?))
+        }
+        if half > 0 && zero_even > half / 2 && zero_odd == 0{
+            return Ok((TextEncoding::Utf16BE, false, Self::decode_utf16(data, true)?))
+        }
+        // latin-1 maps every byte to a char, so only refuse things that look binary
+        if data.contains(&0){
+            return Err("File looks like binary data".to_string())
+        }
+        Ok((TextEncoding::Latin1, false, data.iter().map(|b| *b as char).collect()))
     }
 
-    pub fn load_buffer(&mut self, data:&Vec<u8>){
-        // alright we have to load it and split it on newlines
-        if let Ok(utf8_data) = std::str::from_utf8(&data){
-            self.lines = Self::split_string_to_lines(&utf8_data.to_string());
-            // lets be lazy and redraw all
+    fn decode_utf16(data:&[u8], big_endian:bool)->Result<Vec<char>, String>{
+        if data.len() & 1 != 0{
+            return Err("File has an odd number of bytes for UTF-16".to_string())
+        }
+        let units = data.chunks(2).map(|pair|{
+            if big_endian{
+                ((pair[0] as u16) << 8) | pair[1] as u16
+            }
+            else{
+                ((pair[1] as u16) << 8) | pair[0] as u16
+            }
+        });
+        let mut chars = Vec::with_capacity(data.len() / 2);
+        for ch in std::char::decode_utf16(units){
+            match ch{
+                Ok(ch)=>chars.push(ch),
+                Err(_)=>return Err("File is not valid UTF-16".to_string())
+            }
         }
-        self._char_count = Self::compute_char_count(&self.lines);
+        Ok(chars)
     }
 
+    // the cursors of the undo go back in the view that made it, other views keep theirs
     pub fn undoredo(&mut self, mut text_undo:TextUndo, cursor_set:&mut CursorSet)->TextUndo{
+        let spans_from = self.op_spans.len();
         let mut ops = Vec::new();
         while text_undo.ops.len() > 0{
             let op = text_undo.ops.pop().unwrap();//text_undo.ops.len() - 1);
@@ -269,34 +666,93 @@ impl TextBuffer{
         let text_undo_inverse = TextUndo{
             ops:ops,
             grouping:text_undo.grouping,
-            cursors:cursor_set.clone()
+            cursors:cursor_set.clone(),
+            view_id:self.view_id
         };
-        cursor_set.set = text_undo.cursors.set.clone();
-        cursor_set.last_cursor = text_undo.cursors.last_cursor;
+        if text_undo.view_id == 0 || text_undo.view_id == self.view_id{
+            cursor_set.set = text_undo.cursors.set.clone();
+            cursor_set.last_cursor = text_undo.cursors.last_cursor;
+        }
+        else{
+            for span in &self.op_spans[spans_from.min(self.op_spans.len())..]{
+                span.remap_cursors(cursor_set);
+            }
+            self._cursor_restores.push((text_undo.view_id, self.change_id, text_undo.cursors));
+        }
         text_undo_inverse
     }
 
-    // todo make more reuse in these functions
-    pub fn undo(&mut self, grouped:bool, cursor_set:&mut CursorSet){
-        
-        if self.undo_stack.len() == 0{
-            return;
+    // every editor view on the buffer takes an id of its own
+    pub fn alloc_view_id(&mut self)->u64{
+        self._view_id_alloc += 1;
+        self._view_id_alloc
+    }
+
+    // puts cursors that were set before from_change_id where the edits since moved the text,
+    // edits of view_id itself are skipped as that view moved its cursors already. cursors another
+    // view undid for this one are put back on the way
+    pub fn remap_since(&mut self, from_change_id:u64, view_id:u64, cursors:&mut CursorSet, offsets:&mut [usize]){
+        if from_change_id < self.op_spans_lost{
+            let char_count = self.get_char_count();
+            for cursor in &mut cursors.set{
+                cursor.head = cursor.head.min(char_count);
+                cursor.tail = cursor.tail.min(char_count);
+            }
+            for offset in offsets.iter_mut(){
+                *offset = (*offset).min(char_count);
+            }
+            return
+        }
+        for span in self.op_spans.iter().filter(|span| span.change_id > from_change_id){
+            if span.view_id != view_id{
+                span.remap_cursors(cursors);
+                for offset in offsets.iter_mut(){
+                    *offset = span.remap(*offset);
+                }
+            }
+            if let Some(index) = self._cursor_restores.iter().position(|(id, change_id, _)| *id == view_id && *change_id == span.change_id){
+                let (_, _, restore) = self._cursor_restores.remove(index);
+                cursors.set = restore.set;
+                cursors.last_cursor = restore.last_cursor;
+            }
         }
+    }
+
+    // walks from the current node to its parent
+    fn undo_step(&mut self, cursor_set:&mut CursorSet){
+        let id = self.undo_tree.current;
+        if let Some(text_undo) = self.undo_tree.nodes[id].text_undo.take(){
+            let text_redo = self.undoredo(text_undo, cursor_set);
+            self.undo_tree.nodes[id].text_undo = Some(text_redo);
+        }
+        self.undo_tree.current = self.undo_tree.nodes[id].parent;
+    }
+
+    // walks from the current node into one of its children
+    fn redo_step(&mut self, id:usize, cursor_set:&mut CursorSet){
+        let parent = &mut self.undo_tree.nodes[self.undo_tree.current];
+        if let Some(index) = parent.children.iter().position(|child| *child == id){
+            parent.redo_child = index;
+        }
+        if let Some(text_redo) = self.undo_tree.nodes[id].text_undo.take(){
+            let text_undo = self.undoredo(text_redo, cursor_set);
+            self.undo_tree.nodes[id].text_undo = Some(text_undo);
+        }
+        self.undo_tree.current = id;
+    }
+
+    pub fn undo(&mut self, grouped:bool, cursor_set:&mut CursorSet){
         let mut last_grouping = TextUndoGrouping::Other;
         let mut first = true;
-        while self.undo_stack.len() > 0{
-            if !first && !grouped{
-                break
-            }
-            if self.undo_stack.last().unwrap().grouping != last_grouping && !first{
+        while self.undo_tree.can_undo(){
+            let grouping = self.undo_tree.grouping(self.undo_tree.current);
+            if !first && (!grouped || grouping != last_grouping){
                 break
             }
             first = false;
-            let text_undo = self.undo_stack.pop().unwrap();
-            let wants_grouping = text_undo.grouping.wants_grouping();
-            last_grouping = text_undo.grouping.clone();
-            let text_redo = self.undoredo(text_undo, cursor_set);
-            self.redo_stack.push(text_redo);
+            self.undo_step(cursor_set);
+            let wants_grouping = grouping.wants_grouping();
+            last_grouping = grouping;
             if !wants_grouping{
                 break;
             }
@@ -304,118 +760,52 @@ impl TextBuffer{
     }
 
     pub fn redo(&mut self, grouped:bool, cursor_set:&mut CursorSet){
-        if self.redo_stack.len() == 0{
-            return;
-        }
         let mut last_grouping = TextUndoGrouping::Other;
         let mut first = true;
-        while self.redo_stack.len() > 0{
-            if !first{
-                if self.redo_stack.last().unwrap().grouping != last_grouping || !grouped{
-                    break
-                }
+        while let Some(id) = self.undo_tree.redo_target(){
+            let grouping = self.undo_tree.grouping(id);
+            if !first && (!grouped || grouping != last_grouping){
+                break
             }
             first = false;
-            let text_redo = self.redo_stack.pop().unwrap();
-            let wants_grouping = text_redo.grouping.wants_grouping();
-            last_grouping = text_redo.grouping.clone();
-            let text_undo = self.undoredo(text_redo, cursor_set);
-            self.undo_stack.push(text_undo);
+            self.redo_step(id, cursor_set);
+            let wants_grouping = grouping.wants_grouping();
+            last_grouping = grouping;
             if !wants_grouping{
                 break;
             }
         }
     }
 
-}
-
-pub struct TokenizerState<'a>{
-    pub prev:char,
-    pub cur:char,
-    pub next:char,
-    pub text_buffer:&'a TextBuffer,
-    pub line_counter:usize,
-    pub offset:usize,
-    iter:std::slice::Iter<'a, char>
-}
-
-impl<'a> TokenizerState<'a>{
-    pub fn new(text_buffer:&'a TextBuffer)->Self{
-        let mut ret = Self{
-            text_buffer:text_buffer,
-            line_counter:0,
-            offset:0,
-            prev:'\0',
-            cur:'\0',
-            next:'\0',
-            iter:text_buffer.lines[0].iter()
-        };
-        ret.advance_with_cur();
-        ret
-    }
-
-    pub fn advance(&mut self){
-        if let Some(next) = self.iter.next(){
-            self.next = *next;
-            self.offset += 1;
+    // undo/redo along the tree to any node, through their common ancestor
+    pub fn goto_undo_node(&mut self, target:usize, cursor_set:&mut CursorSet){
+        let (undo, redo) = self.undo_tree.path_to(target);
+        for _ in undo{
+            self.undo_step(cursor_set);
         }
-        else{
-            if self.line_counter < self.text_buffer.lines.len() - 1{
-                self.line_counter += 1;
-                self.offset += 1;
-                self.iter = self.text_buffer.lines[self.line_counter].iter();
-                self.next = '\n'
-            }
-            else{
-                self.offset += 1;
-                self.next = '\0'
-            }
+        for id in redo{
+            self.redo_step(id, cursor_set);
         }
     }
 
-    pub fn next_is_digit(&self)->bool{
-        self.next >= '0' && self.next <='9'
-    }
-
-    pub fn next_is_letter(&self)->bool{
-        self.next >= 'a' && self.next <='z' || self.next >= 'A' && self.next <='Z'
-    }
-
-    pub fn next_is_lowercase_letter(&self)->bool{
-        self.next >= 'a' && self.next <='z' 
-    }
-
-    pub fn next_is_uppercase_letter(&self)->bool{
-        self.next >= 'A' && self.next <='Z' 
-    }
-
-    pub fn next_is_hex(&self)->bool{
-        self.next >= '0' && self.next <='9' || self.next >= 'a' && self.next <= 'f' || self.next >= 'A' && self.next <='F'
-    }
-
-    pub fn advance_with_cur(&mut self){
-        self.cur = self.next;
-        self.advance();
+    // switches to the next or previous branch at the closest branch point, and redoes to its tip
+    pub fn switch_undo_branch(&mut self, delta:isize, cursor_set:&mut CursorSet){
+        if let Some(branch_point) = self.undo_tree.branch_point(){
+            let node = &self.undo_tree.nodes[branch_point];
+            let count = node.children.len() as isize;
+            let index = ((node.redo_child as isize + delta) % count + count) % count;
+            let target = self.undo_tree.branch_tip(node.children[index as usize]);
+            self.goto_undo_node(target, cursor_set);
+        }
     }
 
-    pub fn advance_with_prev(&mut self){
-        self.prev = self.cur;
-        self.cur = self.next;
-        self.advance();
+    // jumps to how the buffer looked some seconds before (negative) or after the current state
+    pub fn undo_by_time(&mut self, seconds:f64, cursor_set:&mut CursorSet){
+        let time = self.undo_tree.node_time(self.undo_tree.current) + seconds;
+        let target = self.undo_tree.find_by_time(time);
+        self.goto_undo_node(target, cursor_set);
     }
 
-    pub fn keyword(&mut self, chunk:&mut Vec<char>, word:&str)->bool{
-        for m in word.chars(){
-            if m == self.next{ 
-                chunk.push(m);
-                self.advance();
-            }
-            else{
-                return false
-            }
-        }
-        return true
-    }
 }
 
 #[derive(Clone)]
@@ -459,8 +849,8 @@ impl Cursor{
         let (row, _col) = text_buffer.offset_to_row_col(self.head);
 
         // alright lets walk the line from the left till its no longer 9 or 32
-        for (pos,ch) in text_buffer.lines[row].iter().enumerate(){
-            if *ch != '\t' && *ch != ' '{
+        for (pos,ch) in text_buffer.line_chars(row).enumerate(){
+            if ch != '\t' && ch != ' '{
                 self.head = text_buffer.row_col_to_offset(row, pos);
                 self.calc_max(text_buffer);
                 return
@@ -471,7 +861,7 @@ impl Cursor{
     pub fn move_end(&mut self, text_buffer:&TextBuffer){
         let (row, _col) = text_buffer.offset_to_row_col(self.head);
         // alright lets walk the line from the left till its no longer 9 or 32
-        self.head = text_buffer.row_col_to_offset(row, text_buffer.lines[row].len());
+        self.head = text_buffer.row_col_to_offset(row, text_buffer.get_line_len(row));
         self.calc_max(text_buffer);
     }
 
@@ -495,10 +885,21 @@ impl Cursor{
         self.calc_max(text_buffer);
     }
 
+    pub fn move_word_left(&mut self, text_buffer:&TextBuffer){
+        self.head = text_buffer.word_left_offset(self.head);
+        self.calc_max(text_buffer);
+    }
+
+    pub fn move_word_right(&mut self, text_buffer:&TextBuffer){
+        self.head = text_buffer.word_right_offset(self.head);
+        self.calc_max(text_buffer);
+    }
+
+    // moves over folded lines as if they weren't there
     pub fn move_up(&mut self, line_count:usize, text_buffer:&TextBuffer){
         let (row,_col) = text_buffer.offset_to_row_col(self.head);
-        if row >= line_count {
-            self.head = text_buffer.row_col_to_offset(row - line_count, self.max);
+        if let Some(row) = text_buffer.folds.visible_row_above(row, line_count){
+            self.head = text_buffer.row_col_to_offset(row, self.max);
         }
         else{
             self.head = 0;
@@ -507,13 +908,14 @@ impl Cursor{
     
     pub fn move_down(&mut self, line_count:usize, text_buffer:&TextBuffer){
         let (row,_col) = text_buffer.offset_to_row_col(self.head);
-        
-        if row + line_count < text_buffer.get_line_count() - 1{
-            
-            self.head = text_buffer.row_col_to_offset(row + line_count, self.max);
-        }
-        else{
-            self.head = text_buffer.get_char_count() - 1;
+        let total_lines = text_buffer.get_line_count();
+        match text_buffer.folds.visible_row_below(row, line_count, total_lines){
+            Some(row) if row < total_lines - 1=>{
+                self.head = text_buffer.row_col_to_offset(row, self.max);
+            },
+            _=>{
+                self.head = text_buffer.get_char_count() - 1;
+            }
         }
     }
 }
@@ -532,16 +934,37 @@ impl CursorSet{
         }
     }
 
+    // the selections of several cursors go on lines of their own, so pasting them back with as many
+    // cursors gives every cursor its own
     pub fn get_all_as_string(&self, text_buffer:&TextBuffer)->String{
         let mut ret = String::new();
-        for cursor in &self.set{
+        for (index, cursor) in self.set.iter().enumerate(){
+            if index > 0{
+                ret.push('\n');
+            }
             let (start, end) = cursor.order();
             text_buffer.get_range_as_string(start, end-start, &mut ret);
         }
         ret
     }
 
+    pub fn get_last_cursor_as_string(&self, text_buffer:&TextBuffer)->String{
+        let mut ret = String::new();
+        let (start, end) = self.set[self.last_cursor].order();
+        text_buffer.get_range_as_string(start, end-start, &mut ret);
+        ret
+    }
+
+    // merges cursors that overlap, and keeps them sorted on where they start
     pub fn fuse_adjacent(&mut self, text_buffer:&TextBuffer){
+        // commands that add cursors can put them anywhere
+        if self.set.windows(2).any(|pair| pair[0].order().0 > pair[1].order().0){
+            let mut indexed:Vec<(usize, Cursor)> = self.set.drain(..).enumerate().collect();
+            indexed.sort_by_key(|(_, cursor)| cursor.order().0);
+            let last_cursor = self.last_cursor;
+            self.last_cursor = indexed.iter().position(|(index, _)| *index == last_cursor).unwrap_or(0);
+            self.set = indexed.into_iter().map(|(_, cursor)| cursor).collect();
+        }
         let mut index = 0;
         loop{
             if self.set.len() < 2 || index >= self.set.len() - 1{ // no more pairs
@@ -550,7 +973,15 @@ impl CursorSet{
             // get the pair data
             let (my_start,my_end) = self.set[index].order();
             let (next_start,next_end) = self.set[index+1].order();
-            if my_end >= next_start{ // fuse them together
+            // selections that only touch stay apart, like two occurrences next to each other,
+            // but a caret touching anything is part of it
+            let overlaps = if my_start != my_end && next_start != next_end{
+                my_end > next_start
+            }
+            else{
+                my_end >= next_start
+            };
+            if overlaps{ // fuse them together
                 // check if we are mergin down or up
                 if my_end < next_end{ // otherwise just remove the next
                     if self.set[index].tail>self.set[index].head{ // down
@@ -568,6 +999,8 @@ impl CursorSet{
                     self.last_cursor -= 1;
                 }
                 self.set.remove(index + 1);
+                // the fused cursor can reach the one after it as well
+                continue
             }
             index += 1;
         }
@@ -670,12 +1103,33 @@ impl CursorSet{
             delta += cursor.collapse(start, end, op.len);
             ops.push(op);
         }
-        text_buffer.redo_stack.truncate(0);
-        text_buffer.undo_stack.push(TextUndo{
-            ops:ops,
-            grouping:grouping,
-            cursors:cursors_clone
-        })
+        text_buffer.push_undo(ops, grouping, cursors_clone);
+    }
+
+    // like replace_text but with its own text for every cursor, as one undo step
+    pub fn replace_texts(&mut self, texts:&[String], text_buffer:&mut TextBuffer){
+        let mut delta:isize = 0;
+        let mut ops = Vec::new();
+        let cursors_clone = self.clone();
+        for (cursor, text) in self.set.iter_mut().zip(texts){
+            let (start, end) = cursor.delta(delta);
+            let op = text_buffer.replace_with_string(start, end-start, text);
+            delta += cursor.collapse(start, end, op.len);
+            ops.push(op);
+        }
+        text_buffer.push_undo(ops, TextUndoGrouping::Block, cursors_clone);
+    }
+
+    // with as many lines as cursors every cursor gets its own line, otherwise they all get everything
+    pub fn paste_text(&mut self, text:&str, text_buffer:&mut TextBuffer){
+        let text = text.replace("\r\n", "\n");
+        let lines:Vec<String> = text.trim_end_matches('\n').split('\n').map(|line| line.to_string()).collect();
+        if self.set.len() > 1 && lines.len() == self.set.len(){
+            self.replace_texts(&lines, text_buffer);
+        }
+        else{
+            self.replace_text(&text, text_buffer);
+        }
     }
 
     pub fn delete(&mut self, text_buffer:&mut TextBuffer){
@@ -695,12 +1149,7 @@ impl CursorSet{
                 delta += cursor.collapse(start, end, 0);
             }
         }
-        text_buffer.redo_stack.truncate(0);
-        text_buffer.undo_stack.push(TextUndo{
-            ops:ops,
-            grouping:TextUndoGrouping::Delete,
-            cursors:cursors_clone
-        })
+        text_buffer.push_undo(ops, TextUndoGrouping::Delete, cursors_clone);
     }
 
     pub fn backspace(&mut self, text_buffer:&mut TextBuffer){
@@ -720,12 +1169,41 @@ impl CursorSet{
                 delta += cursor.collapse(start, end, 0);
             }
         }
-        text_buffer.redo_stack.truncate(0);
-        text_buffer.undo_stack.push(TextUndo{
-            ops:ops,
-            grouping:TextUndoGrouping::Backspace,
-            cursors:cursors_clone
-        })
+        text_buffer.push_undo(ops, TextUndoGrouping::Backspace, cursors_clone);
+    }
+
+    // deletes the selections, or the word before each cursor
+    pub fn backspace_word(&mut self, text_buffer:&mut TextBuffer){
+        let mut delta:isize = 0;
+        let mut ops = Vec::new();
+        let cursors_clone = self.clone();
+        for cursor in &mut self.set{
+            let (start, end) = cursor.delta(delta);
+            let start = if start == end{text_buffer.word_left_offset(start)}else{start};
+            if start != end{
+                let op = text_buffer.replace_with_string(start, end - start, "");
+                ops.push(op);
+                delta += cursor.collapse(start, end, 0);
+            }
+        }
+        text_buffer.push_undo(ops, TextUndoGrouping::Backspace, cursors_clone);
+    }
+
+    // deletes the selections, or the word after each cursor
+    pub fn delete_word(&mut self, text_buffer:&mut TextBuffer){
+        let mut delta:isize = 0;
+        let mut ops = Vec::new();
+        let cursors_clone = self.clone();
+        for cursor in &mut self.set{
+            let (start, end) = cursor.delta(delta);
+            let end = if start == end{text_buffer.word_right_offset(end)}else{end};
+            if start != end{
+                let op = text_buffer.replace_with_string(start, end - start, "");
+                ops.push(op);
+                delta += cursor.collapse(start, end, 0);
+            }
+        }
+        text_buffer.push_undo(ops, TextUndoGrouping::Delete, cursors_clone);
     }
 
     pub fn select_all(&mut self, text_buffer:&mut TextBuffer){
@@ -786,4 +1264,53 @@ impl CursorSet{
         }
         self.fuse_adjacent(text_buffer)
     }
+
+    pub fn move_word_left(&mut self, only_head:bool, text_buffer:&TextBuffer){
+        for cursor in &mut self.set{
+            cursor.move_word_left(text_buffer);
+            if !only_head{cursor.tail = cursor.head}
+        }
+        self.fuse_adjacent(text_buffer)
+    }
+
+    pub fn move_word_right(&mut self, only_head:bool, text_buffer:&TextBuffer){
+        for cursor in &mut self.set{
+            cursor.move_word_right(text_buffer);
+            if !only_head{cursor.tail = cursor.head}
+        }
+        self.fuse_adjacent(text_buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    // fuses cursors given as (head, tail) and hands back what is left
+    fn fuse(text:&str, cursors:&[(usize, usize)])->Vec<(usize, usize)>{
+        let mut text_buffer = TextBuffer::default();
+        text_buffer.load_buffer(&text.as_bytes().to_vec()).unwrap();
+        let mut cursor_set = CursorSet::new();
+        cursor_set.set = cursors.iter().map(|(head, tail)| Cursor{head:*head, tail:*tail, max:0}).collect();
+        cursor_set.last_cursor = cursor_set.set.len() - 1;
+        cursor_set.fuse_adjacent(&text_buffer);
+        cursor_set.set.iter().map(|cursor| (cursor.head, cursor.tail)).collect()
+    }
+
+    #[test]
+    fn touching_selections_stay_apart(){
+        assert_eq!(fuse("abab", &[(2, 0), (4, 2)]), vec![(2, 0), (4, 2)]);
+    }
+
+    #[test]
+    fn caret_touching_selection_fuses(){
+        assert_eq!(fuse("0123456", &[(5, 3), (5, 5)]), vec![(5, 3)]);
+        assert_eq!(fuse("0123456", &[(3, 3), (5, 3)]).len(), 1);
+    }
+
+    #[test]
+    fn overlapping_selections_fuse(){
+        assert_eq!(fuse("0123456", &[(4, 1), (6, 3)]).len(), 1);
+        assert_eq!(fuse("0123456", &[(2, 2), (2, 2)]), vec![(2, 2)]);
+    }
 }
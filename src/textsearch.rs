@@ -0,0 +1,126 @@
+use crate::regex::*;
+use crate::textbuffer::*;
+
+#[derive(Clone, Default, PartialEq)]
+pub struct SearchOptions{
+    pub ignore_case:bool,
+    pub whole_word:bool,
+    pub regex:bool
+}
+
+#[derive(Clone, PartialEq)]
+pub struct SearchMatch{
+    pub start:usize,
+    pub end:usize,
+    // the ranges of the regex groups, group 0 is the whole match
+    pub groups:Vec<Option<(usize, usize)>>
+}
+
+#[derive(Clone)]
+pub struct TextSearch{
+    pub pattern:String,
+    pub options:SearchOptions,
+    regex:Regex
+}
+
+impl TextSearch{
+    pub fn new(pattern:&str, options:&SearchOptions)->Result<TextSearch, String>{
+        let regex = if options.regex{
+            Regex::new(pattern, options.ignore_case)?
+        }
+        else{
+            Regex::literal(pattern, options.ignore_case)?
+        };
+        Ok(TextSearch{
+            pattern:pattern.to_string(),
+            options:options.clone(),
+            regex:regex
+        })
+    }
+
+    pub fn find_all(&self, text:&[char])->Vec<SearchMatch>{
+        let mut matches = Vec::new();
+        if self.pattern.len() == 0{
+            return matches
+        }
+        let mut pos = 0;
+        while pos <= text.len(){
+            let groups = if let Some(groups) = self.regex.find_at(text, pos){groups}else{break};
+            let (start, end) = groups[0].unwrap();
+            if self.options.whole_word && !Self::is_whole_word(text, start, end){
+                pos = start + 1;
+                continue
+            }
+            // step over empty matches so we don't find them again
+            pos = if end > start{end}else{end + 1};
+            matches.push(SearchMatch{
+                start:start,
+                end:end,
+                groups:groups
+            });
+        }
+        matches
+    }
+
+    pub fn find_in_text_buffer(&self, text_buffer:&TextBuffer)->Vec<SearchMatch>{
+        self.find_all(&text_buffer.rope.to_vec())
+    }
+
+    fn is_whole_word(text:&[char], start:usize, end:usize)->bool{
+        (start == 0 || !is_word_char(text[start - 1])) && (end == text.len() || !is_word_char(text[end]))
+    }
+
+    // the text a match is replaced with, in regex mode $0-$9 insert a group and $$ is a $
+    pub fn expand_replace(&self, replace:&str, search_match:&SearchMatch, text:&[char])->String{
+        if !self.options.regex{
+            return replace.to_string()
+        }
+        let mut out = String::new();
+        let mut chars = replace.chars().peekable();
+        while let Some(ch) = chars.next(){
+            if ch != '$'{
+                out.push(ch);
+                continue
+            }
+            match chars.peek().cloned(){
+                Some('$')=>{
+                    chars.next();
+                    out.push('$');
+                },
+                Some(digit) if digit.is_ascii_digit()=>{
+                    chars.next();
+                    let group = digit.to_digit(10).unwrap() as usize;
+                    if let Some(Some((start, end))) = search_match.groups.get(group){
+                        out.extend(&text[*start..*end]);
+                    }
+                },
+                _=>out.push('$')
+            }
+        }
+        out
+    }
+}
+
+impl CursorSet{
+    // turns every match into a selection, keeping the last cursor on the match nearest to where it was
+    pub fn select_matches(&mut self, matches:&[SearchMatch], text_buffer:&TextBuffer){
+        if matches.len() == 0{
+            return
+        }
+        let head = self.set[self.last_cursor].head;
+        self.set.truncate(0);
+        self.last_cursor = 0;
+        for (index, search_match) in matches.iter().enumerate(){
+            if search_match.end <= head{
+                self.last_cursor = index;
+            }
+            let mut cursor = Cursor{
+                head:search_match.end,
+                tail:search_match.start,
+                max:0
+            };
+            cursor.calc_max(text_buffer);
+            self.set.push(cursor);
+        }
+    }
+}
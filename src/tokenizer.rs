@@ -0,0 +1,299 @@
+use crate::rope::*;
+use crate::textbuffer::*;
+
+// What a tokenizer calls a piece of text. Editors map these to colors, so a new language
+// only has to split text up, not draw it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TokenType{
+    Whitespace,
+    Newline,
+    Keyword,
+    Flow,
+    Identifier,
+    Call,
+    TypeName,
+    String,
+    Number,
+    Comment,
+    DocComment,
+    Paren,
+    Operator,
+    Delimiter,
+    Eof
+}
+
+// Tokens never span a line, a '\n' is always a Newline token of its own. Whatever carries over
+// to the next line, like being inside a block comment, is the line state.
+pub trait Tokenizer{
+    // reads the token after state.cur into chunk. it ends at state.offset - 1
+    fn next_token<'a>(&mut self, state:&mut TokenizerState<'a>, chunk:&mut Vec<char>)->TokenType;
+
+    // packed into a number so a TextBuffer can cache it per line without knowing the language
+    fn line_state(&self)->u64{
+        0
+    }
+
+    fn set_line_state(&mut self, _line_state:u64){
+    }
+
+    // the comment markers of the language, for the comment commands
+    fn line_comment(&self)->Option<&'static str>{
+        None
+    }
+
+    fn block_comment(&self)->Option<(&'static str, &'static str)>{
+        None
+    }
+
+    // the brackets and quotes that close themselves when typed
+    fn auto_close_pairs(&self)->&'static [(char, char)]{
+        &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')]
+    }
+}
+
+// The tokenizer line state at the start of each line, so drawing can start tokenizing at the
+// first visible line instead of at the top of the file. After an edit the lines below keep
+// their old states as guesses, when tokenizing reaches one that still matches past the edits
+// everything below it is right again.
+#[derive(Clone, Default)]
+pub struct TokenCache{
+    pub line_states:Vec<Option<u64>>,
+    // the states of the rows before this are known to be right
+    pub valid_rows:usize,
+    // states can't converge before this row, it is below the last pending edit
+    pub _dirty_end:usize
+}
+
+impl TokenCache{
+    pub fn clear(&mut self){
+        self.line_states.truncate(0);
+        self.valid_rows = 0;
+        self._dirty_end = 0;
+    }
+
+    // an edit on row replaced removed_rows line breaks with inserted_rows new ones
+    pub fn invalidate(&mut self, row:usize, removed_rows:usize, inserted_rows:usize){
+        if row + 1 < self.line_states.len(){
+            let end = (row + 1 + removed_rows).min(self.line_states.len());
+            self.line_states.splice(row + 1..end, (0..inserted_rows).map(|_| None));
+        }
+        if self._dirty_end > row + removed_rows{ // an earlier edit below moves with its lines
+            self._dirty_end = self._dirty_end + inserted_rows - removed_rows;
+        }
+        self._dirty_end = self._dirty_end.max(row + inserted_rows + 1);
+        self.valid_rows = self.valid_rows.min(row + 1);
+    }
+
+    // the row to start tokenizing at to get to row, with its line state
+    pub fn start_for_row(&self, row:usize)->(usize, u64){
+        let row = row.min(self.valid_rows.max(1) - 1);
+        if row == 0{
+            return (0, 0)
+        }
+        (row, self.line_states[row].unwrap_or(0))
+    }
+
+    // called for every line start reached while tokenizing forward
+    pub fn set_line_state(&mut self, row:usize, line_state:u64){
+        if row < self.valid_rows{
+            return
+        }
+        if self.line_states.len() == 0{ // the first line always starts in the initial state
+            self.line_states.push(Some(0));
+        }
+        if row < self.line_states.len(){
+            let converged = row >= self._dirty_end && self.line_states[row] == Some(line_state);
+            self.line_states[row] = Some(line_state);
+            if converged{
+                self.valid_rows = self.line_states.len();
+                self._dirty_end = 0;
+            }
+            else{
+                self.valid_rows = row + 1;
+            }
+        }
+        else{
+            self.line_states.push(Some(line_state));
+            self.valid_rows = self.line_states.len();
+        }
+    }
+}
+
+// words and whitespace, for files we don't know the language of
+#[derive(Clone, Default)]
+pub struct PlainTokenizer{
+}
+
+impl PlainTokenizer{
+    fn is_break(ch:char)->bool{
+        match ch{
+            ' ' | '\t' | '\n' | '\0' | '(' | ')' | '[' | ']' | '{' | '}'=>true,
+            _=>false
+        }
+    }
+}
+
+impl Tokenizer for PlainTokenizer{
+    fn next_token<'a>(&mut self, state:&mut TokenizerState<'a>, chunk:&mut Vec<char>)->TokenType{
+        state.advance_with_cur();
+        match state.cur{
+            '\0'=>TokenType::Eof,
+            '\n'=>{
+                chunk.push('\n');
+                TokenType::Newline
+            },
+            ' ' | '\t'=>{
+                chunk.push(state.cur);
+                while state.next == ' ' || state.next == '\t'{
+                    chunk.push(state.next);
+                    state.advance();
+                }
+                TokenType::Whitespace
+            },
+            '(' | ')' | '[' | ']' | '{' | '}'=>{ // so brackets match in any file
+                chunk.push(state.cur);
+                TokenType::Paren
+            },
+            _=>{
+                chunk.push(state.cur);
+                while !Self::is_break(state.next){
+                    chunk.push(state.next);
+                    state.advance();
+                }
+                TokenType::Identifier
+            }
+        }
+    }
+}
+
+// The tokens of a buffer as (range, type), for code that wants to know where tokens are
+// rather than read their chars. Ranges are char offsets, a Newline token is its '\n'.
+pub struct TokenRanges<'a, 't, T:Tokenizer>{
+    tokenizer:&'t mut T,
+    state:TokenizerState<'a>,
+    chunk:Vec<char>
+}
+
+impl<'a, 't, T:Tokenizer> TokenRanges<'a, 't, T>{
+    pub fn new(text_buffer:&'a TextBuffer, tokenizer:&'t mut T)->Self{
+        Self::new_at_row(text_buffer, tokenizer, 0, 0)
+    }
+
+    // row has to start in line_state, like the token cache has it
+    pub fn new_at_row(text_buffer:&'a TextBuffer, tokenizer:&'t mut T, row:usize, line_state:u64)->Self{
+        tokenizer.set_line_state(line_state);
+        Self{
+            tokenizer:tokenizer,
+            state:TokenizerState::new_at_row(text_buffer, row),
+            chunk:Vec::new()
+        }
+    }
+}
+
+impl<'a, 't, T:Tokenizer> Iterator for TokenRanges<'a, 't, T>{
+    type Item = (std::ops::Range<usize>, TokenType);
+
+    fn next(&mut self)->Option<Self::Item>{
+        self.chunk.truncate(0);
+        let token_type = self.tokenizer.next_token(&mut self.state, &mut self.chunk);
+        if token_type == TokenType::Eof{
+            return None
+        }
+        let end = self.state.offset - 1;
+        Some((end - self.chunk.len()..end, token_type))
+    }
+}
+
+pub struct TokenizerState<'a>{
+    pub prev:char,
+    pub cur:char,
+    pub next:char,
+    pub text_buffer:&'a TextBuffer,
+    pub line_counter:usize,
+    pub offset:usize,
+    iter:RopeChars<'a>
+}
+
+impl<'a> TokenizerState<'a>{
+    pub fn new(text_buffer:&'a TextBuffer)->Self{
+        Self::new_at_row(text_buffer, 0)
+    }
+
+    pub fn new_at_row(text_buffer:&'a TextBuffer, row:usize)->Self{
+        let offset = text_buffer.rope.row_to_offset(row);
+        let mut ret = Self{
+            text_buffer:text_buffer,
+            line_counter:row,
+            offset:offset,
+            prev:'\0',
+            cur:'\0',
+            next:'\0',
+            iter:text_buffer.rope.chars_at(offset)
+        };
+        ret.advance_with_cur();
+        ret
+    }
+
+    pub fn advance(&mut self){
+        if let Some(next) = self.iter.next(){
+            if next == '\n'{
+                self.line_counter += 1;
+            }
+            self.next = next;
+            self.offset += 1;
+        }
+        else{
+            self.offset += 1;
+            self.next = '\0'
+        }
+    }
+
+    pub fn next_is_digit(&self)->bool{
+        self.next >= '0' && self.next <='9'
+    }
+
+    pub fn next_is_letter(&self)->bool{
+        self.next >= 'a' && self.next <='z' || self.next >= 'A' && self.next <='Z'
+    }
+
+    pub fn next_is_lowercase_letter(&self)->bool{
+        self.next >= 'a' && self.next <='z' 
+    }
+
+    pub fn next_is_uppercase_letter(&self)->bool{
+        self.next >= 'A' && self.next <='Z' 
+    }
+
+    pub fn next_is_hex(&self)->bool{
+        self.next >= '0' && self.next <='9' || self.next >= 'a' && self.next <= 'f' || self.next >= 'A' && self.next <='F'
+    }
+
+    // the char after next, for the few places one char lookahead isn't enough
+    pub fn peek_after_next(&self)->char{
+        self.iter.clone().next().unwrap_or('\0')
+    }
+
+    pub fn advance_with_cur(&mut self){
+        self.cur = self.next;
+        self.advance();
+    }
+
+    pub fn advance_with_prev(&mut self){
+        self.prev = self.cur;
+        self.cur = self.next;
+        self.advance();
+    }
+
+    pub fn keyword(&mut self, chunk:&mut Vec<char>, word:&str)->bool{
+        for m in word.chars(){
+            if m == self.next{ 
+                chunk.push(m);
+                self.advance();
+            }
+            else{
+                return false
+            }
+        }
+        return true
+    }
+}
@@ -0,0 +1,394 @@
+use crate::textbuffer::*;
+use crate::undotree::*;
+
+// Undo history on disk. A little endian binary format with a version, so a file written by
+// another version is ignored instead of misread. The history is always written with the
+// saved state as current node, and only restored if the file on disk still hashes the same.
+//
+// file:      "MPUNDO" u32:version u64:content_hash u32:current u32:node_count node*
+// node:      u32:parent u32:redo_child f64:time u32:child_count u32:child* u8:has_undo TextUndo?
+// TextUndo:  u8:grouping CursorSet u32:op_count TextOp*
+// TextOp:    u64:start u64:len u32:line_count (u32:char_count u32:char*)*
+// CursorSet: u32:last_cursor u32:cursor_count (u64:head u64:tail u64:max)*
+
+const UNDO_FILE_MAGIC:&[u8] = b"MPUNDO";
+const UNDO_FILE_VERSION:u32 = 2; // 2: node times are wall clock seconds
+
+// FNV-1a, stable across Rust versions unlike the std hashers
+pub fn content_hash(data:&[u8])->u64{
+    let mut hash:u64 = 0xcbf29ce484222325;
+    for byte in data{
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+pub struct UndoFileWriter{
+    pub data:Vec<u8>
+}
+
+impl UndoFileWriter{
+    pub fn new()->UndoFileWriter{
+        UndoFileWriter{
+            data:Vec::new()
+        }
+    }
+
+    pub fn u8(&mut self, v:u8){
+        self.data.push(v);
+    }
+
+    pub fn u32(&mut self, v:u32){
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, v:u64){
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn f64(&mut self, v:f64){
+        self.u64(v.to_bits());
+    }
+}
+
+pub struct UndoFileReader<'a>{
+    pub data:&'a [u8],
+    pub offset:usize
+}
+
+impl<'a> UndoFileReader<'a>{
+    pub fn new(data:&'a [u8])->UndoFileReader<'a>{
+        UndoFileReader{
+            data:data,
+            offset:0
+        }
+    }
+
+    fn bytes(&mut self, len:usize)->Option<&'a [u8]>{
+        if self.offset + len > self.data.len(){
+            return None
+        }
+        let bytes = &self.data[self.offset..self.offset + len];
+        self.offset += len;
+        Some(bytes)
+    }
+
+    pub fn u8(&mut self)->Option<u8>{
+        Some(self.bytes(1)?[0])
+    }
+
+    pub fn u32(&mut self)->Option<u32>{
+        let b = self.bytes(4)?;
+        Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn u64(&mut self)->Option<u64>{
+        let b = self.bytes(8)?;
+        Some(u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+    }
+
+    pub fn f64(&mut self)->Option<f64>{
+        Some(f64::from_bits(self.u64()?))
+    }
+
+    // counts are checked against what is left so a corrupt file can't make us allocate the world
+    pub fn count(&mut self, min_item_size:usize)->Option<usize>{
+        let count = self.u32()? as usize;
+        if count * min_item_size > self.data.len() - self.offset{
+            return None
+        }
+        Some(count)
+    }
+}
+
+impl TextOp{
+    pub fn write_undo_file(&self, w:&mut UndoFileWriter){
+        w.u64(self.start as u64);
+        w.u64(self.len as u64);
+        w.u32(self.lines.len() as u32);
+        for line in &self.lines{
+            w.u32(line.len() as u32);
+            for ch in line{
+                w.u32(*ch as u32);
+            }
+        }
+    }
+
+    pub fn read_undo_file(r:&mut UndoFileReader)->Option<TextOp>{
+        let start = r.u64()? as usize;
+        let len = r.u64()? as usize;
+        let line_count = r.count(4)?;
+        if line_count == 0{ // an op always replaces with at least one (empty) line
+            return None
+        }
+        let mut lines = Vec::with_capacity(line_count);
+        for _ in 0..line_count{
+            let char_count = r.count(4)?;
+            let mut line = Vec::with_capacity(char_count);
+            for _ in 0..char_count{
+                line.push(std::char::from_u32(r.u32()?)?);
+            }
+            lines.push(line);
+        }
+        Some(TextOp{
+            start:start,
+            len:len,
+            lines:lines
+        })
+    }
+}
+
+impl CursorSet{
+    pub fn write_undo_file(&self, w:&mut UndoFileWriter){
+        w.u32(self.last_cursor as u32);
+        w.u32(self.set.len() as u32);
+        for cursor in &self.set{
+            w.u64(cursor.head as u64);
+            w.u64(cursor.tail as u64);
+            w.u64(cursor.max as u64);
+        }
+    }
+
+    pub fn read_undo_file(r:&mut UndoFileReader)->Option<CursorSet>{
+        let last_cursor = r.u32()? as usize;
+        let count = r.count(24)?;
+        let mut set = Vec::with_capacity(count);
+        for _ in 0..count{
+            set.push(Cursor{
+                head:r.u64()? as usize,
+                tail:r.u64()? as usize,
+                max:r.u64()? as usize
+            });
+        }
+        if last_cursor >= set.len(){
+            return None
+        }
+        Some(CursorSet{
+            set:set,
+            last_cursor:last_cursor
+        })
+    }
+}
+
+impl TextUndoGrouping{
+    fn to_undo_file(&self)->u8{
+        match self{
+            TextUndoGrouping::Space=>0,
+            TextUndoGrouping::Newline=>1,
+            TextUndoGrouping::Character=>2,
+            TextUndoGrouping::Backspace=>3,
+            TextUndoGrouping::Delete=>4,
+            TextUndoGrouping::Block=>5,
+            TextUndoGrouping::Cut=>6,
+            TextUndoGrouping::Other=>7
+        }
+    }
+
+    fn from_undo_file(v:u8)->Option<TextUndoGrouping>{
+        Some(match v{
+            0=>TextUndoGrouping::Space,
+            1=>TextUndoGrouping::Newline,
+            2=>TextUndoGrouping::Character,
+            3=>TextUndoGrouping::Backspace,
+            4=>TextUndoGrouping::Delete,
+            5=>TextUndoGrouping::Block,
+            6=>TextUndoGrouping::Cut,
+            7=>TextUndoGrouping::Other,
+            _=>return None
+        })
+    }
+}
+
+impl TextUndo{
+    pub fn write_undo_file(&self, w:&mut UndoFileWriter){
+        w.u8(self.grouping.to_undo_file());
+        self.cursors.write_undo_file(w);
+        w.u32(self.ops.len() as u32);
+        for op in &self.ops{
+            op.write_undo_file(w);
+        }
+    }
+
+    pub fn read_undo_file(r:&mut UndoFileReader)->Option<TextUndo>{
+        let grouping = TextUndoGrouping::from_undo_file(r.u8()?)?;
+        let cursors = CursorSet::read_undo_file(r)?;
+        let op_count = r.count(20)?;
+        let mut ops = Vec::with_capacity(op_count);
+        for _ in 0..op_count{
+            ops.push(TextOp::read_undo_file(r)?);
+        }
+        // view ids don't outlive the session, the cursors go back in whichever view undoes
+        Some(TextUndo{
+            ops:ops,
+            grouping:grouping,
+            cursors:cursors,
+            view_id:0
+        })
+    }
+}
+
+impl UndoTree{
+    pub fn write_undo_file(&self, w:&mut UndoFileWriter){
+        w.u32(self.current as u32);
+        w.u32(self.nodes.len() as u32);
+        for node in &self.nodes{
+            w.u32(node.parent as u32);
+            w.u32(node.redo_child as u32);
+            w.f64(node.time);
+            w.u32(node.children.len() as u32);
+            for child in &node.children{
+                w.u32(*child as u32);
+            }
+            if let Some(text_undo) = &node.text_undo{
+                w.u8(1);
+                text_undo.write_undo_file(w);
+            }
+            else{
+                w.u8(0);
+            }
+        }
+    }
+
+    pub fn read_undo_file(r:&mut UndoFileReader, char_count:usize)->Option<UndoTree>{
+        let current = r.u32()? as usize;
+        let node_count = r.count(21)?;
+        let mut nodes = Vec::with_capacity(node_count);
+        for _ in 0..node_count{
+            let parent = r.u32()? as usize;
+            let redo_child = r.u32()? as usize;
+            let time = r.f64()?;
+            let child_count = r.count(4)?;
+            let mut children = Vec::with_capacity(child_count);
+            for _ in 0..child_count{
+                let child = r.u32()? as usize;
+                if child >= node_count{
+                    return None
+                }
+                children.push(child);
+            }
+            let text_undo = if r.u8()? != 0{
+                Some(TextUndo::read_undo_file(r)?)
+            }
+            else{
+                None
+            };
+            if parent >= node_count || (redo_child > 0 && redo_child >= children.len()){
+                return None
+            }
+            nodes.push(UndoNode{
+                parent:parent,
+                children:children,
+                redo_child:redo_child,
+                time:time,
+                text_undo:text_undo
+            });
+        }
+        if node_count == 0 || current >= node_count{
+            return None
+        }
+        let undo_tree = UndoTree{
+            nodes:nodes,
+            current:current
+        };
+        if !undo_tree.is_well_formed() || !undo_tree.ops_fit(char_count){
+            return None
+        }
+        Some(undo_tree)
+    }
+
+    // parents come before their children and the child lists agree with the parents,
+    // which the undo walks rely on to terminate and to not index out of the tree
+    fn is_well_formed(&self)->bool{
+        for (id, node) in self.nodes.iter().enumerate(){
+            if id == 0 && node.parent != 0 || id > 0 && node.parent >= id{
+                return false
+            }
+            if id > 0 && !self.nodes[node.parent].children.contains(&id){
+                return false
+            }
+            if node.children.iter().any(|child| self.nodes[*child].parent != id || *child == 0){
+                return false
+            }
+            if node.redo_child >= node.children.len().max(1){
+                return false
+            }
+        }
+        true
+    }
+
+    // replays the char counts of every node, starting from the buffer at the current node,
+    // so no op can reach past the text it will be applied to
+    fn ops_fit(&self, char_count:usize)->bool{
+        fn apply(node:&UndoNode, char_count:usize)->Option<usize>{
+            let mut char_count = char_count;
+            if let Some(text_undo) = &node.text_undo{
+                for op in text_undo.ops.iter().rev(){ // undoredo applies them back to front
+                    if op.start.checked_add(op.len)? > char_count{
+                        return None
+                    }
+                    char_count = char_count - op.len + TextBuffer::compute_char_count(&op.lines);
+                }
+            }
+            Some(char_count)
+        }
+        let mut char_counts = vec![None; self.nodes.len()];
+        // the path to the root holds undo ops, applied from the node walking to its parent
+        let mut id = self.current;
+        char_counts[id] = Some(char_count);
+        while id != 0{
+            let parent = self.nodes[id].parent;
+            char_counts[parent] = match apply(&self.nodes[id], char_counts[id].unwrap()){
+                Some(char_count)=>Some(char_count),
+                None=>return false
+            };
+            id = parent;
+        }
+        // every other node holds redo ops, applied from its parent
+        for id in 1..self.nodes.len(){
+            if char_counts[id].is_none(){
+                char_counts[id] = match apply(&self.nodes[id], char_counts[self.nodes[id].parent].unwrap()){
+                    Some(char_count)=>Some(char_count),
+                    None=>return false
+                };
+            }
+        }
+        true
+    }
+}
+
+impl TextBuffer{
+    pub fn serialize_undo_history(&self)->Vec<u8>{
+        // the file on disk is the saved state, so walk a copy of the history there first
+        let mut text_buffer = self.clone();
+        let mut cursor_set = CursorSet::new();
+        text_buffer.goto_undo_node(self._saved_undo_id, &mut cursor_set);
+
+        let mut w = UndoFileWriter::new();
+        w.data.extend_from_slice(UNDO_FILE_MAGIC);
+        w.u32(UNDO_FILE_VERSION);
+        w.u64(self._saved_hash);
+        text_buffer.undo_tree.write_undo_file(&mut w);
+        w.data
+    }
+
+    // restores a serialized history, if it belongs to the file we just loaded
+    pub fn load_undo_history(&mut self, data:&[u8])->bool{
+        if self.undo_tree.nodes.len() > 1{ // already edited, don't mix histories
+            return false
+        }
+        let mut r = UndoFileReader::new(data);
+        if r.bytes(UNDO_FILE_MAGIC.len()) != Some(UNDO_FILE_MAGIC) || r.u32() != Some(UNDO_FILE_VERSION){
+            return false
+        }
+        if r.u64() != Some(self._saved_hash){
+            return false
+        }
+        if let Some(undo_tree) = UndoTree::read_undo_file(&mut r, self.get_char_count()){
+            self._saved_undo_id = undo_tree.current;
+            self.undo_tree = undo_tree;
+            return true
+        }
+        false
+    }
+}
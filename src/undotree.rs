@@ -0,0 +1,205 @@
+use crate::textbuffer::*;
+
+// Every edit becomes a child of the current node, so typing after an undo starts a new
+// branch instead of throwing away the redo history. Nodes on the path from the root to
+// `current` hold their TextUndo in undo direction, every other node holds the inverse
+// that undoredo gave back, ready to be redone.
+
+#[derive(Clone)]
+pub struct UndoNode{
+    pub parent:usize,
+    pub children:Vec<usize>,
+    // the child redo walks into, the newest one unless a branch was selected
+    pub redo_child:usize,
+    pub time:f64,
+    pub text_undo:Option<TextUndo>
+}
+
+#[derive(Clone)]
+pub struct UndoTree{
+    pub nodes:Vec<UndoNode>,
+    pub current:usize
+}
+
+impl Default for UndoTree{
+    fn default()->UndoTree{
+        UndoTree{
+            nodes:vec![UndoNode{
+                parent:0,
+                children:Vec::new(),
+                redo_child:0,
+                time:0.0,
+                text_undo:None
+            }],
+            current:0
+        }
+    }
+}
+
+// seconds since the unix epoch, node times are saved with the history so they have to outlive
+// the session. wasm has no clock without a js import, there the event times passed in are used
+#[cfg(not(target_arch = "wasm32"))]
+pub fn wall_time()->Option<f64>{
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).ok().map(|d| d.as_secs_f64())
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn wall_time()->Option<f64>{
+    None
+}
+
+impl UndoTree{
+    pub fn push(&mut self, text_undo:TextUndo, time:f64){
+        let id = self.nodes.len();
+        self.nodes.push(UndoNode{
+            parent:self.current,
+            children:Vec::new(),
+            redo_child:0,
+            time:time,
+            text_undo:Some(text_undo)
+        });
+        let parent = &mut self.nodes[self.current];
+        parent.children.push(id);
+        parent.redo_child = parent.children.len() - 1;
+        self.current = id;
+    }
+
+    // drops the newest node if it is an undone leaf, used when an edit replaces the one it just undid
+    pub fn forget_last(&mut self){
+        let id = self.nodes.len() - 1;
+        if id == 0 || id == self.current || self.nodes[id].children.len() != 0{
+            return
+        }
+        let parent_id = self.nodes[id].parent;
+        let parent = &mut self.nodes[parent_id];
+        parent.children.retain(|child| *child != id);
+        parent.redo_child = if parent.children.len() > 0{parent.children.len() - 1}else{0};
+        self.nodes.pop();
+    }
+
+    // folds the nodes pushed since start_id into one, so an edit made in several steps undoes
+    // at once. only done while they are the newest nodes and form a straight line
+    pub fn merge_since(&mut self, start_id:usize){
+        if self.current <= start_id || self.current != self.nodes.len() - 1{
+            return
+        }
+        let mut first = self.current;
+        while first > start_id && self.nodes[first].parent != start_id{
+            first = self.nodes[first].parent;
+        }
+        if first <= start_id || first == self.current{
+            return
+        }
+        for id in first + 1..self.current + 1{
+            if self.nodes[id].parent != id - 1 || self.nodes[id - 1].children != vec![id]{
+                return
+            }
+        }
+        let mut ops = Vec::new();
+        for node in self.nodes.drain(first + 1..){
+            if let Some(text_undo) = node.text_undo{
+                ops.extend(text_undo.ops);
+            }
+        }
+        let node = &mut self.nodes[first];
+        if let Some(text_undo) = &mut node.text_undo{
+            text_undo.ops.extend(ops);
+            text_undo.grouping = TextUndoGrouping::Other;
+        }
+        node.children.clear();
+        node.redo_child = 0;
+        self.current = first;
+    }
+
+    pub fn can_undo(&self)->bool{
+        self.current != 0
+    }
+
+    pub fn redo_target(&self)->Option<usize>{
+        let node = &self.nodes[self.current];
+        node.children.get(node.redo_child).cloned()
+    }
+
+    pub fn grouping(&self, id:usize)->TextUndoGrouping{
+        if let Some(text_undo) = &self.nodes[id].text_undo{
+            text_undo.grouping.clone()
+        }
+        else{
+            TextUndoGrouping::Other
+        }
+    }
+
+    // the nodes to undo and then redo to get from current to target
+    pub fn path_to(&self, target:usize)->(Vec<usize>, Vec<usize>){
+        let mut on_current_path = vec![false; self.nodes.len()];
+        let mut id = self.current;
+        on_current_path[0] = true;
+        while id != 0{
+            on_current_path[id] = true;
+            id = self.nodes[id].parent;
+        }
+        // walk up from the target till we hit the common ancestor
+        let mut redo = Vec::new();
+        let mut id = target;
+        while !on_current_path[id]{
+            redo.push(id);
+            id = self.nodes[id].parent;
+        }
+        redo.reverse();
+        let common = id;
+        let mut undo = Vec::new();
+        let mut id = self.current;
+        while id != common{
+            undo.push(id);
+            id = self.nodes[id].parent;
+        }
+        (undo, redo)
+    }
+
+    // the root has no time of its own, it counts as the time of the first edit
+    pub fn node_time(&self, id:usize)->f64{
+        if id != 0 || self.nodes.len() == 1{
+            return self.nodes[id].time
+        }
+        self.nodes[1..].iter().map(|node| node.time).fold(std::f64::INFINITY, f64::min)
+    }
+
+    // the newest node that existed at the given time
+    pub fn find_by_time(&self, time:f64)->usize{
+        let mut found = 0;
+        for (id, node) in self.nodes.iter().enumerate(){
+            if id != 0 && node.time <= time && node.time >= self.nodes[found].time{
+                found = id;
+            }
+        }
+        found
+    }
+
+    // the closest ancestor (or current itself) that has more than one branch
+    pub fn branch_point(&self)->Option<usize>{
+        let mut id = self.current;
+        loop{
+            if self.nodes[id].children.len() > 1{
+                return Some(id)
+            }
+            if id == 0{
+                return None
+            }
+            id = self.nodes[id].parent;
+        }
+    }
+
+    // follows the redo path from a node down to its tip
+    pub fn branch_tip(&self, id:usize)->usize{
+        let mut id = id;
+        loop{
+            let node = &self.nodes[id];
+            if let Some(child) = node.children.get(node.redo_child){
+                id = *child;
+            }
+            else{
+                return id
+            }
+        }
+    }
+}
@@ -0,0 +1,1573 @@
+use widgets::*;
+use std::collections::HashMap;
+use crate::textbuffer::*;
+use crate::textsearch::*;
+use crate::autoedit::*;
+
+// Vim style modal editing over the cursors of a code editor. Normal mode keys are read as
+// text so they follow the keyboard layout, and parse as [register][count]command where an
+// operator takes a [count]motion or a text object. Edits go through the CursorSet and
+// TextBuffer ops like typing does, and a change undoes as one step, its insert mode included.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VimMode{
+    Normal,
+    Insert,
+    Visual,
+    VisualLine
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VimEvent{
+    None,
+    CursorMoved,
+    Save
+}
+
+#[derive(Clone, Default)]
+pub struct VimRegister{
+    pub text:String,
+    pub linewise:bool
+}
+
+#[derive(Clone)]
+pub struct VimSearch{
+    pub pattern:String,
+    pub options:SearchOptions,
+    pub backward:bool
+}
+
+// what . repeats, the keys of a change and the text typed in the insert mode it started
+#[derive(Clone, Default)]
+pub struct VimChange{
+    pub keys:String,
+    pub text:String
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum VimOperator{
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Outdent
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum VimMotion{
+    Left,
+    Right,
+    Up,
+    Down,
+    // the bools are for WORDs, runs of anything but spaces
+    WordStart(bool),
+    WordEnd(bool),
+    WordBack(bool),
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    FirstLine,
+    LastLine,
+    FindChar{ch:char, forward:bool, till:bool},
+    RepeatFind(bool),
+    MatchBracket,
+    ParagraphForward,
+    ParagraphBack,
+    SearchNext(bool),
+    Object{ch:char, inner:bool}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum MotionKind{
+    Exclusive,
+    Inclusive,
+    Linewise
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum VimAction{
+    Move(VimMotion),
+    Operate(VimOperator, VimMotion),
+    OperateLines(VimOperator),
+    Replace(char),
+    Key(char)
+}
+
+struct VimCommand{
+    register:Option<char>,
+    count:Option<usize>,
+    action:VimAction
+}
+
+enum VimParse{
+    Incomplete,
+    Invalid,
+    Command(VimCommand)
+}
+
+// a range to operate on, start end and if it is whole lines
+type VimRange = Option<(usize, usize, bool)>;
+
+#[derive(Clone)]
+pub struct Vim{
+    pub bg:Quad,
+    pub text:Text,
+    pub mode:VimMode,
+    pub registers:HashMap<char, VimRegister>,
+    pub search:Option<VimSearch>,
+    // a : or / command being typed, starting with that char
+    pub command_line:Option<String>,
+    pub status:String,
+    // the + and * registers go to the system clipboard, the editor picks this up
+    pub clipboard_write:Option<String>,
+    pub _keys:String,
+    // the visual selection of every cursor as (anchor, position), both on a char
+    pub _visual:Vec<(usize, usize)>,
+    pub _command_rows:Option<(usize, usize)>,
+    pub _last_find:Option<(char, bool, bool)>,
+    pub _last_change:Option<VimChange>,
+    pub _change:Option<VimChange>,
+    pub _change_undo_id:usize,
+    pub _repeating:bool
+}
+
+impl Style for Vim{
+    fn style(cx:&mut Cx)->Self{
+        Self{
+            bg:Quad{
+                color:color256(45,45,45),
+                ..Style::style(cx)
+            },
+            text:Text{
+                font_id:cx.load_font(&cx.font("mono_font")),
+                font_size:11.0,
+                brightness:1.05,
+                line_spacing:1.4,
+                wrapping:Wrapping::None,
+                ..Style::style(cx)
+            },
+            mode:VimMode::Normal,
+            registers:HashMap::new(),
+            search:None,
+            command_line:None,
+            status:String::new(),
+            clipboard_write:None,
+            _keys:String::new(),
+            _visual:Vec::new(),
+            _command_rows:None,
+            _last_find:None,
+            _last_change:None,
+            _change:None,
+            _change_undo_id:0,
+            _repeating:false
+        }
+    }
+}
+
+fn is_blank(ch:char)->bool{
+    ch == ' ' || ch == '\t'
+}
+
+// 0 for spaces and newlines, runs of the same class are a word
+fn word_class(ch:char, big:bool)->u8{
+    if ch.is_whitespace(){
+        0
+    }
+    else if big || ch.is_alphanumeric() || ch == '_'{
+        1
+    }
+    else{
+        2
+    }
+}
+
+fn char_at(text_buffer:&TextBuffer, offset:usize)->char{
+    text_buffer.rope.char_at(offset).unwrap_or('\n')
+}
+
+fn row_of(text_buffer:&TextBuffer, offset:usize)->usize{
+    text_buffer.offset_to_row_col(offset).0
+}
+
+fn line_start(text_buffer:&TextBuffer, row:usize)->usize{
+    text_buffer.row_col_to_offset(row, 0)
+}
+
+fn line_end(text_buffer:&TextBuffer, row:usize)->usize{
+    line_start(text_buffer, row) + text_buffer.get_line_len(row)
+}
+
+fn first_non_blank(text_buffer:&TextBuffer, row:usize)->usize{
+    line_start(text_buffer, row) + text_buffer.line_chars(row).take_while(|ch| is_blank(*ch)).count()
+}
+
+// an empty line is a word of its own to word motions
+fn is_empty_line(text_buffer:&TextBuffer, offset:usize)->bool{
+    char_at(text_buffer, offset) == '\n' && (offset == 0 || char_at(text_buffer, offset - 1) == '\n')
+}
+
+fn range_text(text_buffer:&TextBuffer, start:usize, end:usize)->String{
+    text_buffer.rope.chars_at(start).take(end - start).collect()
+}
+
+fn lines_range(text_buffer:&TextBuffer, first:usize, last:usize)->(usize, usize, bool){
+    let end = if last + 1 < text_buffer.get_line_count(){line_start(text_buffer, last + 1)}else{text_buffer.get_char_count()};
+    (line_start(text_buffer, first), end, true)
+}
+
+fn convert_case(text:&str, how:char)->String{
+    text.chars().map(|ch| match how{
+        'u'=>ch.to_lowercase().next().unwrap_or(ch),
+        'U'=>ch.to_uppercase().next().unwrap_or(ch),
+        _=>if ch.is_lowercase(){ch.to_uppercase().next().unwrap_or(ch)}else{ch.to_lowercase().next().unwrap_or(ch)}
+    }).collect()
+}
+
+impl Vim{
+    pub fn is_visual(&self)->bool{
+        self.mode == VimMode::Visual || self.mode == VimMode::VisualLine
+    }
+
+    // the line drawn at the bottom of the editor
+    pub fn status_line(&self)->String{
+        if let Some(command_line) = &self.command_line{
+            return format!("{}|", command_line)
+        }
+        if self.status.len() > 0{
+            return self.status.clone()
+        }
+        let mode = match self.mode{
+            VimMode::Normal=>"",
+            VimMode::Insert=>"-- INSERT --",
+            VimMode::Visual=>"-- VISUAL --",
+            VimMode::VisualLine=>"-- VISUAL LINE --"
+        };
+        format!("{}  {}", mode, self._keys).trim().to_string()
+    }
+
+    // keys that don't type text. gives back None for the keys the editor should handle itself
+    pub fn handle_key_down(&mut self, ke:&KeyEvent, cursors:&mut CursorSet, text_buffer:&mut TextBuffer, indent_size:usize)->Option<VimEvent>{
+        if self.mode == VimMode::Insert{
+            match ke.key_code{
+                KeyCode::Escape=>{
+                    self.leave_insert(cursors, text_buffer);
+                    return Some(VimEvent::CursorMoved)
+                },
+                KeyCode::Backspace=>if let Some(change) = &mut self._change{
+                    change.text.pop();
+                },
+                _=>()
+            }
+            return None
+        }
+        if let Some(command_line) = &mut self.command_line{
+            match ke.key_code{
+                KeyCode::Escape=>self.command_line = None,
+                KeyCode::Backspace=>{
+                    command_line.pop();
+                    if command_line.len() == 0{
+                        self.command_line = None;
+                    }
+                },
+                KeyCode::Return=>{
+                    let command_line = self.command_line.take().unwrap();
+                    return Some(self.run_command_line(&command_line, cursors, text_buffer))
+                },
+                _=>()
+            }
+            return Some(VimEvent::None)
+        }
+        if ke.modifiers.control && !ke.modifiers.logo && ke.key_code == KeyCode::KeyR{
+            text_buffer.redo(true, cursors);
+            Self::clamp_cursors(cursors, text_buffer);
+            return Some(VimEvent::CursorMoved)
+        }
+        if ke.modifiers.logo || ke.modifiers.control{
+            return None
+        }
+        let ch = match ke.key_code{
+            KeyCode::Escape=>{
+                self._keys.clear();
+                self.status.clear();
+                if self.is_visual(){
+                    self.exit_visual(false, cursors, text_buffer);
+                    Self::clamp_cursors(cursors, text_buffer);
+                }
+                return Some(VimEvent::CursorMoved)
+            },
+            KeyCode::ArrowLeft | KeyCode::Backspace=>'h',
+            KeyCode::ArrowRight=>'l',
+            KeyCode::ArrowUp=>'k',
+            KeyCode::ArrowDown | KeyCode::Return=>'j',
+            KeyCode::Home=>'0',
+            KeyCode::End=>'$',
+            KeyCode::Delete=>'x',
+            _=>return Some(VimEvent::None)
+        };
+        Some(self.handle_char(ch, cursors, text_buffer, indent_size))
+    }
+
+    // typed text, gives back None in insert mode where the editor types it
+    pub fn handle_text_input(&mut self, input:&str, cursors:&mut CursorSet, text_buffer:&mut TextBuffer, indent_size:usize)->Option<VimEvent>{
+        if self.mode == VimMode::Insert{
+            if let Some(change) = &mut self._change{
+                change.text.push_str(input);
+            }
+            return None
+        }
+        if let Some(command_line) = &mut self.command_line{
+            command_line.extend(input.chars().filter(|ch| !ch.is_control()));
+            return Some(VimEvent::None)
+        }
+        let mut event = VimEvent::None;
+        for ch in input.chars().filter(|ch| !ch.is_control()){
+            match self.handle_char(ch, cursors, text_buffer, indent_size){
+                VimEvent::None=>(),
+                VimEvent::CursorMoved=>if event == VimEvent::None{event = VimEvent::CursorMoved},
+                VimEvent::Save=>event = VimEvent::Save
+            }
+        }
+        Some(event)
+    }
+
+    // a click or drag selects with the mouse, that ends visual mode
+    pub fn handle_finger_down(&mut self){
+        self._keys.clear();
+        if self.is_visual(){
+            self.mode = VimMode::Normal;
+            self._visual.clear();
+        }
+    }
+
+    fn handle_char(&mut self, ch:char, cursors:&mut CursorSet, text_buffer:&mut TextBuffer, indent_size:usize)->VimEvent{
+        self.status.clear();
+        self._keys.push(ch);
+        match Self::parse(&self._keys, self.is_visual()){
+            VimParse::Incomplete=>VimEvent::None,
+            VimParse::Invalid=>{
+                self._keys.clear();
+                VimEvent::None
+            },
+            VimParse::Command(command)=>{
+                let keys = std::mem::replace(&mut self._keys, String::new());
+                self.execute(command, &keys, cursors, text_buffer, indent_size)
+            }
+        }
+    }
+
+    fn parse_count(chars:&mut std::iter::Peekable<std::str::Chars>)->Option<usize>{
+        let mut count:Option<usize> = None;
+        while let Some(digit) = chars.peek().and_then(|ch| ch.to_digit(10)){
+            if digit == 0 && count.is_none(){ // a 0 on its own is a motion
+                break
+            }
+            count = Some((count.unwrap_or(0) * 10 + digit as usize).min(99999));
+            chars.next();
+        }
+        count
+    }
+
+    fn parse(keys:&str, is_visual:bool)->VimParse{
+        let mut chars = keys.chars().peekable();
+        let mut register = None;
+        if chars.peek() == Some(&'"'){
+            chars.next();
+            match chars.next(){
+                Some(ch)=>register = Some(ch),
+                None=>return VimParse::Incomplete
+            }
+        }
+        let mut count = Self::parse_count(&mut chars);
+        let ch = match chars.next(){
+            Some(ch)=>ch,
+            None=>return VimParse::Incomplete
+        };
+        let operator = match ch{
+            'd'=>Some(VimOperator::Delete),
+            'c'=>Some(VimOperator::Change),
+            'y'=>Some(VimOperator::Yank),
+            '>'=>Some(VimOperator::Indent),
+            '<'=>Some(VimOperator::Outdent),
+            _=>None
+        };
+        let action = match operator{
+            // a visual selection is what operators work on there
+            Some(operator) if !is_visual=>{
+                if let Some(count2) = Self::parse_count(&mut chars){
+                    count = Some(count.unwrap_or(1) * count2);
+                }
+                match chars.next(){
+                    None=>return VimParse::Incomplete,
+                    Some(motion) if motion == ch=>VimAction::OperateLines(operator),
+                    Some(motion)=>match Self::parse_motion(motion, &mut chars, true){
+                        Ok(motion)=>VimAction::Operate(operator, motion),
+                        Err(parse)=>return parse
+                    }
+                }
+            },
+            _=>match ch{
+                'r'=>match chars.next(){
+                    Some(ch)=>VimAction::Replace(ch),
+                    None=>return VimParse::Incomplete
+                },
+                'i' | 'a' if !is_visual=>VimAction::Key(ch),
+                'x' | 'X' | 's' | 'S' | 'D' | 'C' | 'Y' | 'R' | 'p' | 'P' | 'u' | 'U' | 'J' | '~' | 'I' | 'A' | 'o' | 'O' |
+                'v' | 'V' | '.' | '*' | '#' | ':' | '/' | '?' | 'd' | 'c' | 'y' | '>' | '<'=>VimAction::Key(ch),
+                _=>match Self::parse_motion(ch, &mut chars, is_visual){
+                    Ok(motion)=>VimAction::Move(motion),
+                    Err(parse)=>return parse
+                }
+            }
+        };
+        VimParse::Command(VimCommand{register:register, count:count, action:action})
+    }
+
+    fn parse_motion(ch:char, chars:&mut std::iter::Peekable<std::str::Chars>, allow_objects:bool)->Result<VimMotion, VimParse>{
+        Ok(match ch{
+            'h'=>VimMotion::Left,
+            'l' | ' '=>VimMotion::Right,
+            'j'=>VimMotion::Down,
+            'k'=>VimMotion::Up,
+            'w'=>VimMotion::WordStart(false),
+            'W'=>VimMotion::WordStart(true),
+            'e'=>VimMotion::WordEnd(false),
+            'E'=>VimMotion::WordEnd(true),
+            'b'=>VimMotion::WordBack(false),
+            'B'=>VimMotion::WordBack(true),
+            '0'=>VimMotion::LineStart,
+            '^'=>VimMotion::FirstNonBlank,
+            '$'=>VimMotion::LineEnd,
+            'G'=>VimMotion::LastLine,
+            '%'=>VimMotion::MatchBracket,
+            '}'=>VimMotion::ParagraphForward,
+            '{'=>VimMotion::ParagraphBack,
+            ';'=>VimMotion::RepeatFind(false),
+            ','=>VimMotion::RepeatFind(true),
+            'n'=>VimMotion::SearchNext(false),
+            'N'=>VimMotion::SearchNext(true),
+            'g'=>match chars.next(){
+                Some('g')=>VimMotion::FirstLine,
+                Some(_)=>return Err(VimParse::Invalid),
+                None=>return Err(VimParse::Incomplete)
+            },
+            'f' | 'F' | 't' | 'T'=>match chars.next(){
+                Some(target)=>VimMotion::FindChar{ch:target, forward:ch == 'f' || ch == 't', till:ch == 't' || ch == 'T'},
+                None=>return Err(VimParse::Incomplete)
+            },
+            'i' | 'a' if allow_objects=>match chars.next(){
+                Some(object)=>VimMotion::Object{ch:object, inner:ch == 'i'},
+                None=>return Err(VimParse::Incomplete)
+            },
+            _=>return Err(VimParse::Invalid)
+        })
+    }
+
+    fn execute(&mut self, command:VimCommand, keys:&str, cursors:&mut CursorSet, text_buffer:&mut TextBuffer, indent_size:usize)->VimEvent{
+        if self.is_visual() && self._visual.len() != cursors.set.len(){ // cursors were added or merged
+            self.exit_visual(false, cursors, text_buffer);
+        }
+        let was_visual = self.is_visual();
+        let undo_id = text_buffer.undo_id();
+        self._change_undo_id = undo_id;
+        let event = if was_visual{
+            self.run_visual(command, keys, cursors, text_buffer, indent_size)
+        }
+        else{
+            // normal mode works from the cursor heads
+            for cursor in &mut cursors.set{
+                cursor.tail = cursor.head;
+            }
+            cursors.fuse_adjacent(text_buffer);
+            let is_change = match command.action{
+                VimAction::Operate(operator, _) | VimAction::OperateLines(operator)=>operator != VimOperator::Yank,
+                VimAction::Replace(_)=>true,
+                VimAction::Key(ch)=>"xXDpPJ~".contains(ch),
+                VimAction::Move(_)=>false
+            };
+            let event = self.run_normal(command.action, command.count, command.register, keys, cursors, text_buffer, indent_size);
+            if is_change && self.mode == VimMode::Normal && !self._repeating{
+                self._last_change = Some(VimChange{keys:keys.to_string(), text:String::new()});
+            }
+            event
+        };
+        match self.mode{
+            VimMode::Normal=>{
+                text_buffer.merge_undo_since(undo_id);
+                Self::clamp_cursors(cursors, text_buffer);
+            },
+            VimMode::Visual | VimMode::VisualLine=>self.show_visual(cursors, text_buffer),
+            VimMode::Insert=>()
+        }
+        event
+    }
+
+    fn run_normal(&mut self, action:VimAction, count:Option<usize>, register:Option<char>, keys:&str, cursors:&mut CursorSet, text_buffer:&mut TextBuffer, indent_size:usize)->VimEvent{
+        let n = count.unwrap_or(1);
+        match action{
+            VimAction::Move(motion)=>self.move_cursors(motion, count, cursors, text_buffer),
+            VimAction::Operate(operator, motion)=>{
+                if let VimMotion::FindChar{ch, forward, till} = motion{
+                    self._last_find = Some((ch, forward, till));
+                }
+                let ranges = cursors.set.iter().map(|cursor| self.motion_range(operator, motion, count, cursor, text_buffer)).collect();
+                self.operate(operator, ranges, register, keys, cursors, text_buffer, indent_size)
+            },
+            VimAction::OperateLines(operator)=>{
+                let last_row = text_buffer.get_line_count() - 1;
+                let ranges = cursors.set.iter().map(|cursor|{
+                    let row = row_of(text_buffer, cursor.head);
+                    Some(lines_range(text_buffer, row, (row + n - 1).min(last_row)))
+                }).collect();
+                self.operate(operator, ranges, register, keys, cursors, text_buffer, indent_size)
+            },
+            VimAction::Replace(ch)=>{
+                let edits = cursors.set.iter().map(|cursor|{
+                    let (row, col) = text_buffer.offset_to_row_col(cursor.head);
+                    if ch == '\n' || col + n > text_buffer.get_line_len(row){
+                        return TypedEdit{start:cursor.head, end:cursor.head, text:String::new(), head:0, tail:0}
+                    }
+                    TypedEdit{start:cursor.head, end:cursor.head + n, text:ch.to_string().repeat(n), head:n - 1, tail:n - 1}
+                }).collect();
+                cursors.apply_typed_edits(edits, TextUndoGrouping::Block, text_buffer);
+                VimEvent::CursorMoved
+            },
+            VimAction::Key(ch)=>match ch{
+                'x'=>self.run_normal(VimAction::Operate(VimOperator::Delete, VimMotion::Right), count, register, keys, cursors, text_buffer, indent_size),
+                'X'=>self.run_normal(VimAction::Operate(VimOperator::Delete, VimMotion::Left), count, register, keys, cursors, text_buffer, indent_size),
+                's'=>self.run_normal(VimAction::Operate(VimOperator::Change, VimMotion::Right), count, register, keys, cursors, text_buffer, indent_size),
+                'S'=>self.run_normal(VimAction::OperateLines(VimOperator::Change), count, register, keys, cursors, text_buffer, indent_size),
+                'D'=>self.run_normal(VimAction::Operate(VimOperator::Delete, VimMotion::LineEnd), count, register, keys, cursors, text_buffer, indent_size),
+                'C'=>self.run_normal(VimAction::Operate(VimOperator::Change, VimMotion::LineEnd), count, register, keys, cursors, text_buffer, indent_size),
+                'Y'=>self.run_normal(VimAction::OperateLines(VimOperator::Yank), count, register, keys, cursors, text_buffer, indent_size),
+                'p' | 'P'=>self.paste(ch == 'P', n, register, cursors, text_buffer),
+                'u'=>{
+                    for _ in 0..n{
+                        text_buffer.undo(true, cursors);
+                    }
+                    VimEvent::CursorMoved
+                },
+                'J'=>{
+                    for _ in 0..(n.max(2) - 1){
+                        // the cursor goes to where the lines were joined
+                        let cols:Vec<usize> = cursors.set.iter().map(|cursor| text_buffer.get_line_len(row_of(text_buffer, cursor.head))).collect();
+                        cursors.join_lines(text_buffer);
+                        for (cursor, col) in cursors.set.iter_mut().zip(cols){
+                            cursor.head = text_buffer.row_col_to_offset(row_of(text_buffer, cursor.head), col);
+                            cursor.tail = cursor.head;
+                        }
+                    }
+                    VimEvent::CursorMoved
+                },
+                '~'=>{
+                    let edits = cursors.set.iter().map(|cursor|{
+                        let (row, col) = text_buffer.offset_to_row_col(cursor.head);
+                        let len = n.min(text_buffer.get_line_len(row) - col);
+                        let text = convert_case(&range_text(text_buffer, cursor.head, cursor.head + len), '~');
+                        TypedEdit{start:cursor.head, end:cursor.head + len, text:text, head:len, tail:len}
+                    }).collect();
+                    cursors.apply_typed_edits(edits, TextUndoGrouping::Block, text_buffer);
+                    VimEvent::CursorMoved
+                },
+                'i' | 'a' | 'I' | 'A'=>{
+                    for cursor in &mut cursors.set{
+                        let row = row_of(text_buffer, cursor.head);
+                        cursor.head = match ch{
+                            'a' if char_at(text_buffer, cursor.head) != '\n'=>cursor.head + 1,
+                            'I'=>first_non_blank(text_buffer, row),
+                            'A'=>line_end(text_buffer, row),
+                            _=>cursor.head
+                        };
+                        cursor.tail = cursor.head;
+                        cursor.calc_max(text_buffer);
+                    }
+                    self.begin_insert(keys);
+                    VimEvent::CursorMoved
+                },
+                'o'=>{
+                    for cursor in &mut cursors.set{
+                        cursor.head = line_end(text_buffer, row_of(text_buffer, cursor.head));
+                        cursor.tail = cursor.head;
+                    }
+                    cursors.insert_newline(indent_size, text_buffer);
+                    self.begin_insert(keys);
+                    VimEvent::CursorMoved
+                },
+                'O'=>{
+                    let edits = cursors.set.iter().map(|cursor|{
+                        let row = row_of(text_buffer, cursor.head);
+                        let indent:String = text_buffer.line_chars(row).take_while(|ch| is_blank(*ch)).collect();
+                        let len = indent.chars().count();
+                        let start = line_start(text_buffer, row);
+                        TypedEdit{start:start, end:start, text:format!("{}\n", indent), head:len, tail:len}
+                    }).collect();
+                    cursors.apply_typed_edits(edits, TextUndoGrouping::Newline, text_buffer);
+                    self.begin_insert(keys);
+                    VimEvent::CursorMoved
+                },
+                'v' | 'V'=>{
+                    self._visual = cursors.set.iter().map(|cursor| (cursor.head, cursor.head)).collect();
+                    self.mode = if ch == 'v'{VimMode::Visual}else{VimMode::VisualLine};
+                    VimEvent::CursorMoved
+                },
+                '.'=>self.repeat_change(count, cursors, text_buffer, indent_size),
+                '*' | '#'=>self.search_word(ch == '#', cursors, text_buffer),
+                ':' | '/' | '?'=>{
+                    self._command_rows = None;
+                    self.command_line = Some(ch.to_string());
+                    VimEvent::None
+                },
+                _=>VimEvent::None
+            }
+        }
+    }
+
+    fn run_visual(&mut self, command:VimCommand, keys:&str, cursors:&mut CursorSet, text_buffer:&mut TextBuffer, indent_size:usize)->VimEvent{
+        match command.action{
+            VimAction::Move(VimMotion::Object{ch, inner})=>{
+                for visual in &mut self._visual{
+                    if let Some((start, end)) = Self::object_range(ch, inner, visual.1, text_buffer){
+                        if end > start{
+                            *visual = (start, end - 1);
+                        }
+                    }
+                }
+                VimEvent::CursorMoved
+            },
+            VimAction::Move(motion)=>self.move_cursors(motion, command.count, cursors, text_buffer),
+            VimAction::Replace(ch)=>{
+                let ranges = self.visual_ranges(false, text_buffer);
+                let edits = ranges.iter().map(|range|{
+                    let (start, end, _) = range.unwrap();
+                    let text:String = range_text(text_buffer, start, end).chars().map(|old| if old == '\n'{old}else{ch}).collect();
+                    TypedEdit{start:start, end:end, text:text, head:0, tail:0}
+                }).collect();
+                self.exit_visual(true, cursors, text_buffer);
+                cursors.apply_typed_edits(edits, TextUndoGrouping::Block, text_buffer);
+                VimEvent::CursorMoved
+            },
+            VimAction::Key(ch)=>match ch{
+                'o'=>{
+                    for visual in &mut self._visual{
+                        *visual = (visual.1, visual.0);
+                    }
+                    VimEvent::CursorMoved
+                },
+                'v' | 'V'=>{
+                    let mode = if ch == 'v'{VimMode::Visual}else{VimMode::VisualLine};
+                    if self.mode == mode{
+                        self.exit_visual(false, cursors, text_buffer);
+                    }
+                    else{
+                        self.mode = mode;
+                    }
+                    VimEvent::CursorMoved
+                },
+                'd' | 'x' | 'X' | 'D' | 'c' | 's' | 'C' | 'S' | 'R' | 'y' | 'Y' | '>' | '<'=>{
+                    let operator = match ch{
+                        'd' | 'x' | 'X' | 'D'=>VimOperator::Delete,
+                        'c' | 's' | 'C' | 'S' | 'R'=>VimOperator::Change,
+                        'y' | 'Y'=>VimOperator::Yank,
+                        '>'=>VimOperator::Indent,
+                        _=>VimOperator::Outdent
+                    };
+                    let ranges = self.visual_ranges("XDCSRY".contains(ch), text_buffer);
+                    self.exit_visual(true, cursors, text_buffer);
+                    self.operate(operator, ranges, command.register, keys, cursors, text_buffer, indent_size)
+                },
+                'J'=>{
+                    let ranges = self.visual_ranges(false, text_buffer);
+                    self.exit_visual(true, cursors, text_buffer);
+                    for (cursor, range) in cursors.set.iter_mut().zip(ranges){
+                        let (start, end, _) = range.unwrap();
+                        cursor.tail = start;
+                        cursor.head = end.saturating_sub(1).max(start);
+                    }
+                    cursors.join_lines(text_buffer);
+                    VimEvent::CursorMoved
+                },
+                '~' | 'u' | 'U'=>{
+                    let ranges = self.visual_ranges(false, text_buffer);
+                    let edits = ranges.iter().map(|range|{
+                        let (start, end, _) = range.unwrap();
+                        TypedEdit{start:start, end:end, text:convert_case(&range_text(text_buffer, start, end), ch), head:0, tail:0}
+                    }).collect();
+                    self.exit_visual(true, cursors, text_buffer);
+                    cursors.apply_typed_edits(edits, TextUndoGrouping::Block, text_buffer);
+                    VimEvent::CursorMoved
+                },
+                'p' | 'P'=>{
+                    let register = match self.get_register(command.register){
+                        Some(register)=>register,
+                        None=>return VimEvent::None
+                    };
+                    let ranges = self.visual_ranges(false, text_buffer);
+                    let edits = ranges.iter().map(|range|{
+                        let (start, end, linewise) = range.unwrap();
+                        let mut text = register.text.clone();
+                        if linewise && !text.ends_with('\n'){
+                            text.push('\n');
+                        }
+                        else if !linewise && register.linewise{
+                            text.pop();
+                        }
+                        let head = if linewise{0}else{text.chars().count().max(1) - 1};
+                        TypedEdit{start:start, end:end, text:text, head:head, tail:head}
+                    }).collect();
+                    self.exit_visual(true, cursors, text_buffer);
+                    cursors.apply_typed_edits(edits, TextUndoGrouping::Block, text_buffer);
+                    VimEvent::CursorMoved
+                },
+                ':'=>{
+                    let rows = self._visual.iter().fold(None, |rows:Option<(usize, usize)>, (anchor, pos)|{
+                        let (first, last) = (row_of(text_buffer, *anchor.min(pos)), row_of(text_buffer, *anchor.max(pos)));
+                        Some(rows.map_or((first, last), |(a, b)| (a.min(first), b.max(last))))
+                    });
+                    self.exit_visual(false, cursors, text_buffer);
+                    self._command_rows = rows;
+                    self.command_line = Some(":".to_string());
+                    VimEvent::CursorMoved
+                },
+                '/' | '?'=>{
+                    self.command_line = Some(ch.to_string());
+                    VimEvent::None
+                },
+                '*' | '#'=>self.search_word(ch == '#', cursors, text_buffer),
+                _=>VimEvent::None
+            },
+            _=>VimEvent::None
+        }
+    }
+
+    // motions move the cursor heads, or the visual selection ends
+    fn move_cursors(&mut self, motion:VimMotion, count:Option<usize>, cursors:&mut CursorSet, text_buffer:&TextBuffer)->VimEvent{
+        if let VimMotion::FindChar{ch, forward, till} = motion{
+            self._last_find = Some((ch, forward, till));
+        }
+        let mut moved = false;
+        if self.is_visual(){
+            for index in 0..self._visual.len(){
+                let pos = self._visual[index].1;
+                let col = text_buffer.offset_to_row_col(pos).1;
+                if let Some((target, _)) = self.motion_target(motion, count, pos, col, text_buffer){
+                    self._visual[index].1 = target;
+                    moved = true;
+                }
+            }
+        }
+        else{
+            for cursor in &mut cursors.set{
+                if let Some((target, _)) = self.motion_target(motion, count, cursor.head, cursor.max, text_buffer){
+                    cursor.head = target;
+                    cursor.tail = target;
+                    match motion{
+                        VimMotion::Up | VimMotion::Down=>(),
+                        VimMotion::LineEnd=>cursor.max = usize::max_value(),
+                        _=>cursor.calc_max(text_buffer)
+                    }
+                    moved = true;
+                }
+            }
+            cursors.fuse_adjacent(text_buffer);
+        }
+        if let VimMotion::SearchNext(_) = motion{
+            if !moved{
+                if let Some(search) = &self.search{
+                    self.status = format!("Pattern not found: {}", search.pattern);
+                }
+            }
+        }
+        VimEvent::CursorMoved
+    }
+
+    fn motion_target(&self, motion:VimMotion, count:Option<usize>, pos:usize, max:usize, text_buffer:&TextBuffer)->Option<(usize, MotionKind)>{
+        let n = count.unwrap_or(1);
+        let (row, col) = text_buffer.offset_to_row_col(pos);
+        let last_row = text_buffer.get_line_count() - 1;
+        let len = text_buffer.get_char_count();
+        Some(match motion{
+            VimMotion::Left=>(pos - col.min(n), MotionKind::Exclusive),
+            VimMotion::Right=>(pos + n.min(text_buffer.get_line_len(row) - col), MotionKind::Exclusive),
+            VimMotion::Up=>{
+                let row = text_buffer.folds.visible_row_above(row, n).unwrap_or(0);
+                (text_buffer.row_col_to_offset(row, max), MotionKind::Linewise)
+            },
+            VimMotion::Down=>{
+                let row = text_buffer.folds.visible_row_below(row, n, last_row + 1).unwrap_or(last_row).min(last_row);
+                (text_buffer.row_col_to_offset(row, max), MotionKind::Linewise)
+            },
+            VimMotion::WordStart(big)=>{
+                let mut target = pos;
+                for _ in 0..n{
+                    target = Self::next_word_start(target, big, text_buffer);
+                }
+                (target, MotionKind::Exclusive)
+            },
+            VimMotion::WordEnd(big)=>{
+                let mut target = pos;
+                for _ in 0..n{
+                    target = Self::next_word_end(target, big, text_buffer);
+                }
+                (target, MotionKind::Inclusive)
+            },
+            VimMotion::WordBack(big)=>{
+                let mut target = pos;
+                for _ in 0..n{
+                    target = Self::prev_word_start(target, big, text_buffer);
+                }
+                (target, MotionKind::Exclusive)
+            },
+            VimMotion::LineStart=>(pos - col, MotionKind::Exclusive),
+            VimMotion::FirstNonBlank=>(first_non_blank(text_buffer, row), MotionKind::Exclusive),
+            VimMotion::LineEnd=>{
+                let row = (row + n - 1).min(last_row);
+                let end = line_end(text_buffer, row);
+                (if end > line_start(text_buffer, row){end - 1}else{end}, MotionKind::Inclusive)
+            },
+            VimMotion::FirstLine | VimMotion::LastLine=>{
+                let row = match count{
+                    Some(count)=>(count - 1).min(last_row),
+                    None=>if motion == VimMotion::FirstLine{0}else{last_row}
+                };
+                (first_non_blank(text_buffer, row), MotionKind::Linewise)
+            },
+            VimMotion::FindChar{ch, forward, till}=>return Self::find_in_line(ch, forward, till, n, pos, text_buffer),
+            VimMotion::RepeatFind(reverse)=>{
+                let (ch, forward, till) = self._last_find?;
+                return Self::find_in_line(ch, forward != reverse, till, n, pos, text_buffer)
+            },
+            VimMotion::MatchBracket=>(Self::match_bracket(pos, text_buffer)?, MotionKind::Inclusive),
+            VimMotion::ParagraphForward | VimMotion::ParagraphBack=>{
+                let mut row = row;
+                for _ in 0..n{
+                    if motion == VimMotion::ParagraphForward{
+                        row += 1;
+                        while row <= last_row && text_buffer.get_line_len(row) != 0{
+                            row += 1;
+                        }
+                    }
+                    else{
+                        row = row.saturating_sub(1);
+                        while row > 0 && text_buffer.get_line_len(row) != 0{
+                            row -= 1;
+                        }
+                    }
+                }
+                (if row > last_row{len}else{line_start(text_buffer, row)}, MotionKind::Exclusive)
+            },
+            VimMotion::SearchNext(reverse)=>{
+                let search = self.search.as_ref()?;
+                let text_search = TextSearch::new(&search.pattern, &search.options).ok()?;
+                let matches = text_search.find_in_text_buffer(text_buffer);
+                let found = if search.backward != reverse{
+                    matches.iter().rev().find(|search_match| search_match.start < pos).or(matches.last())
+                }
+                else{
+                    matches.iter().find(|search_match| search_match.start > pos).or(matches.first())
+                };
+                (found?.start, MotionKind::Exclusive)
+            },
+            VimMotion::Object{..}=>return None
+        })
+    }
+
+    fn next_word_start(offset:usize, big:bool, text_buffer:&TextBuffer)->usize{
+        let len = text_buffer.get_char_count();
+        let mut offset = offset;
+        if offset >= len{
+            return len
+        }
+        let class = word_class(char_at(text_buffer, offset), big);
+        if class != 0{
+            while offset < len && word_class(char_at(text_buffer, offset), big) == class{
+                offset += 1;
+            }
+        }
+        while offset < len{
+            match char_at(text_buffer, offset){
+                '\n'=>{
+                    offset += 1;
+                    if offset < len && char_at(text_buffer, offset) == '\n'{
+                        return offset
+                    }
+                },
+                ch if is_blank(ch)=>offset += 1,
+                _=>break
+            }
+        }
+        offset
+    }
+
+    fn next_word_end(offset:usize, big:bool, text_buffer:&TextBuffer)->usize{
+        let len = text_buffer.get_char_count();
+        if offset + 1 >= len{
+            return offset
+        }
+        let mut offset = offset + 1;
+        while offset < len && char_at(text_buffer, offset).is_whitespace(){
+            offset += 1;
+        }
+        if offset >= len{
+            return len - 1
+        }
+        let class = word_class(char_at(text_buffer, offset), big);
+        while offset + 1 < len && word_class(char_at(text_buffer, offset + 1), big) == class{
+            offset += 1;
+        }
+        offset
+    }
+
+    fn prev_word_start(offset:usize, big:bool, text_buffer:&TextBuffer)->usize{
+        if offset == 0{
+            return 0
+        }
+        let mut offset = offset - 1;
+        while offset > 0 && char_at(text_buffer, offset).is_whitespace() && !is_empty_line(text_buffer, offset){
+            offset -= 1;
+        }
+        if is_empty_line(text_buffer, offset){
+            return offset
+        }
+        let class = word_class(char_at(text_buffer, offset), big);
+        while offset > 0 && word_class(char_at(text_buffer, offset - 1), big) == class{
+            offset -= 1;
+        }
+        offset
+    }
+
+    // f and t are inclusive, F and T are not
+    fn find_in_line(ch:char, forward:bool, till:bool, n:usize, pos:usize, text_buffer:&TextBuffer)->Option<(usize, MotionKind)>{
+        let (row, col) = text_buffer.offset_to_row_col(pos);
+        let line = text_buffer.get_line(row);
+        let start = line_start(text_buffer, row);
+        if forward{
+            let index = (col + 1..line.len()).filter(|index| line[*index] == ch).nth(n - 1)?;
+            Some((start + if till{index - 1}else{index}, MotionKind::Inclusive))
+        }
+        else{
+            let index = (0..col).rev().filter(|index| line[*index] == ch).nth(n - 1)?;
+            Some((start + if till{index + 1}else{index}, MotionKind::Exclusive))
+        }
+    }
+
+    // the bracket matching the first one at or after pos on its line
+    fn match_bracket(pos:usize, text_buffer:&TextBuffer)->Option<usize>{
+        let row = row_of(text_buffer, pos);
+        let end = line_end(text_buffer, row);
+        let start = (pos..end).find(|offset| "()[]{}".contains(char_at(text_buffer, *offset)))?;
+        let ch = char_at(text_buffer, start);
+        let (open, close, forward) = match ch{
+            '('=>('(', ')', true), '['=>('[', ']', true), '{'=>('{', '}', true),
+            ')'=>('(', ')', false), ']'=>('[', ']', false), _=>('{', '}', false)
+        };
+        let mut depth = 0;
+        let mut offset = start;
+        loop{
+            let ch = char_at(text_buffer, offset);
+            if ch == open{
+                depth += if forward{1}else{-1};
+            }
+            else if ch == close{
+                depth += if forward{-1}else{1};
+            }
+            if depth == 0{
+                return Some(offset)
+            }
+            if forward{
+                offset += 1;
+                if offset >= text_buffer.get_char_count(){
+                    return None
+                }
+            }
+            else{
+                if offset == 0{
+                    return None
+                }
+                offset -= 1;
+            }
+        }
+    }
+
+    fn object_range(ch:char, inner:bool, pos:usize, text_buffer:&TextBuffer)->Option<(usize, usize)>{
+        match ch{
+            'w' | 'W'=>Some(Self::word_object(ch == 'W', inner, pos, text_buffer)),
+            '(' | ')' | 'b'=>Self::bracket_object('(', ')', inner, pos, text_buffer),
+            '{' | '}' | 'B'=>Self::bracket_object('{', '}', inner, pos, text_buffer),
+            '[' | ']'=>Self::bracket_object('[', ']', inner, pos, text_buffer),
+            '<' | '>'=>Self::bracket_object('<', '>', inner, pos, text_buffer),
+            '"' | '\'' | '`'=>Self::quote_object(ch, inner, pos, text_buffer),
+            _=>None
+        }
+    }
+
+    // a word and, around it, the blanks after it or else the ones before it
+    fn word_object(big:bool, inner:bool, pos:usize, text_buffer:&TextBuffer)->(usize, usize){
+        let row = row_of(text_buffer, pos);
+        let (first, last) = (line_start(text_buffer, row), line_end(text_buffer, row));
+        if pos >= last{
+            return (pos, pos)
+        }
+        let class_at = |offset:usize| word_class(char_at(text_buffer, offset), big);
+        let class = class_at(pos);
+        let mut start = pos;
+        while start > first && class_at(start - 1) == class{
+            start -= 1;
+        }
+        let mut end = pos + 1;
+        while end < last && class_at(end) == class{
+            end += 1;
+        }
+        if inner{
+            return (start, end)
+        }
+        if class == 0{ // blanks take the word after them along
+            if end < last{
+                let word = class_at(end);
+                while end < last && class_at(end) == word{
+                    end += 1;
+                }
+            }
+            return (start, end)
+        }
+        let word_end = end;
+        while end < last && class_at(end) == 0{
+            end += 1;
+        }
+        if end == word_end{
+            while start > first && class_at(start - 1) == 0{
+                start -= 1;
+            }
+        }
+        (start, end)
+    }
+
+    fn bracket_object(open:char, close:char, inner:bool, pos:usize, text_buffer:&TextBuffer)->Option<(usize, usize)>{
+        let mut depth = 0;
+        let mut offset = pos;
+        let start = loop{
+            let ch = char_at(text_buffer, offset);
+            if ch == close && offset != pos{
+                depth += 1;
+            }
+            else if ch == open{
+                if depth == 0{
+                    break offset
+                }
+                depth -= 1;
+            }
+            if offset == 0{
+                return None
+            }
+            offset -= 1;
+        };
+        let len = text_buffer.get_char_count();
+        let mut depth = 0;
+        let mut end = start + 1;
+        loop{
+            if end >= len{
+                return None
+            }
+            let ch = char_at(text_buffer, end);
+            if ch == open{
+                depth += 1;
+            }
+            else if ch == close{
+                if depth == 0{
+                    break
+                }
+                depth -= 1;
+            }
+            end += 1;
+        }
+        if !inner{
+            return Some((start, end + 1))
+        }
+        // inside a block over several lines, only the lines between the brackets
+        let mut inner_start = start + 1;
+        let mut inner_end = end;
+        if char_at(text_buffer, inner_start) == '\n'{
+            inner_start += 1;
+            let end_row = row_of(text_buffer, end);
+            if end_row > row_of(text_buffer, start) && text_buffer.line_chars(end_row).take_while(|ch| *ch != close).all(is_blank){
+                inner_end = line_start(text_buffer, end_row);
+            }
+        }
+        Some((inner_start, inner_end.max(inner_start)))
+    }
+
+    // quotes pair up from the start of the line, around takes the blanks after it along
+    fn quote_object(quote:char, inner:bool, pos:usize, text_buffer:&TextBuffer)->Option<(usize, usize)>{
+        let (row, col) = text_buffer.offset_to_row_col(pos);
+        let line = text_buffer.get_line(row);
+        let quotes:Vec<usize> = (0..line.len()).filter(|index| line[*index] == quote && (*index == 0 || line[index - 1] != '\\')).collect();
+        let pair = quotes.chunks(2).filter(|pair| pair.len() == 2).find(|pair| col <= pair[1])?;
+        let start = line_start(text_buffer, row);
+        if inner{
+            return Some((start + pair[0] + 1, start + pair[1]))
+        }
+        let mut end = pair[1] + 1;
+        while end < line.len() && is_blank(line[end]){
+            end += 1;
+        }
+        Some((start + pair[0], start + end))
+    }
+
+    fn motion_range(&self, operator:VimOperator, motion:VimMotion, count:Option<usize>, cursor:&Cursor, text_buffer:&TextBuffer)->VimRange{
+        let pos = cursor.head;
+        if let VimMotion::Object{ch, inner} = motion{
+            return Self::object_range(ch, inner, pos, text_buffer).map(|(start, end)| (start, end, false))
+        }
+        // cw changes the word, not the blanks after it, and counts the one it is in
+        let (target, kind) = match motion{
+            VimMotion::WordStart(big) if operator == VimOperator::Change && !char_at(text_buffer, pos).is_whitespace()=>{
+                let class = word_class(char_at(text_buffer, pos), big);
+                let mut target = if word_class(char_at(text_buffer, pos + 1), big) == class{Self::next_word_end(pos, big, text_buffer)}else{pos};
+                for _ in 1..count.unwrap_or(1){
+                    target = Self::next_word_end(target, big, text_buffer);
+                }
+                (target, MotionKind::Inclusive)
+            },
+            motion=>self.motion_target(motion, count, pos, cursor.max, text_buffer)?
+        };
+        let (start, end) = (pos.min(target), pos.max(target));
+        match kind{
+            MotionKind::Linewise=>Some(lines_range(text_buffer, row_of(text_buffer, start), row_of(text_buffer, end))),
+            MotionKind::Inclusive=>Some((start, (end + 1).min(line_end(text_buffer, row_of(text_buffer, end))).max(start), false)),
+            MotionKind::Exclusive=>{
+                // a word motion stops at the end of the line, not at the next word on the next one
+                let end_row = row_of(text_buffer, end);
+                if let VimMotion::WordStart(_) = motion{
+                    if end_row > row_of(text_buffer, start){
+                        return Some((start, line_end(text_buffer, end_row - 1).max(start), false))
+                    }
+                }
+                Some((start, end, false))
+            }
+        }
+    }
+
+    // applies an operator to a range per cursor, cursors without one stay as they are
+    fn operate(&mut self, operator:VimOperator, ranges:Vec<VimRange>, register:Option<char>, keys:&str, cursors:&mut CursorSet, text_buffer:&mut TextBuffer, indent_size:usize)->VimEvent{
+        // cursors can share lines, don't let their ranges overlap
+        let mut prev_end = 0;
+        let ranges:Vec<VimRange> = ranges.into_iter().map(|range| range.map(|(start, end, linewise)|{
+            let start = start.max(prev_end);
+            let end = end.max(start);
+            prev_end = end;
+            (start, end, linewise)
+        })).collect();
+        if ranges.iter().all(|range| range.is_none()){
+            return VimEvent::None
+        }
+        let linewise = ranges.iter().flatten().any(|(_, _, linewise)| *linewise);
+        if operator != VimOperator::Indent && operator != VimOperator::Outdent{
+            let mut text = String::new();
+            for (start, end, linewise) in ranges.iter().flatten(){
+                if text.len() > 0 && !text.ends_with('\n'){
+                    text.push('\n');
+                }
+                text.push_str(&range_text(text_buffer, *start, *end));
+                if *linewise && !text.ends_with('\n'){
+                    text.push('\n');
+                }
+            }
+            self.set_register(register, text, linewise, operator == VimOperator::Yank);
+        }
+        match operator{
+            VimOperator::Yank=>{
+                for (cursor, range) in cursors.set.iter_mut().zip(&ranges){
+                    if let Some((start, _, false)) = range{
+                        cursor.head = *start;
+                        cursor.tail = *start;
+                        cursor.calc_max(text_buffer);
+                    }
+                }
+            },
+            VimOperator::Delete | VimOperator::Change=>{
+                let len = text_buffer.get_char_count();
+                let edits = cursors.set.iter().zip(&ranges).map(|(cursor, range)| match *range{
+                    Some((start, end, true)) if operator == VimOperator::Change=>{
+                        // cc keeps the indent of the first line
+                        let first = row_of(text_buffer, start);
+                        let last = row_of(text_buffer, if end > start{end - 1}else{end});
+                        TypedEdit{start:first_non_blank(text_buffer, first).max(start), end:line_end(text_buffer, last), text:String::new(), head:0, tail:0}
+                    },
+                    // the last line has no newline, take the one before it
+                    Some((start, end, true)) if end == len && start > 0=>TypedEdit{start:start - 1, end:end, text:String::new(), head:0, tail:0},
+                    Some((start, end, _))=>TypedEdit{start:start, end:end, text:String::new(), head:0, tail:0},
+                    None=>TypedEdit{start:cursor.head, end:cursor.head, text:String::new(), head:0, tail:0}
+                }).collect();
+                cursors.apply_typed_edits(edits, TextUndoGrouping::Block, text_buffer);
+                if operator == VimOperator::Change{
+                    self.begin_insert(keys);
+                }
+                else if linewise{
+                    for cursor in &mut cursors.set{
+                        cursor.head = first_non_blank(text_buffer, row_of(text_buffer, cursor.head));
+                        cursor.tail = cursor.head;
+                        cursor.calc_max(text_buffer);
+                    }
+                }
+            },
+            VimOperator::Indent | VimOperator::Outdent=>{
+                for (cursor, range) in cursors.set.iter_mut().zip(&ranges){
+                    let (start, end) = range.map(|(start, end, _)| (start, end)).unwrap_or((cursor.head, cursor.head));
+                    cursor.tail = line_start(text_buffer, row_of(text_buffer, start));
+                    cursor.head = line_end(text_buffer, row_of(text_buffer, if end > start{end - 1}else{end}));
+                }
+                if operator == VimOperator::Indent{
+                    cursors.indent_lines(indent_size, text_buffer);
+                }
+                else{
+                    cursors.outdent_lines(indent_size, text_buffer);
+                }
+                for cursor in &mut cursors.set{
+                    cursor.head = first_non_blank(text_buffer, row_of(text_buffer, cursor.head.min(cursor.tail)));
+                    cursor.tail = cursor.head;
+                    cursor.calc_max(text_buffer);
+                }
+            }
+        }
+        VimEvent::CursorMoved
+    }
+
+    fn paste(&mut self, before:bool, n:usize, register:Option<char>, cursors:&mut CursorSet, text_buffer:&mut TextBuffer)->VimEvent{
+        let register = match self.get_register(register){
+            Some(register)=>register,
+            None=>return VimEvent::None
+        };
+        let text = register.text.repeat(n);
+        let len = text_buffer.get_char_count();
+        let edits = cursors.set.iter().map(|cursor|{
+            if register.linewise{
+                let row = row_of(text_buffer, cursor.head);
+                let indent = text.chars().take_while(|ch| is_blank(*ch)).count();
+                if before{
+                    let start = line_start(text_buffer, row);
+                    TypedEdit{start:start, end:start, text:text.clone(), head:indent, tail:indent}
+                }
+                else if row + 1 < text_buffer.get_line_count(){
+                    let start = line_start(text_buffer, row + 1);
+                    TypedEdit{start:start, end:start, text:text.clone(), head:indent, tail:indent}
+                }
+                else{ // after the last line, which has no newline to go after
+                    let text = format!("\n{}", text.trim_end_matches('\n'));
+                    TypedEdit{start:len, end:len, text:text, head:indent + 1, tail:indent + 1}
+                }
+            }
+            else{
+                let start = if !before && char_at(text_buffer, cursor.head) != '\n'{cursor.head + 1}else{cursor.head};
+                let head = text.chars().count().max(1) - 1;
+                TypedEdit{start:start, end:start, text:text.clone(), head:head, tail:head}
+            }
+        }).collect();
+        cursors.apply_typed_edits(edits, TextUndoGrouping::Block, text_buffer);
+        VimEvent::CursorMoved
+    }
+
+    fn set_register(&mut self, register:Option<char>, text:String, linewise:bool, is_yank:bool){
+        let register = register.unwrap_or('"');
+        if register == '_'{
+            return
+        }
+        let value = VimRegister{text:text, linewise:linewise};
+        if register.is_ascii_uppercase(){ // appends to the lowercase one
+            let named = self.registers.entry(register.to_ascii_lowercase()).or_insert_with(VimRegister::default);
+            named.text.push_str(&value.text);
+            named.linewise |= linewise;
+        }
+        else if register != '"'{
+            self.registers.insert(register, value.clone());
+        }
+        if register == '+' || register == '*'{
+            self.clipboard_write = Some(value.text.clone());
+        }
+        if is_yank{
+            self.registers.insert('0', value.clone());
+        }
+        self.registers.insert('"', value);
+    }
+
+    fn get_register(&self, register:Option<char>)->Option<VimRegister>{
+        self.registers.get(&register.unwrap_or('"').to_ascii_lowercase()).cloned()
+    }
+
+    fn begin_insert(&mut self, keys:&str){
+        self.mode = VimMode::Insert;
+        if !self._repeating{
+            self._change = Some(VimChange{keys:keys.to_string(), text:String::new()});
+        }
+    }
+
+    // back to normal mode, the change and what was typed undo as one
+    fn leave_insert(&mut self, cursors:&mut CursorSet, text_buffer:&mut TextBuffer){
+        self.mode = VimMode::Normal;
+        if let Some(change) = self._change.take(){
+            self._last_change = Some(change);
+        }
+        text_buffer.merge_undo_since(self._change_undo_id);
+        for cursor in &mut cursors.set{
+            let (_, col) = text_buffer.offset_to_row_col(cursor.head);
+            if col > 0{
+                cursor.head -= 1;
+            }
+            cursor.tail = cursor.head;
+            cursor.calc_max(text_buffer);
+        }
+    }
+
+    fn repeat_change(&mut self, count:Option<usize>, cursors:&mut CursorSet, text_buffer:&mut TextBuffer, indent_size:usize)->VimEvent{
+        let change = match self._last_change.clone(){
+            Some(change)=>change,
+            None=>return VimEvent::None
+        };
+        let mut keys = change.keys.clone();
+        if let Some(count) = count{ // the new count replaces the one it had
+            let register_len = if keys.starts_with('"'){2}else{0};
+            let rest:String = keys.chars().skip(register_len).skip_while(|ch| ch.is_ascii_digit()).collect();
+            keys = format!("{}{}{}", &keys[0..register_len.min(keys.len())], count, rest);
+        }
+        let command = match Self::parse(&keys, false){
+            VimParse::Command(command)=>command,
+            _=>return VimEvent::None
+        };
+        self._repeating = true;
+        self.execute(command, &keys, cursors, text_buffer, indent_size);
+        if self.mode == VimMode::Insert{
+            cursors.replace_text(&change.text, text_buffer);
+            self.leave_insert(cursors, text_buffer);
+        }
+        self._repeating = false;
+        VimEvent::CursorMoved
+    }
+
+    fn search_word(&mut self, backward:bool, cursors:&mut CursorSet, text_buffer:&TextBuffer)->VimEvent{
+        let pos = cursors.set[cursors.last_cursor].head;
+        let (start, end) = text_buffer.word_range(pos);
+        if end == start{
+            return VimEvent::None
+        }
+        self.search = Some(VimSearch{
+            pattern:range_text(text_buffer, start, end),
+            options:SearchOptions{whole_word:true, ..SearchOptions::default()},
+            backward:backward
+        });
+        self.move_cursors(VimMotion::SearchNext(false), None, cursors, text_buffer)
+    }
+
+    fn run_command_line(&mut self, command_line:&str, cursors:&mut CursorSet, text_buffer:&mut TextBuffer)->VimEvent{
+        let mut chars = command_line.chars();
+        let kind = chars.next();
+        let rest = chars.as_str();
+        let event = match kind{
+            Some(':')=>self.run_ex_command(rest.trim(), cursors, text_buffer),
+            Some(kind)=>{ // a search, an empty one searches for the last pattern again
+                let pattern = match (&self.search, rest.len()){
+                    (Some(search), 0)=>search.pattern.clone(),
+                    (None, 0)=>return VimEvent::None,
+                    _=>rest.to_string()
+                };
+                self.search = Some(VimSearch{
+                    pattern:pattern,
+                    options:SearchOptions{regex:true, ..SearchOptions::default()},
+                    backward:kind == '?'
+                });
+                let event = self.move_cursors(VimMotion::SearchNext(false), None, cursors, text_buffer);
+                if self.is_visual(){
+                    self.show_visual(cursors, text_buffer);
+                }
+                event
+            },
+            None=>VimEvent::None
+        };
+        if !self.is_visual(){
+            Self::clamp_cursors(cursors, text_buffer);
+        }
+        event
+    }
+
+    fn run_ex_command(&mut self, command:&str, cursors:&mut CursorSet, text_buffer:&mut TextBuffer)->VimEvent{
+        if command == "w" || command == "write"{
+            return VimEvent::Save
+        }
+        if let Ok(line) = command.parse::<usize>(){
+            let row = line.max(1).min(text_buffer.get_line_count()) - 1;
+            let head = first_non_blank(text_buffer, row);
+            cursors.set = vec![Cursor{head:head, tail:head, max:0}];
+            cursors.last_cursor = 0;
+            cursors.set[0].calc_max(text_buffer);
+            return VimEvent::CursorMoved
+        }
+        let (all, rest) = if command.starts_with('%'){(true, &command[1..])}else{(false, command)};
+        if rest.starts_with('s') && rest.len() > 1{
+            return self.substitute(&rest[1..], all, cursors, text_buffer)
+        }
+        self.status = format!("Not an editor command: {}", command);
+        VimEvent::None
+    }
+
+    // :s/pattern/replace/flags on the cursor lines, the visual lines or with :% all of them
+    fn substitute(&mut self, spec:&str, all:bool, cursors:&mut CursorSet, text_buffer:&mut TextBuffer)->VimEvent{
+        let mut chars = spec.chars();
+        let delimiter = chars.next().unwrap();
+        let mut parts = vec![String::new()];
+        let mut escaped = false;
+        for ch in chars{
+            if escaped{
+                if ch != delimiter{
+                    parts.last_mut().unwrap().push('\\');
+                }
+                parts.last_mut().unwrap().push(ch);
+                escaped = false;
+            }
+            else if ch == '\\'{
+                escaped = true;
+            }
+            else if ch == delimiter{
+                parts.push(String::new());
+            }
+            else{
+                parts.last_mut().unwrap().push(ch);
+            }
+        }
+        let pattern = &parts[0];
+        let replace = parts.get(1).cloned().unwrap_or_default();
+        let flags = parts.get(2).cloned().unwrap_or_default();
+        let options = SearchOptions{regex:true, ignore_case:flags.contains('i'), ..SearchOptions::default()};
+        let search = match TextSearch::new(pattern, &options){
+            Ok(search)=>search,
+            Err(err)=>{
+                self.status = err;
+                return VimEvent::None
+            }
+        };
+        let (first, last) = if all{
+            (0, text_buffer.get_line_count() - 1)
+        }
+        else if let Some(rows) = self._command_rows.take(){
+            rows
+        }
+        else{
+            let rows = cursors.set.iter().map(|cursor| row_of(text_buffer, cursor.head));
+            (rows.clone().min().unwrap_or(0), rows.max().unwrap_or(0))
+        };
+        let (start, end) = (line_start(text_buffer, first), line_end(text_buffer, last));
+        let chars = text_buffer.rope.to_vec();
+        let mut last_row = None;
+        let matches:Vec<SearchMatch> = search.find_all(&chars).into_iter().filter(|search_match|{
+            if search_match.start < start || search_match.end > end{
+                return false
+            }
+            // without g only the first match of a line
+            let row = row_of(text_buffer, search_match.start);
+            let first_in_row = last_row != Some(row);
+            last_row = Some(row);
+            first_in_row || flags.contains('g')
+        }).collect();
+        if matches.len() == 0{
+            self.status = format!("Pattern not found: {}", pattern);
+            return VimEvent::None
+        }
+        let replaces:Vec<String> = matches.iter().map(|search_match| search.expand_replace(&replace, search_match, &chars)).collect();
+        let row = row_of(text_buffer, matches[matches.len() - 1].start);
+        cursors.select_matches(&matches, text_buffer);
+        cursors.replace_texts(&replaces, text_buffer);
+        let head = first_non_blank(text_buffer, row);
+        cursors.set = vec![Cursor{head:head, tail:head, max:0}];
+        cursors.last_cursor = 0;
+        cursors.set[0].calc_max(text_buffer);
+        self.status = format!("{} substitutions", matches.len());
+        VimEvent::CursorMoved
+    }
+
+    fn visual_ranges(&self, force_lines:bool, text_buffer:&TextBuffer)->Vec<VimRange>{
+        let len = text_buffer.get_char_count();
+        self._visual.iter().map(|(anchor, pos)|{
+            let (start, end) = (*anchor.min(pos), *anchor.max(pos));
+            if self.mode == VimMode::VisualLine || force_lines{
+                Some(lines_range(text_buffer, row_of(text_buffer, start), row_of(text_buffer, end)))
+            }
+            else{
+                Some((start, (end + 1).min(len), false))
+            }
+        }).collect()
+    }
+
+    // to_start puts the cursors where the selections start, for the operators that end it
+    fn exit_visual(&mut self, to_start:bool, cursors:&mut CursorSet, text_buffer:&TextBuffer){
+        for (cursor, (anchor, pos)) in cursors.set.iter_mut().zip(&self._visual){
+            cursor.head = if !to_start{
+                *pos
+            }
+            else if self.mode == VimMode::VisualLine{
+                line_start(text_buffer, row_of(text_buffer, *anchor.min(pos)))
+            }
+            else{
+                *anchor.min(pos)
+            };
+            cursor.tail = cursor.head;
+            cursor.calc_max(text_buffer);
+        }
+        self.mode = VimMode::Normal;
+        self._visual.clear();
+    }
+
+    // the cursor selections show the visual ones, which include the char at both ends
+    fn show_visual(&self, cursors:&mut CursorSet, text_buffer:&TextBuffer){
+        let len = text_buffer.get_char_count();
+        for (cursor, (anchor, pos)) in cursors.set.iter_mut().zip(&self._visual){
+            let (anchor, pos) = (*anchor, *pos);
+            if self.mode == VimMode::VisualLine{
+                let (start, _, _) = lines_range(text_buffer, row_of(text_buffer, anchor.min(pos)), 0);
+                let end = line_end(text_buffer, row_of(text_buffer, anchor.max(pos)));
+                if pos >= anchor{
+                    cursor.tail = start;
+                    cursor.head = end;
+                }
+                else{
+                    cursor.tail = end;
+                    cursor.head = start;
+                }
+            }
+            else if pos >= anchor{
+                cursor.tail = anchor;
+                cursor.head = (pos + 1).min(len);
+            }
+            else{
+                cursor.tail = (anchor + 1).min(len);
+                cursor.head = pos;
+            }
+            cursor.calc_max(text_buffer);
+        }
+    }
+
+    // a normal mode cursor sits on a char, not after the last one of a line
+    fn clamp_cursors(cursors:&mut CursorSet, text_buffer:&TextBuffer){
+        for cursor in &mut cursors.set{
+            let (row, col) = text_buffer.offset_to_row_col(cursor.head);
+            let line_len = text_buffer.get_line_len(row);
+            if col > 0 && col >= line_len{
+                cursor.head = text_buffer.row_col_to_offset(row, line_len - 1);
+            }
+            cursor.tail = cursor.head;
+        }
+    }
+
+    // the mode or the command being typed, at the bottom of the view
+    pub fn draw_vim_status(&mut self, cx:&mut Cx, scroll_pos:Vec2){
+        let origin = cx.turtle_origin();
+        let width = cx.width_total(false);
+        let height = cx.height_total(false);
+        let mono_size = self.text.get_monospace_size(cx, None);
+        let bar_height = mono_size.y + 4.;
+
+        cx.new_instance_layer(self.bg.shader_id, 0);
+        self.bg.draw_quad(cx, Rect{x:scroll_pos.x, y:scroll_pos.y + height - bar_height, w:width, h:bar_height});
+
+        cx.new_instance_layer(self.text.shader_id, 0);
+        let mut inst = self.text.begin_text(cx);
+        let chunk:Vec<char> = self.status_line().chars().collect();
+        self.text.color = if self.status.starts_with("Pattern not found") || self.status.starts_with("Not an"){color("#c66")}else{color("#a")};
+        self.text.add_text(cx, origin.x + scroll_pos.x + 6., origin.y + scroll_pos.y + height - bar_height + 2., 0, &mut inst, &chunk, |_,_,_,_|{0.0});
+        self.text.end_text(cx, &inst);
+    }
+}
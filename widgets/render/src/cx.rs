@@ -74,6 +74,9 @@ pub struct Cx{
     pub captured_fingers:Vec<Area>,
     pub tapped_fingers:Vec<(f64,u32)>,
 
+    // text for the system clipboard, the platform picks it up after the event
+    pub clipboard_write:Option<String>,
+
     pub user_events:Vec<Event>,
 
     pub playing_anim_areas:Vec<AnimArea>,
@@ -142,6 +145,8 @@ impl Default for Cx{
             captured_fingers:captured_fingers,
             tapped_fingers:tapped_fingers,
 
+            clipboard_write:None,
+
             user_events:Vec::new(),
 
             style_values:BTreeMap::new(),
@@ -320,6 +325,22 @@ impl Cx{
         self.key_focus = focus_area;
     }
 
+    // puts text on the system clipboard, pastes come back as Event::TextPaste
+    pub fn set_clipboard_text(&mut self, text:&str){
+        self.clipboard_write = Some(text.to_string());
+    }
+
+    // a finger down quickly after the last one of the same digit counts as a double or triple tap
+    pub fn process_tap_count(&mut self, digit:usize, time:f64)->u32{
+        if digit >= self.tapped_fingers.len(){
+            return 1
+        }
+        let (last_time, count) = self.tapped_fingers[digit];
+        let count = if time - last_time < 0.5{count + 1}else{1};
+        self.tapped_fingers[digit] = (time, count);
+        count
+    }
+
 
     // event handler wrappers
 
@@ -238,10 +238,7 @@ impl CocoaWindow{
                             match &events[0]{
                                 Event::TextCopy(req)=>if let Some(response) = &req.response{
                                     // plug it into the apple clipboard
-                                    let nsstring:id = NSString::alloc(nil).init_str(&response);
-                                    let array: id = msg_send![class!(NSArray), arrayWithObject:NSStringPboardType];
-                                    msg_send![self.pasteboard.unwrap(), declareTypes:array owner:nil];
-                                    msg_send![self.pasteboard.unwrap(), setString:nsstring forType:NSStringPboardType];
+                                    self.set_clipboard_text(&response);
                                 },
                                 _=>()
                             };
@@ -269,10 +266,8 @@ impl CocoaWindow{
                     }
                     if let Some(paste_text) = paste_text{
                         self.do_callback(&mut vec![
-                            Event::TextInput(TextInputEvent{
-                                input:paste_text,
-                                was_paste:true,
-                                replace_last:false
+                            Event::TextPaste(TextPasteEvent{
+                                text:paste_text
                             })
                         ]);
                     }
@@ -406,6 +401,15 @@ impl CocoaWindow{
         }
     }
 
+    pub fn set_clipboard_text(&mut self, text:&str){
+        unsafe{
+            let nsstring:id = NSString::alloc(nil).init_str(text);
+            let array: id = msg_send![class!(NSArray), arrayWithObject:NSStringPboardType];
+            msg_send![self.pasteboard.unwrap(), declareTypes:array owner:nil];
+            msg_send![self.pasteboard.unwrap(), setString:nsstring forType:NSStringPboardType];
+        }
+    }
+
     pub fn do_callback(&mut self, events:&mut Vec<Event>){
         unsafe{
             if self.event_callback.is_none(){
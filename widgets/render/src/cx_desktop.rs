@@ -9,17 +9,28 @@ pub struct FileReadRequest{
     path:String
 }
 
+#[derive(Clone)]
+pub struct FileWriteRequest{
+    id:u64,
+    path:String,
+    data:Vec<u8>
+}
+
 #[derive(Clone)]
 pub struct CxDesktop{
     pub file_read_id:u64,
     pub file_read_requests:Vec<FileReadRequest>,
+    pub file_write_id:u64,
+    pub file_write_requests:Vec<FileWriteRequest>,
 }
 
 impl Default for CxDesktop{
     fn default()->CxDesktop{
         CxDesktop{
             file_read_id:1,
-            file_read_requests:Vec::new()
+            file_read_requests:Vec::new(),
+            file_write_id:1,
+            file_write_requests:Vec::new()
         }
     }
 }
@@ -37,6 +48,66 @@ impl Cx{
         id
     }
 
+    pub fn write_file(&mut self, path:&str, data:&[u8])->u64{
+        let desktop = &mut self.platform.desktop;
+        let id = desktop.file_write_id;
+        desktop.file_write_id += 1;
+        desktop.file_write_requests.push(FileWriteRequest{
+            id:id,
+            path:path.to_string(),
+            data:data.to_vec()
+        });
+        id
+    }
+
+    // write to a temp file next to the target and rename it over it,
+    // so a crash halfway through never leaves a truncated file behind.
+    // a symlink gets its target replaced, and the file keeps its permissions
+    fn write_file_atomic(path:&str, data:&[u8])->io::Result<()>{
+        let target = std::fs::canonicalize(path).unwrap_or_else(|_| std::path::PathBuf::from(path));
+        let mut temp_path = target.clone().into_os_string();
+        temp_path.push(".makepad_tmp");
+        let result = (||{
+            let mut file = File::create(&temp_path)?;
+            file.write_all(data)?;
+            file.sync_all()?;
+            if let Ok(metadata) = std::fs::metadata(&target){
+                std::fs::set_permissions(&temp_path, metadata.permissions())?;
+            }
+            std::fs::rename(&temp_path, &target)
+        })();
+        if result.is_err(){
+            let _ = std::fs::remove_file(&temp_path);
+        }
+        result
+    }
+
+    pub fn process_desktop_file_write_requests<F>(&mut self, mut event_handler:F)
+    where F: FnMut(&mut Cx, &mut Event)
+    {
+        if self.platform.desktop.file_write_requests.len() == 0{
+            return
+        }
+
+        let file_write_requests = self.platform.desktop.file_write_requests.clone();
+        self.platform.desktop.file_write_requests.truncate(0);
+
+        for write_req in file_write_requests{
+            let error = match Self::write_file_atomic(&write_req.path, &write_req.data){
+                Ok(_)=>None,
+                Err(err)=>Some(format!("Failed to write {}: {}", write_req.path, err))
+            };
+            event_handler(self, &mut Event::FileWrite(FileWriteEvent{
+                id:write_req.id,
+                error:error
+            }))
+        }
+
+        if self.platform.desktop.file_write_requests.len() != 0{
+            self.process_desktop_file_write_requests(event_handler);
+        }
+    }
+
     pub fn process_desktop_file_read_requests<F>(&mut self, mut event_handler:F)
     where F: FnMut(&mut Cx, &mut Event)
     {   
@@ -216,7 +216,7 @@ impl Cx{
                             },
                             Event::FingerDown(fe)=>{
                                 // lets set the finger tap count
-                                
+                                fe.tap_count = self.process_tap_count(fe.digit, fe.time);
                             },
                             _=>()
                         };
@@ -263,6 +263,7 @@ impl Cx{
             }
 
             self.process_desktop_file_read_requests(&mut event_handler);
+            self.process_desktop_file_write_requests(&mut event_handler);
 
             // set a cursor
             if !self.down_mouse_cursor.is_none(){
@@ -280,6 +281,10 @@ impl Cx{
                 cocoa_window.ime_spot = set_ime_position;
             }
 
+            if let Some(text) = self.clipboard_write.take(){
+                cocoa_window.set_clipboard_text(&text);
+            }
+
             // repaint everything if we need to
             if self.paint_dirty{
                 self.paint_dirty = false;
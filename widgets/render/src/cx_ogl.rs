@@ -6,6 +6,7 @@ use std::mem;
 use std::ptr;
 use std::ffi::CStr;
 use time::precise_time_ns;
+use clipboard::{ClipboardProvider, ClipboardContext};
 
 use crate::cx::*;
 
@@ -122,6 +123,9 @@ impl Cx{
         // lets compile all shaders
         self.compile_all_ogl_shaders();
 
+        // x11 stops serving what we copied when the context goes away, so we keep one around
+        let mut clipboard:Option<ClipboardContext> = ClipboardProvider::new().ok();
+
         let start_time = precise_time_ns();
         let mut root_view = View::<NoScrollBar>{
             ..Style::style(self)
@@ -134,7 +138,7 @@ impl Cx{
 
         while self.running{
             events_loop.poll_events(|winit_event|{
-                let mut events = self.map_winit_event(winit_event, &glutin_window);
+                let mut events = self.map_winit_event(winit_event, &glutin_window, &mut clipboard);
                 for mut event in &mut events{
                     match &event{
                         Event::Resized(_)=>{ // do thi
@@ -146,6 +150,7 @@ impl Cx{
                         Event::None=>{},
                         _=>{
                             self.call_event_handler(&mut event_handler, &mut event); 
+                            self.process_clipboard(&event, &mut clipboard);
                         }
                     }
                 }
@@ -163,6 +168,7 @@ impl Cx{
             }
              
              self.process_desktop_file_read_requests(&mut event_handler);
+             self.process_desktop_file_write_requests(&mut event_handler);
 
             // set a cursor
             if !self.down_mouse_cursor.is_none(){
@@ -183,7 +189,7 @@ impl Cx{
             // wait for the next event blockingly so it stops eating power
             if self.playing_anim_areas.len() == 0 && self.redraw_areas.len() == 0{
                 events_loop.run_forever(|winit_event|{
-                    let mut events = self.map_winit_event(winit_event, &glutin_window);
+                    let mut events = self.map_winit_event(winit_event, &glutin_window, &mut clipboard);
                     for mut event in &mut events{
                         match &event{
                             Event::Resized(_)=>{ // do thi
@@ -195,6 +201,7 @@ impl Cx{
                             Event::None=>{},
                             _=>{
                                 self.call_event_handler(&mut event_handler, &mut event);
+                                self.process_clipboard(&event, &mut clipboard);
                             }
                         }
                     }
@@ -204,6 +211,20 @@ impl Cx{
         }
     }
 
+    // copies and Cx::set_clipboard_text go to the system clipboard
+    fn process_clipboard(&mut self, event:&Event, clipboard:&mut Option<ClipboardContext>){
+        if let Event::TextCopy(req) = event{
+            if let Some(response) = &req.response{
+                self.clipboard_write = Some(response.clone());
+            }
+        }
+        if let Some(text) = self.clipboard_write.take(){
+            if let Some(clipboard) = clipboard{
+                let _ = clipboard.set_contents(text);
+            }
+        }
+    }
+
     fn make_mouse_move_events(&self)->Vec<Event>{
         let mut out = Vec::new();
         for i in 0..self.platform.fingers_down.len(){
@@ -269,7 +290,7 @@ impl Cx{
         window.hide_cursor(hide);
     }
 
-    pub fn map_winit_event(&mut self, winit_event:winit::Event, glutin_window:&winit::Window)->Vec<Event>{
+    pub fn map_winit_event(&mut self, winit_event:winit::Event, glutin_window:&winit::Window, clipboard:&mut Option<ClipboardContext>)->Vec<Event>{
         //self.log(&format!("{:?}\n", winit_event));
 
         match winit_event{
@@ -295,6 +316,41 @@ impl Cx{
                 winit::WindowEvent::ReceivedCharacter(chr)=>{
                     //println!("GOT CHARACTER {}", chr);
                 },
+                winit::WindowEvent::KeyboardInput{input, ..}=>{
+                    let key_code = winit_to_key_code(input.virtual_keycode);
+                    let key_event = KeyEvent{
+                        key_code:key_code.clone(),
+                        key_char:'\0',
+                        is_repeat:false,
+                        modifiers:KeyModifiers{
+                            shift:input.modifiers.shift,
+                            control:input.modifiers.ctrl,
+                            alt:input.modifiers.alt,
+                            logo:input.modifiers.logo
+                        },
+                        time:0.0
+                    };
+                    if input.state == winit::ElementState::Released{
+                        return vec![Event::KeyUp(key_event)]
+                    }
+                    // like on the mac the copy goes before the key, so cut can delete what was copied
+                    let mut events = Vec::new();
+                    let is_command = input.modifiers.ctrl || input.modifiers.logo;
+                    if is_command && (key_code == KeyCode::KeyC || key_code == KeyCode::KeyX){
+                        events.push(Event::TextCopy(TextCopyEvent{
+                            response:None
+                        }));
+                    }
+                    events.push(Event::KeyDown(key_event));
+                    if is_command && key_code == KeyCode::KeyV{
+                        if let Some(text) = clipboard.as_mut().and_then(|clipboard| clipboard.get_contents().ok()){
+                            events.push(Event::TextPaste(TextPasteEvent{
+                                text:text
+                            }));
+                        }
+                    }
+                    return events
+                },
                 winit::WindowEvent::MouseWheel{delta, ..}=>{
                     let (x, xis_wheel) = match delta{
                         winit::MouseScrollDelta::LineDelta(dx,_dy)=>(-dx*32.0, true),
@@ -365,6 +421,7 @@ impl Cx{
                                 digit = 0;
                             };
                             self.platform.fingers_down[digit] = true;
+                            let tap_count = self.process_tap_count(digit, precise_time_ns() as f64 / 1_000_000_000.0);
                             return vec![Event::FingerDown(FingerDownEvent{
                                 modifiers:KeyModifiers{..Default::default()},
                                 abs:self.platform.last_mouse_pos,
@@ -373,6 +430,7 @@ impl Cx{
                                 handled:false,
                                 digit:digit,
                                 is_touch:false,
+                                tap_count:tap_count,
                                 time:0.0
                             })]
                         },
@@ -869,3 +927,114 @@ impl Texture2D{
         self.dirty = false;
     }
 }
+
+fn winit_to_key_code(virtual_keycode:Option<winit::VirtualKeyCode>)->KeyCode{
+    let virtual_keycode = if let Some(virtual_keycode) = virtual_keycode{virtual_keycode}else{return KeyCode::Unknown};
+    match virtual_keycode{
+        winit::VirtualKeyCode::Escape=>KeyCode::Escape,
+        winit::VirtualKeyCode::Grave=>KeyCode::Backtick,
+        winit::VirtualKeyCode::Key0=>KeyCode::Key0,
+        winit::VirtualKeyCode::Key1=>KeyCode::Key1,
+        winit::VirtualKeyCode::Key2=>KeyCode::Key2,
+        winit::VirtualKeyCode::Key3=>KeyCode::Key3,
+        winit::VirtualKeyCode::Key4=>KeyCode::Key4,
+        winit::VirtualKeyCode::Key5=>KeyCode::Key5,
+        winit::VirtualKeyCode::Key6=>KeyCode::Key6,
+        winit::VirtualKeyCode::Key7=>KeyCode::Key7,
+        winit::VirtualKeyCode::Key8=>KeyCode::Key8,
+        winit::VirtualKeyCode::Key9=>KeyCode::Key9,
+        winit::VirtualKeyCode::Minus=>KeyCode::Minus,
+        winit::VirtualKeyCode::Equals=>KeyCode::Equals,
+        winit::VirtualKeyCode::Back=>KeyCode::Backspace,
+        winit::VirtualKeyCode::Tab=>KeyCode::Tab,
+        winit::VirtualKeyCode::Q=>KeyCode::KeyQ,
+        winit::VirtualKeyCode::W=>KeyCode::KeyW,
+        winit::VirtualKeyCode::E=>KeyCode::KeyE,
+        winit::VirtualKeyCode::R=>KeyCode::KeyR,
+        winit::VirtualKeyCode::T=>KeyCode::KeyT,
+        winit::VirtualKeyCode::Y=>KeyCode::KeyY,
+        winit::VirtualKeyCode::U=>KeyCode::KeyU,
+        winit::VirtualKeyCode::I=>KeyCode::KeyI,
+        winit::VirtualKeyCode::O=>KeyCode::KeyO,
+        winit::VirtualKeyCode::P=>KeyCode::KeyP,
+        winit::VirtualKeyCode::LBracket=>KeyCode::LBracket,
+        winit::VirtualKeyCode::RBracket=>KeyCode::RBracket,
+        winit::VirtualKeyCode::Return=>KeyCode::Return,
+        winit::VirtualKeyCode::A=>KeyCode::KeyA,
+        winit::VirtualKeyCode::S=>KeyCode::KeyS,
+        winit::VirtualKeyCode::D=>KeyCode::KeyD,
+        winit::VirtualKeyCode::F=>KeyCode::KeyF,
+        winit::VirtualKeyCode::G=>KeyCode::KeyG,
+        winit::VirtualKeyCode::H=>KeyCode::KeyH,
+        winit::VirtualKeyCode::J=>KeyCode::KeyJ,
+        winit::VirtualKeyCode::K=>KeyCode::KeyK,
+        winit::VirtualKeyCode::L=>KeyCode::KeyL,
+        winit::VirtualKeyCode::Semicolon=>KeyCode::Semicolon,
+        winit::VirtualKeyCode::Apostrophe=>KeyCode::Quote,
+        winit::VirtualKeyCode::Backslash=>KeyCode::Backslash,
+        winit::VirtualKeyCode::Z=>KeyCode::KeyZ,
+        winit::VirtualKeyCode::X=>KeyCode::KeyX,
+        winit::VirtualKeyCode::C=>KeyCode::KeyC,
+        winit::VirtualKeyCode::V=>KeyCode::KeyV,
+        winit::VirtualKeyCode::B=>KeyCode::KeyB,
+        winit::VirtualKeyCode::N=>KeyCode::KeyN,
+        winit::VirtualKeyCode::M=>KeyCode::KeyM,
+        winit::VirtualKeyCode::Comma=>KeyCode::Comma,
+        winit::VirtualKeyCode::Period=>KeyCode::Period,
+        winit::VirtualKeyCode::Slash=>KeyCode::Slash,
+        winit::VirtualKeyCode::LControl=>KeyCode::LeftControl,
+        winit::VirtualKeyCode::LAlt=>KeyCode::LeftAlt,
+        winit::VirtualKeyCode::LShift=>KeyCode::LeftShift,
+        winit::VirtualKeyCode::LWin=>KeyCode::LeftLogo,
+        winit::VirtualKeyCode::RControl=>KeyCode::RightControl,
+        winit::VirtualKeyCode::RShift=>KeyCode::RightShift,
+        winit::VirtualKeyCode::RAlt=>KeyCode::RightAlt,
+        winit::VirtualKeyCode::RWin=>KeyCode::RightLogo,
+        winit::VirtualKeyCode::Space=>KeyCode::Space,
+        winit::VirtualKeyCode::Capital=>KeyCode::Capslock,
+        winit::VirtualKeyCode::F1=>KeyCode::F1,
+        winit::VirtualKeyCode::F2=>KeyCode::F2,
+        winit::VirtualKeyCode::F3=>KeyCode::F3,
+        winit::VirtualKeyCode::F4=>KeyCode::F4,
+        winit::VirtualKeyCode::F5=>KeyCode::F5,
+        winit::VirtualKeyCode::F6=>KeyCode::F6,
+        winit::VirtualKeyCode::F7=>KeyCode::F7,
+        winit::VirtualKeyCode::F8=>KeyCode::F8,
+        winit::VirtualKeyCode::F9=>KeyCode::F9,
+        winit::VirtualKeyCode::F10=>KeyCode::F10,
+        winit::VirtualKeyCode::F11=>KeyCode::F11,
+        winit::VirtualKeyCode::F12=>KeyCode::F12,
+        winit::VirtualKeyCode::Snapshot=>KeyCode::PrintScreen,
+        winit::VirtualKeyCode::Scroll=>KeyCode::Scrolllock,
+        winit::VirtualKeyCode::Pause=>KeyCode::Pause,
+        winit::VirtualKeyCode::Insert=>KeyCode::Insert,
+        winit::VirtualKeyCode::Delete=>KeyCode::Delete,
+        winit::VirtualKeyCode::Home=>KeyCode::Home,
+        winit::VirtualKeyCode::End=>KeyCode::End,
+        winit::VirtualKeyCode::PageUp=>KeyCode::PageUp,
+        winit::VirtualKeyCode::PageDown=>KeyCode::PageDown,
+        winit::VirtualKeyCode::Numpad0=>KeyCode::Numpad0,
+        winit::VirtualKeyCode::Numpad1=>KeyCode::Numpad1,
+        winit::VirtualKeyCode::Numpad2=>KeyCode::Numpad2,
+        winit::VirtualKeyCode::Numpad3=>KeyCode::Numpad3,
+        winit::VirtualKeyCode::Numpad4=>KeyCode::Numpad4,
+        winit::VirtualKeyCode::Numpad5=>KeyCode::Numpad5,
+        winit::VirtualKeyCode::Numpad6=>KeyCode::Numpad6,
+        winit::VirtualKeyCode::Numpad7=>KeyCode::Numpad7,
+        winit::VirtualKeyCode::Numpad8=>KeyCode::Numpad8,
+        winit::VirtualKeyCode::Numpad9=>KeyCode::Numpad9,
+        winit::VirtualKeyCode::NumpadEquals=>KeyCode::NumpadEquals,
+        winit::VirtualKeyCode::Subtract=>KeyCode::NumpadSubtract,
+        winit::VirtualKeyCode::Add=>KeyCode::NumpadAdd,
+        winit::VirtualKeyCode::Decimal=>KeyCode::NumpadDecimal,
+        winit::VirtualKeyCode::Multiply=>KeyCode::NumpadMultiply,
+        winit::VirtualKeyCode::Divide=>KeyCode::NumpadDivide,
+        winit::VirtualKeyCode::Numlock=>KeyCode::Numlock,
+        winit::VirtualKeyCode::NumpadEnter=>KeyCode::NumpadEnter,
+        winit::VirtualKeyCode::Up=>KeyCode::ArrowUp,
+        winit::VirtualKeyCode::Down=>KeyCode::ArrowDown,
+        winit::VirtualKeyCode::Left=>KeyCode::ArrowLeft,
+        winit::VirtualKeyCode::Right=>KeyCode::ArrowRight,
+        _=>KeyCode::Unknown
+    }
+}
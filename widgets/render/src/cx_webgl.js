@@ -204,6 +204,19 @@
 			this.mu32[pos++] = 16;
 		}
 
+		text_paste(text){
+			let pos = this.fit(1);
+			this.mu32[pos++] = 18;
+			this.send_string(text);
+		}
+
+		write_file_result(id, status){
+			let pos = this.fit(3);
+			this.mu32[pos++] = 17;
+			this.mu32[pos++] = id;
+			this.mu32[pos++] = status;
+		}
+
 		end(){
 			let pos = this.fit(1);
 			this.mu32[pos] = 0;
@@ -871,11 +884,7 @@
 					if(was_paste){
 						was_paste = false;
 
-						this.to_wasm.text_input({
-							was_paste:true,
-							input:ta.value.substring(last_len),
-							replace_last:false,
-						})
+						this.to_wasm.text_paste(ta.value.substring(last_len))
 						ta.value = "";
 					}
 					else{
@@ -985,6 +994,13 @@
 		hide_text_ime(){
 		}
 
+		set_clipboard_text(text){
+			// only works in secure contexts, copy and cut keys also go through the text area
+			if(navigator.clipboard && navigator.clipboard.writeText){
+				navigator.clipboard.writeText(text).catch(_=>{})
+			}
+		}
+
 		alloc_array_buffer(array_buffer_id, array){
 			var gl = this.gl;
 			let gl_buf = this.array_buffers[array_buffer_id] || gl.createBuffer()
@@ -1128,6 +1144,18 @@
 			}, err=>{
 			})
 		}
+
+		write_file(id, file_path, buf_ptr, buf_len){
+			// copy it out now, the wasm side frees the buffer on the next message
+			let data = new Uint8Array(this.memory.buffer, buf_ptr, buf_len).slice();
+			post_path(file_path, data).then(status=>{
+				this.to_wasm.write_file_result(id, status)
+				this.do_wasm_io();
+			}, status=>{
+				this.to_wasm.write_file_result(id, status)
+				this.do_wasm_io();
+			})
+		}
 	}
 
 	// array of function id's wasm can call on us, self is pointer to WasmApp
@@ -1231,6 +1259,15 @@
 		},
 		function text_copy_response_16(self){
 			self.text_copy_response = self.parse_string();
+		},
+		function write_file_17(self){
+			let id = self.mu32[self.parse++];
+			let buf_ptr = self.mu32[self.parse++];
+			let buf_len = self.mu32[self.parse++];
+			self.write_file(id, self.parse_string(), buf_ptr, buf_len);
+		},
+		function set_clipboard_text_18(self){
+			self.set_clipboard_text(self.parse_string());
 		}
 	]
 	
@@ -1334,6 +1371,23 @@
 		})
 	}
 
+	function post_path(file_path, data){
+		return new Promise(function(resolve, reject){
+			var req = new XMLHttpRequest()
+			req.addEventListener("error", function(){
+				reject(0)
+			})
+			req.addEventListener("load", function(){
+				if(req.status !== 200){
+					return reject(req.status)
+				}
+				resolve(req.status)
+			})
+			req.open("POST", file_path)
+			req.send(data)
+		})
+	}
+
 	function watchFileChange(){
 		var req = new XMLHttpRequest()
 		req.timeout = 60000
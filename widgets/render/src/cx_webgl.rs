@@ -81,6 +81,8 @@ impl Cx{
         
         let mut to_wasm = ToWasm::from(msg);
         self.platform.from_wasm = FromWasm::new();
+        // the JS side has copied the data of last rounds file writes by now
+        self.platform.file_write_buffers.truncate(0);
         let mut is_animation_frame = false;
         loop{
             let msg_type = to_wasm.mu32();
@@ -154,7 +156,7 @@ impl Cx{
                     let y = to_wasm.mf32();
                     let digit = to_wasm.mu32() as usize;
                     self.platform.fingers_down[digit] = true;
-                    self.call_event_handler(&mut event_handler, &mut Event::FingerDown(FingerDownEvent{
+                    let mut fe = FingerDownEvent{
                         abs:Vec2{x:x, y:y}, 
                         rel:Vec2{x:x, y:y},
                         rect:Rect::zero(),
@@ -162,8 +164,11 @@ impl Cx{
                         digit:digit,
                         is_touch:to_wasm.mu32()>0,
                         modifiers:unpack_key_modifier(to_wasm.mu32()),
+                        tap_count:0,
                         time:to_wasm.mf64()
-                    }));
+                    };
+                    fe.tap_count = self.process_tap_count(digit, fe.time);
+                    self.call_event_handler(&mut event_handler, &mut Event::FingerDown(fe));
                 },
                 7=>{ // finger up
                     let x = to_wasm.mf32();
@@ -294,6 +299,19 @@ impl Cx{
                         _=>()
                     };
                 },
+                17=>{ // file write result
+                    let id = to_wasm.mu32();
+                    let status = to_wasm.mu32();
+                    self.call_event_handler(&mut event_handler, &mut Event::FileWrite(FileWriteEvent{
+                        id: id as u64,
+                        error:if status == 200{None}else{Some(format!("Failed to write file, status {}", status))}
+                    }));
+                },
+                18=>{ // text paste
+                    self.call_event_handler(&mut event_handler, &mut Event::TextPaste(TextPasteEvent{
+                        text:to_wasm.parse_string()
+                    }));
+                },
                 _=>{
                     panic!("Message unknown")
                 }
@@ -315,6 +333,10 @@ impl Cx{
             self.platform.from_wasm.set_mouse_cursor(MouseCursor::Default);
         }
 
+        if let Some(text) = self.clipboard_write.take(){
+            self.platform.from_wasm.set_clipboard_text(&text);
+        }
+
         if is_animation_frame && self.paint_dirty{
             self.paint_dirty = false;
             self.repaint_id += 1;
@@ -354,6 +376,16 @@ impl Cx{
         id
     }
 
+    pub fn write_file(&mut self, path:&str, data:&[u8])->u64{
+        let id = self.platform.file_write_id;
+        let data = data.to_vec();
+        self.platform.from_wasm.write_file(id as u32, path, data.as_ptr() as u32, data.len() as u32);
+        // keep the data alive until the JS side has copied it out
+        self.platform.file_write_buffers.push(data);
+        self.platform.file_write_id += 1;
+        id
+    }
+
     pub fn show_text_ime(&mut self, x:f32, y:f32){
         self.platform.from_wasm.show_text_ime(x,y);
     }
@@ -598,6 +630,8 @@ pub struct CxPlatform{
     pub root_view_ptr:u32,
     pub fingers_down:Vec<bool>,
     pub file_read_id:u64,
+    pub file_write_id:u64,
+    pub file_write_buffers:Vec<Vec<u8>>,
 }
 
 impl Default for CxPlatform{
@@ -612,6 +646,8 @@ impl Default for CxPlatform{
             vaos_free:Vec::new(),
             root_view_ptr:0,
             file_read_id:1,
+            file_write_id:1,
+            file_write_buffers:Vec::new(),
             fingers_down:Vec::new()
         }
     }
@@ -1023,6 +1059,21 @@ impl FromWasm{
         self.add_string(response);
     }
 
+    pub fn write_file(&mut self, id:u32, path:&str, buf_ptr:u32, buf_len:u32){
+        self.fit(4);
+        self.mu32(17);
+        self.mu32(id);
+        self.mu32(buf_ptr);
+        self.mu32(buf_len);
+        self.add_string(path);
+    }
+
+    pub fn set_clipboard_text(&mut self, text:&str){
+        self.fit(1);
+        self.mu32(18);
+        self.add_string(text);
+    }
+
     fn add_string(&mut self, msg:&str){
         let len = msg.chars().count();
         self.fit(len + 1);
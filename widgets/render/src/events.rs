@@ -124,8 +124,8 @@ pub struct FileReadEvent{
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct FileWriteEvent{
-    id:u64,
-    error:Option<String>
+    pub id:u64,
+    pub error:Option<String>
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -156,6 +156,11 @@ pub struct TextCopyEvent{
     pub response:Option<String>
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextPasteEvent{
+    pub text:String
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Event{
     None,
@@ -179,7 +184,8 @@ pub enum Event{
     KeyDown(KeyEvent),
     KeyUp(KeyEvent),
     TextInput(TextInputEvent),
-    TextCopy(TextCopyEvent)
+    TextCopy(TextCopyEvent),
+    TextPaste(TextPasteEvent)
 }
 
 impl Default for Event{
@@ -272,6 +278,11 @@ impl Event{
                     );
                 }
             },
+            Event::TextPaste(_)=>{
+                if area == cx.key_focus{
+                    return self.clone();
+                }
+            },
             Event::Animate(_)=>{
                 for anim in &cx.playing_anim_areas{
                     if anim.area == area{
@@ -70,6 +70,19 @@ impl FileNode{
             FileNode::Folder{name,..}=>name.clone()
         }
     }
+
+    // collects the paths of all files below this node, open folders or not
+    fn collect_file_paths(&self, parent_path:&str, paths:&mut Vec<String>){
+        match self{
+            FileNode::File{name,..}=>paths.push(format!("{}/{}", parent_path, name)),
+            FileNode::Folder{name, folder, ..}=>{
+                let path = format!("{}/{}", parent_path, name);
+                for node in folder{
+                    node.collect_file_paths(&path, paths);
+                }
+            }
+        }
+    }
 }
 
 struct StackEntry<'a>{
@@ -287,6 +300,16 @@ impl FileTree{
         }
     } 
 
+    pub fn get_all_file_paths(&self)->Vec<String>{
+        let mut paths = Vec::new();
+        if let FileNode::Folder{folder, ..} = &self.root_node{
+            for node in folder{
+                node.collect_file_paths("", &mut paths);
+            }
+        }
+        paths
+    }
+
     pub fn load_from_json(&mut self, cx:&mut Cx, json_data:&str){
         let value:Result<JsonFolder> = serde_json::from_str(json_data); 
         if let Ok(value) = value{
@@ -326,6 +349,22 @@ impl FileTree{
         paths
     }
 
+    pub fn get_marked_file_path(&mut self)->Option<String>{
+        let mut file_walker = FileWalker::new(&mut self.root_node);
+        while let Some((_depth, _index, _len, node)) = file_walker.walk(){
+            let is_filenode = if let FileNode::File{..} = node{true} else {false};
+            let node_draw = if let Some(node_draw) = node.get_draw(){node_draw}else{continue};
+            if node_draw.marked != 0 && is_filenode{
+                return Some(file_walker.current_path())
+            }
+        }
+        None
+    }
+
+    pub fn has_key_focus(&self, cx:&Cx)->bool{
+        cx.key_focus == self.view.get_view_area(cx)
+    }
+
     pub fn handle_file_tree(&mut self, cx:&mut Cx, event:&mut Event)->FileTreeEvent{
         // alright. someone clicking on the tree items.
         let mut file_walker = FileWalker::new(&mut self.root_node);
@@ -348,6 +387,7 @@ impl FileTree{
                     node_draw.animator.calc_write(cx, "bg.color", ae.time, node_draw.animator.area);
                 },
                 Event::FingerDown(_fe)=>{
+                    cx.set_key_focus(self.view.get_view_area(cx));
                     // mark ourselves, unmark others
                     if node_draw.marked != 0 && is_filenode{
                         select_node = true;